@@ -598,6 +598,208 @@ INSTANTIATE_TEST_SUITE_P(
                                         AVIF_PIXEL_FORMAT_ANDROID_P010}),
                      testing::ValuesIn({AVIF_PLANES_ALL, AVIF_PLANES_YUV})));
 
+TEST(ImageTest, SetViewRectSharesPlanesWithParent) {
+  constexpr int kWidth = 64;
+  constexpr int kHeight = 48;
+  ImagePtr src(avifImageCreate(kWidth, kHeight, 8, AVIF_PIXEL_FORMAT_YUV420));
+  ASSERT_EQ(avifImageAllocatePlanes(src.get(), AVIF_PLANES_ALL),
+            AVIF_RESULT_OK);
+  for (int i = 0; i < 4; ++i) {
+    uint8_t* plane = avifImagePlane(src.get(), i);
+    const uint32_t row_bytes = avifImagePlaneRowBytes(src.get(), i);
+    std::fill(plane, plane + row_bytes * avifImagePlaneHeight(src.get(), i),
+              0);
+  }
+
+  ImagePtr view(avifImageCreateEmpty());
+  const avifCropRect rect{.x = 16, .y = 8, .width = 32, .height = 24};
+  ASSERT_EQ(avifImageSetViewRect(view.get(), src.get(), &rect),
+            AVIF_RESULT_OK);
+  EXPECT_EQ(view->width, rect.width);
+  EXPECT_EQ(view->height, rect.height);
+  EXPECT_EQ(view->imageOwnsYUVPlanes, AVIF_FALSE);
+  EXPECT_EQ(view->imageOwnsAlphaPlane, AVIF_FALSE);
+  for (int i = 0; i < 4; ++i) {
+    EXPECT_EQ(avifImagePlaneRowBytes(view.get(), i),
+              avifImagePlaneRowBytes(src.get(), i));
+  }
+
+  // Writes through the view must be visible in the parent image.
+  avifImagePlane(view.get(), AVIF_CHAN_Y)[0] = 1;
+  avifImagePlane(view.get(), AVIF_CHAN_U)[0] = 2;
+  avifImagePlane(view.get(), AVIF_CHAN_A)[0] = 3;
+  EXPECT_EQ(avifImagePlane(src.get(), AVIF_CHAN_Y)
+                [rect.y * src->yuvRowBytes[AVIF_CHAN_Y] + rect.x],
+            1);
+  EXPECT_EQ(avifImagePlane(src.get(), AVIF_CHAN_U)
+                [(rect.y / 2) * src->yuvRowBytes[AVIF_CHAN_U] + rect.x / 2],
+            2);
+  EXPECT_EQ(avifImagePlane(src.get(), AVIF_CHAN_A)
+                [rect.y * src->alphaRowBytes + rect.x],
+            3);
+
+  // Destroying the view must not free the parent's planes.
+  view.reset();
+  EXPECT_EQ(avifImagePlane(src.get(), AVIF_CHAN_Y)
+                [rect.y * src->yuvRowBytes[AVIF_CHAN_Y] + rect.x],
+            1);
+}
+
+TEST(ImageTest, SetViewRectRejectsInvalidRects) {
+  ImagePtr src(avifImageCreate(64, 48, 8, AVIF_PIXEL_FORMAT_YUV420));
+  ASSERT_EQ(avifImageAllocatePlanes(src.get(), AVIF_PLANES_YUV),
+            AVIF_RESULT_OK);
+  ImagePtr view(avifImageCreateEmpty());
+  // Not aligned to the chroma grid.
+  avifCropRect rect{.x = 1, .y = 0, .width = 32, .height = 24};
+  EXPECT_EQ(avifImageSetViewRect(view.get(), src.get(), &rect),
+            AVIF_RESULT_INVALID_ARGUMENT);
+  rect = {.x = 0, .y = 1, .width = 32, .height = 24};
+  EXPECT_EQ(avifImageSetViewRect(view.get(), src.get(), &rect),
+            AVIF_RESULT_INVALID_ARGUMENT);
+  // Out of bounds.
+  rect = {.x = 48, .y = 0, .width = 32, .height = 24};
+  EXPECT_EQ(avifImageSetViewRect(view.get(), src.get(), &rect),
+            AVIF_RESULT_INVALID_ARGUMENT);
+  rect = {.x = 0, .y = 0, .width = 65, .height = 24};
+  EXPECT_EQ(avifImageSetViewRect(view.get(), src.get(), &rect),
+            AVIF_RESULT_INVALID_ARGUMENT);
+  // The interleaved chroma plane of NV12 must start on a U sample.
+  ImagePtr nv12(avifImageCreate(64, 48, 8, AVIF_PIXEL_FORMAT_ANDROID_NV12));
+  ASSERT_EQ(avifImageAllocatePlanes(nv12.get(), AVIF_PLANES_YUV),
+            AVIF_RESULT_OK);
+  rect = {.x = 1, .y = 0, .width = 32, .height = 24};
+  EXPECT_EQ(avifImageSetViewRect(view.get(), nv12.get(), &rect),
+            AVIF_RESULT_INVALID_ARGUMENT);
+}
+
+class SetViewRectTest
+    : public testing::TestWithParam<std::tuple<int, avifPixelFormat>> {};
+
+TEST_P(SetViewRectTest, PlanesAddressOnlyValidPixels) {
+  const auto depth = std::get<0>(GetParam());
+  const auto pixel_format = std::get<1>(GetParam());
+  if ((pixel_format == AVIF_PIXEL_FORMAT_ANDROID_P010 && depth == 8) ||
+      (pixel_format == AVIF_PIXEL_FORMAT_ANDROID_NV12 && depth != 8)) {
+    GTEST_SKIP() << "This combination of parameters is not valid. Skipping.";
+  }
+  const int pixel_size = (depth > 8) ? 2 : 1;
+  ImagePtr src(avifImageCreate(64, 48, depth, pixel_format));
+  ASSERT_EQ(avifImageAllocatePlanes(src.get(), AVIF_PLANES_ALL),
+            AVIF_RESULT_OK);
+  for (int i = 0; i < 4; ++i) {
+    uint8_t* plane = avifImagePlane(src.get(), i);
+    if (plane == nullptr) continue;
+    const uint32_t row_bytes = avifImagePlaneRowBytes(src.get(), i);
+    for (uint32_t y = 0; y < avifImagePlaneHeight(src.get(), i); ++y) {
+      std::iota(plane + y * row_bytes, plane + (y + 1) * row_bytes, y);
+    }
+  }
+
+  ImagePtr view(avifImageCreateEmpty());
+  const avifCropRect rect{.x = 16, .y = 8, .width = 34, .height = 22};
+  ASSERT_EQ(avifImageSetViewRect(view.get(), src.get(), &rect),
+            AVIF_RESULT_OK);
+  for (int i = 0; i < 4; ++i) {
+    const uint8_t* src_plane = avifImagePlane(src.get(), i);
+    const uint8_t* view_plane = avifImagePlane(view.get(), i);
+    ASSERT_EQ(src_plane == nullptr, view_plane == nullptr);
+    if (src_plane == nullptr) continue;
+    const uint32_t src_row_bytes = avifImagePlaneRowBytes(src.get(), i);
+    const uint32_t view_row_bytes = avifImagePlaneRowBytes(view.get(), i);
+    const uint32_t src_width_bytes =
+        avifImagePlaneWidth(src.get(), i) * pixel_size;
+    const uint32_t src_height = avifImagePlaneHeight(src.get(), i);
+    const uint32_t view_width_bytes =
+        avifImagePlaneWidth(view.get(), i) * pixel_size;
+    const uint32_t view_height = avifImagePlaneHeight(view.get(), i);
+    // The view plane pointer is the top left pixel of the rect in the parent.
+    const uint32_t view_offset = static_cast<uint32_t>(view_plane - src_plane);
+    const uint32_t view_x = view_offset % src_row_bytes;
+    const uint32_t view_y = view_offset / src_row_bytes;
+    const bool is_chroma = (i == AVIF_CHAN_U || i == AVIF_CHAN_V);
+    const uint32_t shift_x =
+        is_chroma && (pixel_format == AVIF_PIXEL_FORMAT_YUV420) ? 1 : 0;
+    const uint32_t shift_y =
+        is_chroma && (pixel_format != AVIF_PIXEL_FORMAT_YUV444) ? 1 : 0;
+    EXPECT_EQ(view_x, (rect.x >> shift_x) * pixel_size);
+    EXPECT_EQ(view_y, rect.y >> shift_y);
+    // Every row of the view stays within the matching row of the parent.
+    EXPECT_LE(view_x + view_width_bytes, src_width_bytes);
+    EXPECT_LE(view_y + view_height, src_height);
+    for (uint32_t y = 0; y < view_height; ++y) {
+      const uint8_t* row = view_plane + y * view_row_bytes;
+      for (uint32_t x = 0; x < view_width_bytes; ++x) {
+        ASSERT_EQ(row[x], static_cast<uint8_t>(view_y + y + view_x + x));
+      }
+    }
+  }
+}
+
+INSTANTIATE_TEST_SUITE_P(
+    SetViewRectTestInstance, SetViewRectTest,
+    testing::Combine(testing::ValuesIn({8, 10}),
+                     testing::ValuesIn({AVIF_PIXEL_FORMAT_YUV444,
+                                        AVIF_PIXEL_FORMAT_YUV420,
+                                        AVIF_PIXEL_FORMAT_ANDROID_NV12,
+                                        AVIF_PIXEL_FORMAT_ANDROID_P010})));
+
+TEST(GainMapTest, CreateAndDestroy) {
+  avifGainMap* gain_map = avifGainMapCreate();
+  ASSERT_NE(gain_map, nullptr);
+  EXPECT_EQ(gain_map->image, nullptr);
+  EXPECT_EQ(gain_map->baseHdrHeadroom.n, 0u);
+  EXPECT_EQ(gain_map->baseHdrHeadroom.d, 1u);
+  EXPECT_EQ(gain_map->alternateHdrHeadroom.n, 1u);
+  EXPECT_EQ(gain_map->alternateHdrHeadroom.d, 1u);
+  for (int c = 0; c < 3; ++c) {
+    EXPECT_EQ(gain_map->baseOffset[c].n, 1);
+    EXPECT_EQ(gain_map->baseOffset[c].d, 64u);
+  }
+  EXPECT_EQ(gain_map->altICC.size, 0u);
+  // The gain map takes ownership of its image and altICC.
+  const uint8_t icc[] = {1, 2, 3};
+  ASSERT_EQ(avifRWDataSet(&gain_map->altICC, icc, sizeof(icc)),
+            AVIF_RESULT_OK);
+  gain_map->image = avifImageCreate(16, 16, 8, AVIF_PIXEL_FORMAT_YUV400);
+  ASSERT_NE(gain_map->image, nullptr);
+  ASSERT_EQ(avifImageAllocatePlanes(gain_map->image, AVIF_PLANES_YUV),
+            AVIF_RESULT_OK);
+  avifGainMapDestroy(gain_map);
+  avifGainMapDestroy(nullptr);
+}
+
+TEST(GainMapTest, ImageOwnsGainMap) {
+  ImagePtr image(avifImageCreate(16, 16, 8, AVIF_PIXEL_FORMAT_YUV420));
+  ASSERT_NE(image, nullptr);
+  image->gainMap = avifGainMapCreate();
+  ASSERT_NE(image->gainMap, nullptr);
+  image->gainMap->image = avifImageCreateEmpty();
+  image->gainMap->baseHdrHeadroom = {6, 2};
+  EXPECT_EQ(image->gainMap->baseHdrHeadroom.n, 6u);
+  // The gain map is freed along with the image.
+  image.reset();
+}
+
+TEST(GainMapTest, DestroyDecodedImage) {
+  if (!testutil::Av1DecoderAvailable()) {
+    GTEST_SKIP() << "AV1 Codec unavailable, skip test.";
+  }
+  auto decoder = CreateDecoder("seine_sdr_gainmap_srgb.avif");
+  ASSERT_NE(decoder, nullptr);
+  decoder->imageContentToDecode |= AVIF_IMAGE_CONTENT_GAIN_MAP;
+  avifImage* image = avifImageCreateEmpty();
+  ASSERT_NE(image, nullptr);
+  ASSERT_EQ(avifDecoderRead(decoder.get(), image), AVIF_RESULT_OK);
+  ASSERT_NE(image->gainMap, nullptr);
+  ASSERT_NE(image->gainMap->image, nullptr);
+  // The image owns a copy of the gain map of the decoder.
+  EXPECT_NE(image->gainMap, decoder->image->gainMap);
+  EXPECT_GT(image->gainMap->image->yuvRowBytes[0], 0u);
+  avifImageDestroy(image);
+  avifImageDestroy(nullptr);
+}
+
 TEST(DecoderTest, SetRawIO) {
   DecoderPtr decoder(avifDecoderCreate());
   ASSERT_NE(decoder, nullptr);
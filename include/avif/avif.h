@@ -566,6 +566,10 @@ avifResult crabby_avifDecoderNthImageMaxExtent(const avifDecoder *decoder,
 
 avifBool crabby_avifPeekCompatibleFileType(const avifROData *input);
 
+avifGainMap *crabby_avifGainMapCreate();
+
+void crabby_avifGainMapDestroy(avifGainMap *gainMap);
+
 avifImage *crabby_avifImageCreateEmpty();
 
 avifImage *crabby_avifImageCreate(uint32_t width,
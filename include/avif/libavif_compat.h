@@ -36,6 +36,8 @@
 #define avifDecoderSetSource crabby_avifDecoderSetSource
 #define avifDiagnosticsClearError crabby_avifDiagnosticsClearError
 #define avifFree crabby_avifFree
+#define avifGainMapCreate crabby_avifGainMapCreate
+#define avifGainMapDestroy crabby_avifGainMapDestroy
 #define avifGetPixelFormatInfo crabby_avifGetPixelFormatInfo
 #define avifIOCreateFileReader crabby_avifIOCreateFileReader
 #define avifIOCreateMemoryReader crabby_avifIOCreateMemoryReader
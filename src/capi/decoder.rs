@@ -197,6 +197,7 @@ impl From<&avifDecoder> for Settings {
             image_count_limit: decoder.imageCountLimit,
             max_threads: u32::try_from(decoder.maxThreads).unwrap_or(0),
             android_mediacodec_output_color_format: decoder.androidMediaCodecOutputColorFormat,
+            ..Default::default()
         }
     }
 }
@@ -356,6 +357,13 @@ pub unsafe extern "C" fn crabby_avifDecoderRead(
         }
         rust_decoder_to_avifDecoder(rust_decoder, &mut (*decoder));
         *image = (*decoder).image_object.clone();
+        // The gain map of the decoder cannot be shared, since the image owns its gain map.
+        if !(*image).gainMap.is_null() {
+            (*image).gainMap = copy_gainmap(&*(*image).gainMap);
+            if (*image).gainMap.is_null() {
+                return avifResult::OutOfMemory;
+            }
+        }
         avifResult::Ok
     }
 }
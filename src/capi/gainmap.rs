@@ -95,3 +95,47 @@ impl From<&GainMap> for avifGainMap {
         }
     }
 }
+
+// Returns a copy of gainMap that can be freed with crabby_avifGainMapDestroy(), or null if altICC
+// could not be copied. The image is copied too, but its planes are not, so they remain owned by the
+// source of gainMap.
+pub(crate) unsafe fn copy_gainmap(gainMap: &avifGainMap) -> *mut avifGainMap {
+    let mut altICC = avifRWData::default();
+    if unsafe { crabby_avifRWDataSet(&mut altICC, gainMap.altICC.data, gainMap.altICC.size) }
+        != avifResult::Ok
+    {
+        return std::ptr::null_mut();
+    }
+    let image = if gainMap.image.is_null() {
+        std::ptr::null_mut()
+    } else {
+        let mut image = unsafe { (*gainMap.image).clone() };
+        // Only the top level image can have a gain map.
+        image.gainMap = std::ptr::null_mut();
+        Box::into_raw(Box::new(image))
+    };
+    Box::into_raw(Box::new(avifGainMap {
+        image,
+        altICC,
+        ..*gainMap
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn crabby_avifGainMapCreate() -> *mut avifGainMap {
+    Box::into_raw(Box::<avifGainMap>::default())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn crabby_avifGainMapDestroy(gainMap: *mut avifGainMap) {
+    if gainMap.is_null() {
+        return;
+    }
+    unsafe {
+        if !(*gainMap).image.is_null() {
+            crabby_avifImageDestroy((*gainMap).image);
+        }
+        crabby_avifRWDataFree(&mut (*gainMap).altICC);
+        let _ = Box::from_raw(gainMap);
+    }
+}
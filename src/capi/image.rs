@@ -439,8 +439,13 @@ pub unsafe extern "C" fn crabby_avifImageFreePlanes(
 
 #[no_mangle]
 pub unsafe extern "C" fn crabby_avifImageDestroy(image: *mut avifImage) {
+    if image.is_null() {
+        return;
+    }
     unsafe {
         crabby_avifImageFreePlanes(image, avifPlanesFlag::AvifPlanesAll as u32);
+        // The gain map is owned by the image, as in libavif.
+        crabby_avifGainMapDestroy((*image).gainMap);
         let _ = Box::from_raw(image);
     }
 }
@@ -551,12 +556,22 @@ pub unsafe extern "C" fn crabby_avifImagePlaneHeight(
     }
 }
 
+fn view_plane_offset(x: u32, y: u32, row_bytes: u32, pixel_size: u32) -> AvifResult<isize> {
+    isize_from_u32(checked_add!(
+        checked_mul!(y, row_bytes)?,
+        checked_mul!(x, pixel_size)?
+    )?)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn crabby_avifImageSetViewRect(
     dstImage: *mut avifImage,
     srcImage: *const avifImage,
     rect: *const avifCropRect,
 ) -> avifResult {
+    if dstImage.is_null() || srcImage.is_null() || rect.is_null() {
+        return avifResult::InvalidArgument;
+    }
     let dst = unsafe { &mut (*dstImage) };
     let src = unsafe { &(*srcImage) };
     let rect = unsafe { &(*rect) };
@@ -567,12 +582,23 @@ pub unsafe extern "C" fn crabby_avifImageSetViewRect(
     {
         return avifResult::InvalidArgument;
     }
+    // The chroma plane of the NV12 and NV21 formats interleaves U and V at half the horizontal
+    // resolution, so the view must also start on an even column for them.
+    let subsampled_x = src.yuvFormat.chroma_shift_x().0 != 0
+        || matches!(
+            src.yuvFormat,
+            PixelFormat::AndroidNv12 | PixelFormat::AndroidNv21
+        );
     if !src.yuvFormat.is_monochrome()
-        && ((rect.x & src.yuvFormat.chroma_shift_x().0) != 0
-            || (rect.y & src.yuvFormat.chroma_shift_y()) != 0)
+        && ((subsampled_x && (rect.x & 1) != 0) || (rect.y & src.yuvFormat.chroma_shift_y()) != 0)
     {
         return avifResult::InvalidArgument;
     }
+    // The view does not own any of its planes. Release the planes that dst may currently own
+    // before overwriting it.
+    unsafe {
+        crabby_avifImageFreePlanes(dstImage, avifPlanesFlag::AvifPlanesAll as u32);
+    }
     *dst = avifImage {
         width: src.width,
         height: src.height,
@@ -602,7 +628,7 @@ pub unsafe extern "C" fn crabby_avifImageSetViewRect(
         let chroma_shift = src.yuvFormat.chroma_shift_x();
         let x = if plane == 0 { rect.x } else { (rect.x >> chroma_shift.0) << chroma_shift.1 };
         let y = if plane == 0 { rect.y } else { rect.y >> src.yuvFormat.chroma_shift_y() };
-        let offset = match isize_from_u32(y * src.yuvRowBytes[plane] + x * pixel_size) {
+        let offset = match view_plane_offset(x, y, src.yuvRowBytes[plane], pixel_size) {
             Ok(x) => x,
             _ => return avifResult::InvalidArgument,
         };
@@ -610,7 +636,7 @@ pub unsafe extern "C" fn crabby_avifImageSetViewRect(
         dst.yuvRowBytes[plane] = src.yuvRowBytes[plane];
     }
     if !src.alphaPlane.is_null() {
-        let offset = match isize_from_u32(rect.y * src.alphaRowBytes + rect.x * pixel_size) {
+        let offset = match view_plane_offset(rect.x, rect.y, src.alphaRowBytes, pixel_size) {
             Ok(x) => x,
             _ => return avifResult::InvalidArgument,
         };
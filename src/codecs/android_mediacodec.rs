@@ -667,6 +667,26 @@ impl Decoder for MediaCodec {
             "all the codecs failed to extract an image".into(),
         ))
     }
+
+    fn flush(&mut self) -> AvifResult<()> {
+        if self.codec.is_none() {
+            return Ok(());
+        }
+        let codec = self.codec.unwrap();
+        if self.output_buffer_index.is_some() {
+            unsafe {
+                AMediaCodec_releaseOutputBuffer(codec, self.output_buffer_index.unwrap(), false);
+            }
+            self.output_buffer_index = None;
+        }
+        let status = unsafe { AMediaCodec_flush(codec) };
+        if status != media_status_t_AMEDIA_OK {
+            return Err(AvifError::UnknownError(format!(
+                "AMediaCodec_flush returned {status}"
+            )));
+        }
+        Ok(())
+    }
 }
 
 impl MediaCodec {
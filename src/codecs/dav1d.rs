@@ -28,6 +28,7 @@ use std::mem::MaybeUninit;
 pub struct Dav1d {
     context: Option<*mut Dav1dContext>,
     picture: Option<Dav1dPicture>,
+    frame_threading: bool,
 }
 
 unsafe extern "C" fn avif_dav1d_free_callback(
@@ -52,8 +53,9 @@ impl Decoder for Dav1d {
         let mut settings_uninit: MaybeUninit<Dav1dSettings> = MaybeUninit::uninit();
         unsafe { dav1d_default_settings(settings_uninit.as_mut_ptr()) };
         let mut settings = unsafe { settings_uninit.assume_init() };
-        settings.max_frame_delay = 1;
-        settings.n_threads = i32::try_from(config.max_threads).unwrap_or(1);
+        settings.max_frame_delay = i32::try_from(config.dav1d_frame_threads).unwrap_or(1);
+        settings.n_threads = i32::try_from(config.dav1d_tile_threads).unwrap_or(1);
+        self.frame_threading = settings.max_frame_delay != 1;
         settings.operating_point = config.operating_point as i32;
         settings.all_layers = if config.all_layers { 1 } else { 0 };
         // Set a maximum frame size limit to avoid OOM'ing fuzzers. In 32-bit builds, if
@@ -104,6 +106,7 @@ impl Decoder for Dav1d {
             }
             let mut next_frame: Dav1dPicture = std::mem::zeroed();
             let got_picture;
+            let mut waited_for_frame_threads = false;
             loop {
                 if !data.data.is_null() {
                     let res = dav1d_send_data(self.context.unwrap(), (&mut data) as *mut _);
@@ -121,6 +124,13 @@ impl Decoder for Dav1d {
                     if !data.data.is_null() {
                         continue;
                     }
+                    // With frame threading, the frame may still be decoded by another thread.
+                    // Calling dav1d_get_picture() again without sending data in between waits for
+                    // it.
+                    if self.frame_threading && !waited_for_frame_threads {
+                        waited_for_frame_threads = true;
+                        continue;
+                    }
                     return Err(AvifError::UnknownError("".into()));
                 } else if res < 0 {
                     if !data.data.is_null() {
@@ -251,6 +261,15 @@ impl Decoder for Dav1d {
         }
         Ok(())
     }
+
+    fn flush(&mut self) -> AvifResult<()> {
+        // self.picture stays valid after the flush and is released when the next picture is
+        // decoded.
+        if self.context.is_some() {
+            unsafe { dav1d_flush(self.context.unwrap()) };
+        }
+        Ok(())
+    }
 }
 
 impl Drop for Dav1d {
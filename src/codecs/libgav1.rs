@@ -84,6 +84,7 @@ impl Decoder for Libgav1 {
                     "Libgav1DecoderEnqueueFrame returned {ret}"
                 )));
             }
+            // The buffer of the previous frame is invalidated by Libgav1DecoderDequeueFrame().
             self.image = None;
             let mut next_frame: *const Libgav1DecoderBuffer = std::ptr::null_mut();
             loop {
@@ -104,14 +105,10 @@ impl Decoder for Libgav1 {
             }
             // Got an image.
             if next_frame.is_null() {
-                if category == Category::Alpha {
-                    // Special case for alpha, re-use last frame.
-                } else {
-                    return Err(AvifError::UnknownError("".into()));
-                }
-            } else {
-                self.image = Some(*next_frame);
+                // The previous frame cannot be reused since its planes are no longer valid.
+                return Err(AvifError::UnknownError("".into()));
             }
+            self.image = Some(*next_frame);
 
             let gav1_image = &self.image.unwrap();
             match category {
@@ -188,6 +185,20 @@ impl Decoder for Libgav1 {
         }
         Ok(())
     }
+
+    fn flush(&mut self) -> AvifResult<()> {
+        // The buffer returned by the last dequeue is invalidated by the flush.
+        self.image = None;
+        if self.decoder.is_some() {
+            let ret = unsafe { Libgav1DecoderSignalEOS(self.decoder.unwrap()) };
+            if ret != Libgav1StatusCode_kLibgav1StatusOk {
+                return Err(AvifError::UnknownError(format!(
+                    "Libgav1DecoderSignalEOS returned {ret}"
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Drop for Libgav1 {
@@ -40,6 +40,8 @@ pub struct DecoderConfig {
     pub codec_config: CodecConfiguration,
     pub category: Category,
     pub android_mediacodec_output_color_format: AndroidMediaCodecOutputColorFormat,
+    pub dav1d_frame_threads: u32,
+    pub dav1d_tile_threads: u32,
 }
 
 pub trait Decoder {
@@ -51,5 +53,8 @@ pub trait Decoder {
         image: &mut Image,
         category: Category,
     ) -> AvifResult<()>;
+    // Discards any decoder state so that the next call to get_next_image() can start again from
+    // a keyframe.
+    fn flush(&mut self) -> AvifResult<()>;
     // Destruction must be implemented using Drop.
 }
@@ -15,9 +15,11 @@
 use crate::decoder::*;
 use crate::internal_utils::stream::*;
 use crate::parser::mp4box::*;
+use crate::parser::obu::Av1SequenceHeader;
 use crate::*;
 
 use std::collections::BTreeMap;
+use std::ops::Range;
 
 #[derive(Debug, Default)]
 pub struct Item {
@@ -36,6 +38,9 @@ pub struct Item {
     pub dimg_index: u32,
     pub prem_by_id: u32,
     pub has_unsupported_essential_property: bool,
+    // (property_index, essential) ipma associations whose index is greater than the number of
+    // properties in ipco. They are not part of properties.
+    pub invalid_property_associations: Vec<(u16, bool)>,
     pub progressive: bool,
     pub idat: Vec<u8>,
     pub derived_item_ids: Vec<u32>,
@@ -265,7 +270,13 @@ impl Item {
         Ok(())
     }
 
-    pub(crate) fn validate_properties(&self, items: &Items, pixi_required: bool) -> AvifResult<()> {
+    // preferred_layer is Settings::preferred_layer, which replaces the layer requested by lsel.
+    pub(crate) fn validate_properties(
+        &self,
+        items: &Items,
+        pixi_required: bool,
+        preferred_layer: Option<u16>,
+    ) -> AvifResult<()> {
         let codec_config = self
             .codec_config()
             .ok_or(AvifError::BmffParseFailed("missing av1C property".into()))?;
@@ -285,6 +296,7 @@ impl Item {
                 }
             }
         }
+        self.validate_layer_selection(codec_config, preferred_layer)?;
         match self.pixi() {
             Some(pixi) => {
                 for depth in &pixi.plane_depths {
@@ -304,6 +316,72 @@ impl Item {
         Ok(())
     }
 
+    // Validates the layer that is decoded: preferred_layer if set, otherwise the one of lsel. Items
+    // without lsel are decoded progressively and have nothing to validate.
+    fn validate_layer_selection(
+        &self,
+        codec_config: &CodecConfiguration,
+        preferred_layer: Option<u16>,
+    ) -> AvifResult<()> {
+        let layer_id = match self.lsel() {
+            Some(lsel) => preferred_layer.unwrap_or(*lsel),
+            None => return Ok(()),
+        };
+        if layer_id == 0xFFFF {
+            return Ok(());
+        }
+        let config_obus = match codec_config {
+            CodecConfiguration::Av1(av1_config) if av1_config.raw_data.len() > 4 => {
+                &av1_config.raw_data[4..]
+            }
+            _ => return Ok(()),
+        };
+        // The configOBUs field of av1C optionally contains the sequence header. If it is not
+        // present, the operating points cannot be validated without reading the item payload.
+        let sequence_header = match Av1SequenceHeader::parse_from_obus(config_obus) {
+            Ok(sequence_header) => sequence_header,
+            Err(_) => return Ok(()),
+        };
+        // https://aomediacodec.github.io/av1-avif/v1.1.0.html#layer-selector-property:
+        //   The layer_id shall be a spatial_id of a layer included in the selected operating
+        //   point (operating point 0 if there is no a1op property).
+        let operating_point = self.operating_point();
+        match sequence_header.operating_point_has_spatial_layer(operating_point, layer_id) {
+            None => Err(AvifError::BmffParseFailed(format!(
+                "a1op operating point {operating_point} is not in the sequence header"
+            ))),
+            Some(false) => Err(AvifError::BmffParseFailed(format!(
+                "selected layer {layer_id} is not part of the a1op operating point \
+                 {operating_point}"
+            ))),
+            Some(true) => Ok(()),
+        }
+    }
+
+    pub(crate) fn validate_unique_properties(&self) -> AvifResult<()> {
+        // These properties describe a single value for the whole item. Files with multiple
+        // instances of one of them are ambiguous.
+        let mut seen = [false; 6];
+        for property in &self.properties {
+            let (index, name) = match property {
+                ItemProperty::ContentLightLevelInformation(_) => (0, "clli"),
+                ItemProperty::PixelAspectRatio(_) => (1, "pasp"),
+                ItemProperty::CleanAperture(_) => (2, "clap"),
+                ItemProperty::ImageRotation(_) => (3, "irot"),
+                ItemProperty::ImageMirror(_) => (4, "imir"),
+                ItemProperty::PixelInformation(_) => (5, "pixi"),
+                _ => continue,
+            };
+            if seen[index] {
+                return Err(AvifError::BmffParseFailed(format!(
+                    "multiple {name} properties were found"
+                )));
+            }
+            seen[index] = true;
+        }
+        Ok(())
+    }
+
     pub(crate) fn codec_config(&self) -> Option<&CodecConfiguration> {
         find_property!(self.properties, CodecConfiguration)
     }
@@ -349,15 +427,24 @@ impl Item {
         self.size == 0
             // An essential property isn't supported by libavif. Ignore the whole item.
             || self.has_unsupported_essential_property
+            // An essential property cannot be found. Ignore the whole item.
+            || self.has_invalid_essential_property_association()
             // Probably Exif/XMP or some other data.
             || !self.is_image_item()
             // libavif does not support thumbnails.
             || self.thumbnail_for_id != 0
     }
 
+    pub(crate) fn has_invalid_essential_property_association(&self) -> bool {
+        self.invalid_property_associations
+            .iter()
+            .any(|(_, essential)| *essential)
+    }
+
     fn is_metadata(&self, item_type: &str, color_id: Option<u32>) -> bool {
         self.size != 0
             && !self.has_unsupported_essential_property
+            && !self.has_invalid_essential_property_association()
             && (color_id.is_none() || self.desc_for_id == color_id.unwrap())
             && self.item_type == *item_type
     }
@@ -370,6 +457,10 @@ impl Item {
         self.is_metadata("mime", color_id) && self.content_type == "application/rdf+xml"
     }
 
+    pub(crate) fn is_region(&self, color_id: u32) -> bool {
+        self.is_metadata("rgan", Some(color_id))
+    }
+
     pub(crate) fn is_tmap(&self) -> bool {
         self.is_metadata("tmap", None) && self.thumbnail_for_id == 0
     }
@@ -441,6 +532,58 @@ fn insert_item_if_not_exists(id: u32, items: &mut Items) {
     );
 }
 
+// Returns a description of each problem found in the file level extents (construction_method 0)
+// of the iloc box. size_hint is ignored if it is 0. Extents must not overlap any of the
+// box_ranges since those contain box structures rather than item data.
+pub(crate) fn validate_item_extents(
+    meta: &MetaBox,
+    size_hint: u64,
+    box_ranges: &[(String, Range<u64>)],
+) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    for iloc in &meta.iloc.items {
+        let item_id = iloc.item_id;
+        if iloc.extents.len() != iloc.extent_count as usize {
+            problems.push(format!(
+                "item id {item_id} has {} extents but extent_count is {}",
+                iloc.extents.len(),
+                iloc.extent_count
+            ));
+        }
+        if iloc.construction_method != 0 {
+            continue;
+        }
+        for extent in &iloc.extents {
+            let range = match iloc
+                .base_offset
+                .checked_add(extent.offset)
+                .and_then(|offset| Some(offset..offset.checked_add(extent.size as u64)?))
+            {
+                Some(range) => range,
+                None => {
+                    problems.push(format!("item id {item_id} has an extent that overflows"));
+                    continue;
+                }
+            };
+            if size_hint != 0 && range.end > size_hint {
+                problems.push(format!(
+                    "item id {item_id} has an extent [{}, {}) beyond the end of the file ({size_hint} bytes)",
+                    range.start, range.end
+                ));
+            }
+            for (box_type, box_range) in box_ranges {
+                if range.start < box_range.end && box_range.start < range.end {
+                    problems.push(format!(
+                        "item id {item_id} has an extent [{}, {}) overlapping the {box_type} box",
+                        range.start, range.end
+                    ));
+                }
+            }
+        }
+    }
+    problems
+}
+
 pub(crate) fn construct_items(meta: &MetaBox) -> AvifResult<Items> {
     let mut items: Items = BTreeMap::new();
     for iinf in &meta.iinf {
@@ -499,14 +642,15 @@ pub(crate) fn construct_items(meta: &MetaBox) -> AvifResult<Items> {
                 }
                 continue;
             }
-            // property_index is 1-based.
-            if property_index > meta.iprp.properties.len() {
-                return Err(AvifError::BmffParseFailed(
-                    "invalid property_index in ipma".into(),
-                ));
-            }
+            // property_index is 1-based. Out of range indices are recorded so that the caller can
+            // reject or ignore them depending on the strictness.
+            let Some(property) = meta.iprp.properties.get(property_index - 1) else {
+                item.invalid_property_associations
+                    .push((*property_index_ref, essential));
+                continue;
+            };
 
-            match (&meta.iprp.properties[property_index - 1], essential) {
+            match (property, essential) {
                 (ItemProperty::Unknown(_), true) => item.has_unsupported_essential_property = true,
                 (ItemProperty::AV1LayeredImageIndexing(_), true) => {
                     return Err(AvifError::BmffParseFailed(
@@ -566,5 +710,126 @@ pub(crate) fn construct_items(meta: &MetaBox) -> AvifResult<Items> {
             }
         }
     }
+    resolve_identity_items(&mut items);
     Ok(items)
 }
+
+// Maximum number of nested identity derived image items. This also guards against reference
+// cycles.
+const MAX_IDENTITY_ITEM_DEPTH: usize = 8;
+
+fn is_transformative_property(property: &ItemProperty) -> bool {
+    matches!(
+        property,
+        ItemProperty::CleanAperture(_)
+            | ItemProperty::ImageRotation(_)
+            | ItemProperty::ImageMirror(_)
+    )
+}
+
+// The properties of an iden item replace the properties of the same kind of its input. An item
+// can have both an nclx and an ICC colr property, and unknown properties are told apart by their
+// box type.
+fn property_kind(property: &ItemProperty) -> (std::mem::Discriminant<ItemProperty>, &str) {
+    let subtype = match property {
+        ItemProperty::ColorInformation(ColorInformation::Icc { .. }) => "prof",
+        ItemProperty::ColorInformation(ColorInformation::Nclx(_)) => "nclx",
+        ItemProperty::Unknown(box_type) => box_type.as_str(),
+        _ => "",
+    };
+    (std::mem::discriminant(property), subtype)
+}
+
+// Returns the ids of the dimg chain from the iden item iden_id to the first item that is not an
+// iden item. Returns None if an iden item of the chain does not have exactly one input, or if there
+// are too many nested iden items.
+fn identity_chain(items: &Items, iden_id: u32) -> Option<Vec<u32>> {
+    let mut chain = vec![iden_id];
+    loop {
+        let current_id = *chain.last().unwrap();
+        let mut sources = items.values().filter(|x| x.dimg_for_id == current_id);
+        let (Some(source), None) = (sources.next(), sources.next()) else {
+            return None;
+        };
+        if source.item_type != "iden" {
+            chain.push(source.id);
+            return Some(chain);
+        }
+        if chain.len() > MAX_IDENTITY_ITEM_DEPTH {
+            return None;
+        }
+        chain.push(source.id);
+    }
+}
+
+// Section 6.6.2.2 of ISO/IEC 23008-12 (HEIF):
+//   The identity derivation ('iden') has exactly one input image and produces it unchanged, the
+//   transformative properties associated with the derived image item are then applied.
+// Each iden item is turned into a copy of the coded image item at the end of its dimg chain, so
+// that the rest of the decoder can treat it like any other coded image item. The iden items that
+// cannot be resolved keep no payload, so they are skipped like other unsupported items instead of
+// making files with an unused broken iden item undecodable.
+fn resolve_identity_items(items: &mut Items) {
+    let iden_ids: Vec<u32> = items
+        .values()
+        .filter(|x| x.item_type == "iden")
+        .map(|x| x.id)
+        .collect();
+    for iden_id in iden_ids {
+        let Some(chain) = identity_chain(items, iden_id) else {
+            continue;
+        };
+        let coded_item = items.get(chain.last().unwrap()).unwrap();
+        if !coded_item.is_image_codec_item() {
+            // Derived image items as the input of an iden item are not supported.
+            continue;
+        }
+        let transformative_item_count = chain
+            .iter()
+            .filter(|id| {
+                items
+                    .get(id)
+                    .unwrap()
+                    .properties
+                    .iter()
+                    .any(is_transformative_property)
+            })
+            .count();
+        if transformative_item_count > 1 {
+            // Combining the transformations of several items is not supported.
+            continue;
+        }
+        // The descriptive properties of the outer items take precedence. The spatial extents
+        // always come from the coded item since they have to match the decoded image.
+        let mut properties: Vec<ItemProperty> = Vec::new();
+        for item_id in &chain {
+            let item = items.get(item_id).unwrap();
+            // The properties taken from the outer items.
+            let seen_count = properties.len();
+            for property in &item.properties {
+                let is_ispe = matches!(property, ItemProperty::ImageSpatialExtents(_));
+                let seen = properties[..seen_count]
+                    .iter()
+                    .any(|x| property_kind(x) == property_kind(property));
+                if (is_ispe && item.id != coded_item.id) || (!is_ispe && seen) {
+                    continue;
+                }
+                properties.push(property.clone());
+            }
+        }
+        let item_type = coded_item.item_type.clone();
+        let size = coded_item.size;
+        let extents = coded_item.extents.clone();
+        let idat = coded_item.idat.clone();
+        let has_unsupported_essential_property = chain
+            .iter()
+            .any(|id| items.get(id).unwrap().has_unsupported_essential_property);
+        let iden_item = items.get_mut(&iden_id).unwrap();
+        iden_item.item_type = item_type;
+        iden_item.size = size;
+        iden_item.extents = extents;
+        iden_item.idat = idat;
+        iden_item.properties = properties;
+        iden_item.has_unsupported_essential_property = has_unsupported_essential_property;
+    }
+}
@@ -14,11 +14,13 @@
 
 pub mod gainmap;
 pub mod item;
+pub mod region;
 pub mod tile;
 pub mod track;
 
 use crate::decoder::gainmap::*;
 use crate::decoder::item::*;
+use crate::decoder::region::*;
 use crate::decoder::tile::*;
 use crate::decoder::track::*;
 
@@ -39,10 +41,15 @@ use crate::parser::exif;
 use crate::parser::mp4box;
 use crate::parser::mp4box::*;
 use crate::parser::obu::Av1SequenceHeader;
+#[cfg(feature = "conversion")]
+use crate::reformat::depth::Dither;
 use crate::*;
 
+use std::cell::Cell;
 use std::cmp::max;
 use std::cmp::min;
+use std::rc::Rc;
+use std::sync::Arc;
 
 pub trait IO {
     fn read(&mut self, offset: u64, max_read_size: usize) -> AvifResult<&[u8]>;
@@ -65,7 +72,7 @@ impl dyn IO {
 pub type GenericIO = Box<dyn IO>;
 pub type Codec = Box<dyn crate::codecs::Decoder>;
 
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum CodecChoice {
     #[default]
     Auto,
@@ -126,10 +133,12 @@ pub const DEFAULT_IMAGE_SIZE_LIMIT: u32 = 16384 * 16384;
 pub const DEFAULT_IMAGE_DIMENSION_LIMIT: u32 = 32768;
 pub const DEFAULT_IMAGE_COUNT_LIMIT: u32 = 12 * 3600 * 60;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ImageContentType {
     None,
     ColorAndAlpha,
+    // Only the alpha auxiliary image. The color planes of the decoded image are left empty.
+    AlphaOnly,
     GainMap,
     All,
 }
@@ -139,6 +148,7 @@ impl ImageContentType {
         match self {
             Self::None => vec![],
             Self::ColorAndAlpha => vec![Category::Color, Category::Alpha],
+            Self::AlphaOnly => vec![Category::Alpha],
             Self::GainMap => vec![Category::Gainmap],
             Self::All => Category::ALL.to_vec(),
         }
@@ -149,21 +159,79 @@ impl ImageContentType {
     }
 }
 
+// The settings are read at different times:
+//  - The settings marked "parse" are read by parse(). Changing them afterwards requires calling
+//    parse() again. Otherwise the decoding functions return AvifError::CannotChangeSetting.
+//  - The settings marked "codec" are read when the codecs are created, by the first decoding call
+//    that follows parse(). Changing them after that call makes the decoding functions return
+//    AvifError::CannotChangeSetting until parse() is called again.
+//  - The other settings are read by every decoding call and can be changed at any time.
 #[derive(Debug)]
 pub struct Settings {
+    // Parse.
     pub source: Source,
+    // Parse.
     pub ignore_exif: bool,
+    // Parse.
     pub ignore_xmp: bool,
+    // Parse. Some of the checks happen while decoding the first frame.
     pub strictness: Strictness,
+    // Parse.
     pub allow_progressive: bool,
     pub allow_incremental: bool,
+    // Codec. The gain map is only read by parse() if it is requested, so requesting it after a
+    // parse() that did not request it is an error if the file has a gain map.
     pub image_content_to_decode: ImageContentType,
+    // Codec.
     pub codec_choice: CodecChoice,
+    // Parse.
     pub image_size_limit: u32,
+    // Parse.
     pub image_dimension_limit: u32,
+    // Parse.
     pub image_count_limit: u32,
+    // Codec.
     pub max_threads: u32,
+    // Codec.
     pub android_mediacodec_output_color_format: AndroidMediaCodecOutputColorFormat,
+    // Codec. dav1d only. Maximum number of frames decoded in parallel (max_frame_delay of
+    // dav1d). More frame threads increase the throughput of sequences at the cost of latency and
+    // memory. Defaults to 1 because frames are decoded one at a time.
+    pub dav1d_frame_threads: Option<u32>,
+    // Codec. dav1d only. Size of the worker thread pool (n_threads of dav1d), which dav1d shares
+    // between tile, postfilter and frame tasks. Defaults to max_threads.
+    pub dav1d_tile_threads: Option<u32>,
+    // If true, a frame of a sequence that is not a keyframe and that the codec fails to decode is
+    // replaced by a gray frame and reported in diagnostics, instead of making next_image() return
+    // an error. Keyframe failures are still errors. The following frames may show artifacts until
+    // the next keyframe.
+    pub skip_corrupt_frames: bool,
+    // Parse. If set, overrides the layer requested by the 'lsel' property of layered items.
+    // Ignored for items that do not have an 'lsel' property.
+    pub preferred_layer: Option<u16>,
+    // Parse. If true, 4:4:4 images without any matrix coefficients in the nclx property or in the
+    // sequence header are assumed to be lossless GBR content and use
+    // MatrixCoefficients::Identity. Lossless coding cannot be detected from the headers, so this
+    // should only be set when the content is known to be lossless. Lossy YUV 4:4:4 images that
+    // lack CICP information would be converted to RGB with the wrong colors.
+    pub assume_identity_when_444_lossless: bool,
+    // Parse. If set, the planes that the decoder allocates (for example when compositing grids) are
+    // requested from this allocator instead of the Rust allocator. Planes that reference the
+    // codec's output buffers are not affected.
+    pub plane_allocator: Option<Arc<dyn PlaneAllocator>>,
+    // If true, limited range images are converted to full range once fully decoded (see
+    // Image::to_full_range()) and reported with YuvRange::Full, for callers that only handle full
+    // range data. Rows reported by incremental decoding before the frame is complete are still
+    // limited range. The gain map image keeps its range.
+    pub force_full_range_output: bool,
+    // If true, images with a depth higher than 8 bits are reduced to 8 bits with
+    // force_8bit_output_dither once fully decoded, so that callers such as previews do not have
+    // to handle 16-bit planes. The gain map image keeps its depth. Not supported for the Android
+    // specific pixel formats.
+    #[cfg(feature = "conversion")]
+    pub force_8bit_output: bool,
+    #[cfg(feature = "conversion")]
+    pub force_8bit_output_dither: Dither,
 }
 
 impl Default for Settings {
@@ -182,10 +250,87 @@ impl Default for Settings {
             image_count_limit: DEFAULT_IMAGE_COUNT_LIMIT,
             max_threads: 1,
             android_mediacodec_output_color_format: AndroidMediaCodecOutputColorFormat::default(),
+            dav1d_frame_threads: None,
+            dav1d_tile_threads: None,
+            skip_corrupt_frames: false,
+            preferred_layer: None,
+            assume_identity_when_444_lossless: false,
+            plane_allocator: None,
+            force_full_range_output: false,
+            #[cfg(feature = "conversion")]
+            force_8bit_output: false,
+            #[cfg(feature = "conversion")]
+            force_8bit_output_dither: Dither::default(),
+        }
+    }
+}
+
+// The settings read by parse(). See Settings.
+#[derive(Debug, Default, PartialEq)]
+struct ParseSettings {
+    source: Source,
+    ignore_exif: bool,
+    ignore_xmp: bool,
+    strictness: Strictness,
+    allow_progressive: bool,
+    image_size_limit: u32,
+    image_dimension_limit: u32,
+    image_count_limit: u32,
+    preferred_layer: Option<u16>,
+    assume_identity_when_444_lossless: bool,
+}
+
+// The settings read when the codecs are created. See Settings.
+#[derive(Debug, PartialEq)]
+struct CodecSettings {
+    image_content_to_decode: ImageContentType,
+    codec_choice: CodecChoice,
+    max_threads: u32,
+    android_mediacodec_output_color_format: AndroidMediaCodecOutputColorFormat,
+    dav1d_frame_threads: Option<u32>,
+    dav1d_tile_threads: Option<u32>,
+}
+
+impl Settings {
+    fn parse_settings(&self) -> ParseSettings {
+        ParseSettings {
+            source: self.source,
+            ignore_exif: self.ignore_exif,
+            ignore_xmp: self.ignore_xmp,
+            strictness: self.strictness.clone(),
+            allow_progressive: self.allow_progressive,
+            image_size_limit: self.image_size_limit,
+            image_dimension_limit: self.image_dimension_limit,
+            image_count_limit: self.image_count_limit,
+            preferred_layer: self.preferred_layer,
+            assume_identity_when_444_lossless: self.assume_identity_when_444_lossless,
+        }
+    }
+
+    fn codec_settings(&self) -> CodecSettings {
+        CodecSettings {
+            image_content_to_decode: self.image_content_to_decode,
+            codec_choice: self.codec_choice,
+            max_threads: self.max_threads,
+            android_mediacodec_output_color_format: self.android_mediacodec_output_color_format,
+            dav1d_frame_threads: self.dav1d_frame_threads,
+            dav1d_tile_threads: self.dav1d_tile_threads,
         }
     }
 }
 
+impl CodecSettings {
+    fn dav1d_tile_threads(&self) -> u32 {
+        self.dav1d_tile_threads.unwrap_or(self.max_threads)
+    }
+
+    // The decoder sends one sample and waits for its picture, so additional frame contexts would
+    // only cost memory unless explicitly requested.
+    fn dav1d_frame_threads(&self) -> u32 {
+        self.dav1d_frame_threads.unwrap_or(1)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
 pub struct Extent {
@@ -211,14 +356,37 @@ impl Extent {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum StrictnessFlag {
+    // Image items must have a pixi property. Only checked for the items of the file: the alpha
+    // grid item that is made up when each color tile has its own alpha auxiliary image never has
+    // one, so it is accepted whatever the strictness.
     PixiRequired,
     ClapValid,
     AlphaIspeRequired,
+    // Single-valued properties such as clli or pixi must not be associated more than once with
+    // the same item.
+    UniquePropertiesRequired,
+    // A primary item with an unknown essential property is rejected instead of being decoded
+    // without that property.
+    EssentialPropertiesKnown,
+    // The property indices of ipma must refer to properties of ipco.
+    PropertyIndicesValid,
+    // The alpha image must have the dimensions of the color image instead of being scaled to them.
+    AlphaDimensionsValid,
+    // The color space of an ICC profile must match the number of channels of the image.
+    IccColorSpaceValid,
+    // The iloc extents must be within the file and must not overlap the top level boxes other
+    // than mdat, such as ftyp or meta.
+    ItemExtentsValid,
+    // The depth and format of the decoded frames must match the codec configuration property.
+    CodecConfigurationValid,
+    // The level of av1C must allow the dimensions of the image. Other decoders play files with a
+    // level that is too low, so this flag is only enabled by SpecificInclude.
+    Av1LevelValid,
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum Strictness {
     None,
     #[default]
@@ -228,28 +396,57 @@ pub enum Strictness {
 }
 
 impl Strictness {
-    pub(crate) fn pixi_required(&self) -> bool {
+    // SpecificInclude enables only the listed flags and SpecificExclude enables all the flags but
+    // the listed ones. All and None enable and disable all the flags, respectively. This does not
+    // apply to Av1LevelValid, see av1_level_validated().
+    fn is_enabled(&self, flag: StrictnessFlag) -> bool {
         match self {
             Strictness::All => true,
-            Strictness::SpecificInclude(flags) => flags
-                .iter()
-                .any(|x| matches!(x, StrictnessFlag::PixiRequired)),
-            Strictness::SpecificExclude(flags) => !flags
-                .iter()
-                .any(|x| matches!(x, StrictnessFlag::PixiRequired)),
-            _ => false,
+            Strictness::SpecificInclude(flags) => flags.contains(&flag),
+            Strictness::SpecificExclude(flags) => !flags.contains(&flag),
+            Strictness::None => false,
         }
     }
 
+    pub(crate) fn pixi_required(&self) -> bool {
+        self.is_enabled(StrictnessFlag::PixiRequired)
+    }
+
     pub(crate) fn alpha_ispe_required(&self) -> bool {
+        self.is_enabled(StrictnessFlag::AlphaIspeRequired)
+    }
+
+    pub(crate) fn unique_properties_required(&self) -> bool {
+        self.is_enabled(StrictnessFlag::UniquePropertiesRequired)
+    }
+
+    pub(crate) fn unknown_essential_properties_rejected(&self) -> bool {
+        self.is_enabled(StrictnessFlag::EssentialPropertiesKnown)
+    }
+
+    pub(crate) fn item_extents_validated(&self) -> bool {
+        self.is_enabled(StrictnessFlag::ItemExtentsValid)
+    }
+
+    pub(crate) fn codec_config_validated(&self) -> bool {
+        self.is_enabled(StrictnessFlag::CodecConfigurationValid)
+    }
+
+    pub(crate) fn property_indices_validated(&self) -> bool {
+        self.is_enabled(StrictnessFlag::PropertyIndicesValid)
+    }
+
+    pub(crate) fn alpha_dimensions_validated(&self) -> bool {
+        self.is_enabled(StrictnessFlag::AlphaDimensionsValid)
+    }
+
+    pub(crate) fn icc_color_space_validated(&self) -> bool {
+        self.is_enabled(StrictnessFlag::IccColorSpaceValid)
+    }
+
+    pub(crate) fn av1_level_validated(&self) -> bool {
         match self {
-            Strictness::All => true,
-            Strictness::SpecificInclude(flags) => flags
-                .iter()
-                .any(|x| matches!(x, StrictnessFlag::AlphaIspeRequired)),
-            Strictness::SpecificExclude(flags) => !flags
-                .iter()
-                .any(|x| matches!(x, StrictnessFlag::AlphaIspeRequired)),
+            Strictness::SpecificInclude(flags) => flags.contains(&StrictnessFlag::Av1LevelValid),
             _ => false,
         }
     }
@@ -280,6 +477,48 @@ pub struct IOStats {
     pub alpha_obu_size: usize,
 }
 
+// Byte counts of the compressed payload of an item or of a track sample, as reported by
+// Decoder::verify_payload_integrity().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PayloadIntegrity {
+    pub category: Category,
+    // 0 if the payload is a track sample.
+    pub item_id: u32,
+    // Index of the sample in its track. Always 0 for items.
+    pub sample_index: usize,
+    // Item size, or sample size from stsz for track samples.
+    pub declared_size: usize,
+    // Sum of the iloc extent sizes. Same as declared_size for track samples.
+    pub extents_size: usize,
+    // Number of bytes that could actually be read.
+    pub read_size: usize,
+    // True if at least one extent (or the track sample) could not be read entirely.
+    pub short_read: bool,
+    // True if the track sample is not entirely contained in the payload of a MediaDataBox. Always
+    // false for items.
+    pub outside_mdat: bool,
+}
+
+impl PayloadIntegrity {
+    pub fn is_intact(&self) -> bool {
+        !self.short_read
+            && !self.outside_mdat
+            && self.extents_size == self.declared_size
+            && self.read_size == self.declared_size
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntegrityReport {
+    pub payloads: Vec<PayloadIntegrity>,
+}
+
+impl IntegrityReport {
+    pub fn is_intact(&self) -> bool {
+        self.payloads.iter().all(|payload| payload.is_intact())
+    }
+}
+
 #[derive(Default)]
 pub struct Decoder {
     pub settings: Settings,
@@ -292,6 +531,11 @@ pub struct Decoder {
     repetition_count: RepetitionCount,
     gainmap: GainMap,
     gainmap_present: bool,
+    // Whether the primary item is a 'tmap' item, whose base image is decoded as the color image.
+    tone_mapped: bool,
+    regions: Vec<RegionAnnotation>,
+    metadata_track_samples: Vec<MetadataSample>,
+    diagnostics: Vec<String>,
     image: Image,
     source: Source,
     tile_info: [TileInfo; Category::COUNT],
@@ -305,7 +549,82 @@ pub struct Decoder {
     color_track_id: Option<u32>,
     parse_state: ParseState,
     io_stats: IOStats,
+    // Shared with the CountingIO wrapping io.
+    io_bytes_read: Rc<Cell<u64>>,
     compression_format: CompressionFormat,
+    // Decoded instead of the primary item by image_collection().
+    collection_item_id: Option<u32>,
+    // Cached result of is_effectively_monochrome() as (image_index, tolerance, result).
+    effectively_monochrome: Option<(i32, u16, bool)>,
+    movie_times: Option<MediaTimes>,
+    // The ipma entries of the top level meta box, in item id order.
+    property_associations: Vec<ItemPropertyAssociation>,
+    // Whether a tile of the current frame failed to decode and was skipped because of
+    // Settings::skip_corrupt_frames.
+    corrupt_frame: bool,
+    // Whether the frame being decoded is only fed to the codecs as a reference for the following
+    // frames, without being copied into the output images. Used by next_image_at().
+    skip_output: bool,
+    // The item ids of the first 'altr' entity group containing the primary item, in order of
+    // preference. Empty if there is no such group.
+    alternative_ids: Vec<u32>,
+    // Decoded instead of the primary item if it is one of alternative_ids. Set by
+    // select_alternative().
+    selected_alternative: Option<u32>,
+    // The last row count passed to the callback of next_image_incremental() for the frame being
+    // decoded.
+    reported_row_count: u32,
+    // Whether self.image was converted to full range because of Settings::force_full_range_output.
+    // The range of the file is restored before decoding the next frame.
+    full_range_forced: bool,
+    // The values of the settings when parse() and create_codecs() were last called.
+    parse_settings: ParseSettings,
+    codec_settings: Option<CodecSettings>,
+}
+
+// Where the fields of a SequenceHeaderInfo come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SequenceHeaderInfoSource {
+    // Only the FileTypeBox was available.
+    #[default]
+    FileType,
+    // The properties of the primary item in the MetaBox.
+    PrimaryItem,
+    // The AV1 sequence header at the start of the MediaDataBox.
+    SequenceHeader,
+}
+
+// Best-effort information about the primary image, as returned by
+// Decoder::peek_sequence_header_info().
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SequenceHeaderInfo {
+    pub source: SequenceHeaderInfoSource,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub depth: Option<u8>,
+    pub yuv_format: Option<PixelFormat>,
+}
+
+// An item of the top level meta box, as returned by Decoder::item_infos().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetaItemInfo {
+    pub item_id: u32,
+    pub item_type: String,
+    // Only set for 'mime' items, such as "application/rdf+xml" for XMP.
+    pub content_type: String,
+    // The id of the item that this item describes through a 'cdsc' reference, or 0.
+    pub describes_item_id: u32,
+    pub size: usize,
+}
+
+// An item of the 'altr' entity group of the primary item, as returned by
+// Decoder::alternatives().
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AlternativeInfo {
+    pub item_id: u32,
+    pub item_type: String,
+    // Whether the item can be decoded by select_alternative().
+    pub supported: bool,
 }
 
 #[repr(C)]
@@ -367,15 +686,147 @@ impl Decoder {
     pub fn repetition_count(&self) -> RepetitionCount {
         self.repetition_count
     }
+    // Returns the creation and modification times of the movie (mvhd), or of the color track
+    // (tkhd) if there is no mvhd box. Returns None if the file has no tracks.
+    pub fn media_times(&self) -> Option<MediaTimes> {
+        if !self.parsing_complete() || self.tracks.is_empty() {
+            return None;
+        }
+        self.movie_times.or_else(|| {
+            let color_track_id = self.color_track_id?;
+            self.tracks
+                .iter()
+                .find(|track| track.id == color_track_id)
+                .map(|track| track.times)
+        })
+    }
     pub fn gainmap(&self) -> &GainMap {
         &self.gainmap
     }
     pub fn gainmap_present(&self) -> bool {
         self.gainmap_present
     }
+    // Returns true if the primary item of the file is a 'tmap' derived image item. Its base image
+    // is decoded as decoder.image, whether or not image_content_to_decode includes the gain map,
+    // so a viewer that does not apply gain maps still gets the base image.
+    pub fn is_tone_mapped(&self) -> bool {
+        self.tone_mapped
+    }
+    // Returns the gain map metadata if a supported gain map is present. It is populated by
+    // parse() even when image_content_to_decode does not include the gain map.
+    pub fn gainmap_metadata(&self) -> Option<&GainMapMetadata> {
+        if self.gainmap_present {
+            Some(&self.gainmap.metadata)
+        } else {
+            None
+        }
+    }
+    pub fn regions(&self) -> &[RegionAnnotation] {
+        &self.regions
+    }
+    // Samples of all the timed metadata tracks, in track order and then in decoding order.
+    pub fn metadata_track_samples(&self) -> &[MetadataSample] {
+        &self.metadata_track_samples
+    }
+    // Problems that were tolerated while parsing and decoding, either because of the configured
+    // strictness or because they do not prevent decoding the image.
+    pub fn diagnostics(&self) -> &[String] {
+        &self.diagnostics
+    }
+    // Item id and box type of each unrecognized non-essential property associated with an item,
+    // in item id order. Unrecognized essential properties are not listed. They make their item
+    // unusable unless the strictness allows ignoring them.
+    pub fn unknown_properties(&self) -> Vec<(u32, String)> {
+        self.items
+            .values()
+            .flat_map(|item| {
+                item.properties
+                    .iter()
+                    .filter_map(|property| match property {
+                        ItemProperty::Unknown(box_type) => Some((item.id, box_type.clone())),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+    // The (1-based property index, essential) pairs of the ipma entry of the given item, as
+    // stored in the file. Returns None if the item has no ipma entry.
+    pub fn item_property_associations(&self, item_id: u32) -> Option<&[(u16, bool)]> {
+        self.property_associations
+            .iter()
+            .find(|entry| entry.item_id == item_id)
+            .map(|entry| entry.associations.as_slice())
+    }
+    // Returns the items of the top level meta box in item id order, such as all the Exif and XMP
+    // metadata items when there is more than one. Empty if parse() was not successfully called.
+    pub fn item_infos(&self) -> Vec<MetaItemInfo> {
+        self.items
+            .values()
+            .filter(|item| !item.is_made_up)
+            .map(|item| MetaItemInfo {
+                item_id: item.id,
+                item_type: item.item_type.clone(),
+                content_type: item.content_type.clone(),
+                describes_item_id: item.desc_for_id,
+                size: item.size,
+            })
+            .collect()
+    }
+    // Returns the payload of the given item of item_infos(), as stored in the file. For Exif
+    // items, this includes the 4-byte offset to the TIFF header that precedes the Exif data.
+    pub fn extract_item_data(&mut self, item_id: u32) -> AvifResult<Vec<u8>> {
+        if !self.parsing_complete() {
+            return Err(AvifError::NoContent);
+        }
+        let item = self
+            .items
+            .get_mut(&item_id)
+            .filter(|item| !item.is_made_up)
+            .ok_or(AvifError::InvalidArgument)?;
+        let mut stream = item.stream(self.io.unwrap_mut())?;
+        Ok(stream.get_slice(stream.bytes_left()?)?.to_vec())
+    }
+    // Returns the items of the 'altr' entity group containing the primary item, in the order of
+    // preference of the file. Empty if the primary item has no alternatives or if parse() was not
+    // successfully called.
+    pub fn alternatives(&self) -> Vec<AlternativeInfo> {
+        self.alternative_ids
+            .iter()
+            .filter_map(|item_id| self.items.get(item_id))
+            .map(|item| AlternativeInfo {
+                item_id: item.id,
+                item_type: item.item_type.clone(),
+                supported: !item.has_unsupported_essential_property
+                    && (item.is_tmap() || !item.should_skip()),
+            })
+            .collect()
+    }
+    // Decodes the given item of alternatives() instead of the primary item, starting from the
+    // first frame. The selection persists across calls to parse() and applies to files whose
+    // primary item belongs to an 'altr' group containing item_id.
+    pub fn select_alternative(&mut self, item_id: u32) -> AvifResult<()> {
+        if !self.parsing_complete() {
+            return Err(AvifError::NoContent);
+        }
+        if !self
+            .alternatives()
+            .iter()
+            .any(|alternative| alternative.item_id == item_id && alternative.supported)
+        {
+            return Err(AvifError::InvalidArgument);
+        }
+        self.selected_alternative = Some(item_id);
+        self.parse_state = ParseState::None;
+        self.parse()
+    }
     pub fn io_stats(&self) -> IOStats {
         self.io_stats
     }
+    // Returns the number of bytes returned by the IO since it was set. Bytes that are read more
+    // than once are counted every time. Not part of IOStats, whose layout matches avifIOStats.
+    pub fn total_bytes_read(&self) -> u64 {
+        self.io_bytes_read.get()
+    }
     pub fn compression_format(&self) -> CompressionFormat {
         self.compression_format
     }
@@ -385,14 +836,12 @@ impl Decoder {
     }
 
     pub fn set_io_file(&mut self, filename: &String) -> AvifResult<()> {
-        self.io = Some(Box::new(DecoderFileIO::create(filename)?));
-        self.parse_state = ParseState::None;
+        self.set_io(Box::new(DecoderFileIO::create(filename)?));
         Ok(())
     }
 
     pub fn set_io_vec(&mut self, data: Vec<u8>) {
-        self.io = Some(Box::new(DecoderMemoryIO { data }));
-        self.parse_state = ParseState::None;
+        self.set_io(Box::new(DecoderMemoryIO { data }));
     }
 
     /// # Safety
@@ -400,13 +849,16 @@ impl Decoder {
     /// This function is intended for use only from the C API. The assumption is that the caller
     /// will always pass in a valid pointer and size.
     pub unsafe fn set_io_raw(&mut self, data: *const u8, size: usize) -> AvifResult<()> {
-        self.io = Some(Box::new(unsafe { DecoderRawIO::create(data, size) }));
-        self.parse_state = ParseState::None;
+        self.set_io(Box::new(unsafe { DecoderRawIO::create(data, size) }));
         Ok(())
     }
 
     pub fn set_io(&mut self, io: GenericIO) {
-        self.io = Some(io);
+        self.io_bytes_read = Rc::new(Cell::new(0));
+        self.io = Some(Box::new(CountingIO {
+            io,
+            bytes_read: self.io_bytes_read.clone(),
+        }));
         self.parse_state = ParseState::None;
     }
 
@@ -562,16 +1014,40 @@ impl Decoder {
         Ok(())
     }
 
+    // Returns the id of the first item accepted by is_candidate that has a 'cdsc' reference to
+    // one of described_item_ids, in that order of preference. If described_item_ids is empty, the
+    // first item accepted by is_candidate is returned whatever it describes. Items are visited in
+    // item id order, so the lowest id is used among equally preferred items.
+    fn find_metadata_item_id(
+        items: &Items,
+        described_item_ids: &[u32],
+        is_candidate: impl Fn(&Item) -> bool,
+    ) -> Option<u32> {
+        let mut candidates = items.values().filter(|item| is_candidate(item));
+        if described_item_ids.is_empty() {
+            return candidates.next().map(|item| item.id);
+        }
+        let candidates: Vec<&Item> = candidates.collect();
+        described_item_ids
+            .iter()
+            .find_map(|id| candidates.iter().find(|item| item.desc_for_id == *id))
+            .map(|item| item.id)
+    }
+
+    // Copies the payload of the Exif and XMP items chosen by find_metadata_item_id() to image. All
+    // of them can be retrieved with item_infos() and extract_item_data().
     fn search_exif_or_xmp_metadata(
         items: &mut Items,
-        color_item_index: Option<u32>,
+        described_item_ids: &[u32],
         settings: &Settings,
         io: &mut GenericIO,
         image: &mut Image,
     ) -> AvifResult<()> {
         if !settings.ignore_exif {
-            if let Some(exif) = items.iter_mut().rfind(|x| x.1.is_exif(color_item_index)) {
-                let mut stream = exif.1.stream(io)?;
+            if let Some(id) =
+                Self::find_metadata_item_id(items, described_item_ids, |x| x.is_exif(None))
+            {
+                let mut stream = items.get_mut(&id).unwrap().stream(io)?;
                 exif::parse(&mut stream)?;
                 image
                     .exif
@@ -579,8 +1055,10 @@ impl Decoder {
             }
         }
         if !settings.ignore_xmp {
-            if let Some(xmp) = items.iter_mut().rfind(|x| x.1.is_xmp(color_item_index)) {
-                let mut stream = xmp.1.stream(io)?;
+            if let Some(id) =
+                Self::find_metadata_item_id(items, described_item_ids, |x| x.is_xmp(None))
+            {
+                let mut stream = items.get_mut(&id).unwrap().stream(io)?;
                 image
                     .xmp
                     .extend_from_slice(stream.get_slice(stream.bytes_left()?)?);
@@ -589,6 +1067,22 @@ impl Decoder {
         Ok(())
     }
 
+    fn parse_region_items(&mut self, color_item_id: u32) -> AvifResult<()> {
+        let color_item = self.items.get(&color_item_id).unwrap();
+        let (width, height) = (color_item.width, color_item.height);
+        for item in self.items.values_mut() {
+            if !item.is_region(color_item_id) {
+                continue;
+            }
+            let mut stream = item.stream(self.io.unwrap_mut())?;
+            let mut region_annotation = mp4box::parse_rgan(&mut stream)?;
+            region_annotation.item_id = item.id;
+            region_annotation.scale(width, height)?;
+            self.regions.push(region_annotation);
+        }
+        Ok(())
+    }
+
     fn generate_tiles(&mut self, item_id: u32, category: Category) -> AvifResult<Vec<Tile>> {
         let mut tiles: Vec<Tile> = Vec::new();
         let item = self
@@ -602,6 +1096,7 @@ impl Decoder {
             let mut tile = Tile::create_from_item(
                 self.items.get_mut(&item_id).unwrap(),
                 self.settings.allow_progressive,
+                self.settings.preferred_layer,
                 self.settings.image_count_limit,
                 self.io.unwrap_ref().size_hint(),
             )?;
@@ -615,7 +1110,7 @@ impl Decoder {
                     "dimg items were found but image is not grid or overlay.".into(),
                 ));
             }
-            let mut progressive = true;
+            let mut progressive = false;
             for derived_item_id in item.derived_item_ids.clone() {
                 let derived_item = self
                     .items
@@ -624,14 +1119,22 @@ impl Decoder {
                 let mut tile = Tile::create_from_item(
                     derived_item,
                     self.settings.allow_progressive,
+                    self.settings.preferred_layer,
                     self.settings.image_count_limit,
                     self.io.unwrap_ref().size_hint(),
                 )?;
                 tile.input.category = category;
                 tiles.push(tile);
-                progressive = progressive && derived_item.progressive;
+                progressive = progressive || derived_item.progressive;
             }
 
+            if progressive && self.settings.allow_progressive {
+                // All the cells advance to the same layer with each frame. The cells that are not
+                // progressive, such as the ones with an lsel property, keep their only sample.
+                for tile in &mut tiles {
+                    tile.input.pinned = tile.input.samples.len() == 1;
+                }
+            }
             if category == Category::Color && progressive {
                 // Propagate the progressive status to the top-level item.
                 self.items.get_mut(&item_id).unwrap().progressive = true;
@@ -802,11 +1305,32 @@ impl Decoder {
         self.duration = decoder.duration;
         self.repetition_count = decoder.repetition_count;
         self.gainmap = decoder.gainmap;
+        self.gainmap
+            .image
+            .plane_allocator
+            .clone_from(&self.settings.plane_allocator);
         self.gainmap_present = decoder.gainmap_present;
+        self.tone_mapped = decoder.tone_mapped;
+        self.regions = decoder.regions;
+        self.metadata_track_samples = decoder.metadata_track_samples;
+        self.diagnostics = decoder.diagnostics;
         self.image = decoder.image;
+        self.image
+            .plane_allocator
+            .clone_from(&self.settings.plane_allocator);
         self.tile_info = decoder.tile_info;
         self.tiles = decoder.tiles;
         self.image_index = decoder.image_index;
+        self.effectively_monochrome = decoder.effectively_monochrome;
+        self.movie_times = decoder.movie_times;
+        self.property_associations = decoder.property_associations;
+        self.corrupt_frame = decoder.corrupt_frame;
+        self.skip_output = decoder.skip_output;
+        self.alternative_ids = decoder.alternative_ids;
+        self.reported_row_count = decoder.reported_row_count;
+        self.full_range_forced = decoder.full_range_forced;
+        self.parse_settings = self.settings.parse_settings();
+        self.codec_settings = decoder.codec_settings;
         self.items = decoder.items;
         self.tracks = decoder.tracks;
         self.codecs = decoder.codecs;
@@ -826,11 +1350,15 @@ impl Decoder {
 
         if self.parse_state == ParseState::None {
             self.reset();
-            let avif_boxes = mp4box::parse(self.io.unwrap_mut())?;
+            let mut avif_boxes = mp4box::parse(self.io.unwrap_mut())?;
             self.tracks = avif_boxes.tracks;
+            self.movie_times = avif_boxes.movie_times;
             if !self.tracks.is_empty() {
                 self.image.image_sequence_track_present = true;
                 for track in &self.tracks {
+                    if track.is_metadata() {
+                        continue;
+                    }
                     if !track.check_limits(
                         self.settings.image_size_limit,
                         self.settings.image_dimension_limit,
@@ -842,6 +1370,70 @@ impl Decoder {
                 }
             }
             self.items = construct_items(&avif_boxes.meta)?;
+            self.property_associations = std::mem::take(&mut avif_boxes.meta.iprp.associations);
+            let primary_item_id = avif_boxes.meta.primary_item_id;
+            self.alternative_ids = avif_boxes
+                .meta
+                .grpl
+                .iter()
+                .find(|group| {
+                    group.grouping_type == "altr" && group.entity_ids.contains(&primary_item_id)
+                })
+                .map(|group| group.entity_ids.clone())
+                .unwrap_or_default();
+            let problems = validate_item_extents(
+                &avif_boxes.meta,
+                self.io.unwrap_ref().size_hint(),
+                &avif_boxes.box_ranges,
+            );
+            if !problems.is_empty() {
+                if self.settings.strictness.item_extents_validated() {
+                    return Err(AvifError::BmffParseFailed(problems.join("; ")));
+                }
+                self.diagnostics.extend(problems);
+            }
+            for item in self.items.values() {
+                if let Some(CodecConfiguration::Hevc(hvcc)) = item.codec_config() {
+                    for nal_unit_type in &hvcc.skipped_nal_unit_types {
+                        self.diagnostics.push(format!(
+                            "item id {}: ignored hvcC array of NAL unit type {nal_unit_type}",
+                            item.id
+                        ));
+                    }
+                }
+            }
+            if !self
+                .settings
+                .strictness
+                .unknown_essential_properties_rejected()
+            {
+                for item in self.items.values_mut() {
+                    if item.has_unsupported_essential_property {
+                        item.has_unsupported_essential_property = false;
+                        self.diagnostics.push(format!(
+                            "item id {}: ignored unknown essential property",
+                            item.id
+                        ));
+                    }
+                }
+            }
+            for item in self.items.values() {
+                for (property_index, essential) in &item.invalid_property_associations {
+                    let problem = format!(
+                        "item id {}: property_index {property_index} in ipma exceeds the property \
+                         count",
+                        item.id
+                    );
+                    if self.settings.strictness.property_indices_validated() {
+                        return Err(AvifError::BmffParseFailed(problem));
+                    }
+                    self.diagnostics.push(if *essential {
+                        format!("{problem}, ignored the item")
+                    } else {
+                        format!("{problem}, ignored the property")
+                    });
+                }
+            }
             if avif_boxes.ftyp.has_tmap() && !self.items.values().any(|x| x.item_type == "tmap") {
                 return Err(AvifError::BmffParseFailed(
                     "tmap was required but not found".into(),
@@ -856,6 +1448,7 @@ impl Decoder {
             }
 
             self.source = match self.settings.source {
+                _ if self.collection_item_id.is_some() => Source::PrimaryItem,
                 // Decide the source based on the major brand.
                 Source::Auto => match avif_boxes.ftyp.major_brand.as_str() {
                     "avis" => Source::Tracks,
@@ -884,7 +1477,7 @@ impl Decoder {
                     let mut color_track_items = construct_items(meta)?;
                     Self::search_exif_or_xmp_metadata(
                         &mut color_track_items,
-                        None,
+                        &[],
                         &self.settings,
                         self.io.unwrap_mut(),
                         &mut self.image,
@@ -916,9 +1509,29 @@ impl Decoder {
                     self.image.alpha_premultiplied = color_track.prem_by_id == Some(alpha_track.id);
                 }
 
+                for track in self.tracks.iter().filter(|x| x.is_metadata()) {
+                    let samples = track.read_metadata_samples(
+                        self.io.unwrap_mut(),
+                        self.settings.image_count_limit,
+                    )?;
+                    self.metadata_track_samples.extend(samples);
+                }
+
                 self.image_index = -1;
                 self.image_count =
                     self.tiles[Category::Color.usize()][0].input.samples.len() as u32;
+                // The timing of every sample can be computed anyway, so these problems are only
+                // reported.
+                if self.settings.strictness != Strictness::None {
+                    if let Some(sample_table) = &color_track.sample_table {
+                        for problem in
+                            sample_table.time_to_sample_problems(self.image_count as usize)
+                        {
+                            self.diagnostics
+                                .push(format!("track id {}: {problem}", color_track.id));
+                        }
+                    }
+                }
                 self.timescale = color_track.media_timescale as u64;
                 self.duration_in_timescales = color_track.media_duration;
                 if self.timescale != 0 {
@@ -934,30 +1547,73 @@ impl Decoder {
             } else {
                 assert_eq!(self.source, Source::PrimaryItem);
                 let mut item_ids: [u32; Category::COUNT] = [0; Category::COUNT];
+                let mut primary_item_id = self
+                    .collection_item_id
+                    .or(self
+                        .selected_alternative
+                        .filter(|id| self.alternative_ids.contains(id)))
+                    .unwrap_or(avif_boxes.meta.primary_item_id);
+                if self
+                    .items
+                    .get(&primary_item_id)
+                    .is_some_and(|item| item.is_tmap())
+                {
+                    // The primary item is the tone mapped image. Decode its base image (the
+                    // first dimg input) as the color item. The gain map is found from the base
+                    // image below.
+                    primary_item_id = self
+                        .items
+                        .values()
+                        .find(|x| x.dimg_for_id == primary_item_id && x.dimg_index == 0)
+                        .map(|x| x.id)
+                        .ok_or(AvifError::InvalidToneMappedImage(
+                            "tmap has no base image".into(),
+                        ))?;
+                    self.tone_mapped = true;
+                }
+
+                if self
+                    .items
+                    .get(&primary_item_id)
+                    .is_some_and(|item| item.has_unsupported_essential_property)
+                {
+                    // A reader is required to process the essential properties of an item.
+                    return Err(AvifError::NotImplemented);
+                }
 
                 // Mandatory color item (primary item).
                 let color_item_id = self
                     .items
                     .iter()
-                    .find(|x| {
-                        !x.1.should_skip()
-                            && x.1.id != 0
-                            && x.1.id == avif_boxes.meta.primary_item_id
-                    })
+                    .find(|x| !x.1.should_skip() && x.1.id != 0 && x.1.id == primary_item_id)
                     .map(|it| *it.0);
 
                 item_ids[Category::Color.usize()] = color_item_id.ok_or(AvifError::NoContent)?;
                 self.read_and_parse_item(item_ids[Category::Color.usize()], Category::Color)?;
 
                 // Find exif/xmp from meta if any.
+                // The metadata of the primary item of the file, such as a tmap item, is preferred
+                // over the metadata of the decoded color item. The images of a collection only get
+                // their own metadata.
+                let mut described_item_ids = vec![self
+                    .collection_item_id
+                    .unwrap_or(avif_boxes.meta.primary_item_id)];
+                if self.collection_item_id.is_none()
+                    && item_ids[Category::Color.usize()] != avif_boxes.meta.primary_item_id
+                {
+                    described_item_ids.push(item_ids[Category::Color.usize()]);
+                }
                 Self::search_exif_or_xmp_metadata(
                     &mut self.items,
-                    Some(item_ids[Category::Color.usize()]),
+                    &described_item_ids,
                     &self.settings,
                     self.io.unwrap_mut(),
                     &mut self.image,
                 )?;
 
+                // Find region annotations from meta if any.
+                self.parse_region_items(item_ids[Category::Color.usize()])?;
+
                 // Optional alpha auxiliary item
                 if let Some(alpha_item_id) =
                     self.find_alpha_item(item_ids[Category::Color.usize()])?
@@ -1021,13 +1677,20 @@ impl Decoder {
                         alpha_item.height = height;
                     }
 
-                    self.tiles[category.usize()] = self.generate_tiles(item_id, category)?;
                     let item = self.items.get(&item_id).unwrap();
                     // Made up alpha item does not contain the pixi property. So do not try to
                     // validate it.
                     let pixi_required =
                         self.settings.strictness.pixi_required() && !item.is_made_up;
-                    item.validate_properties(&self.items, pixi_required)?;
+                    item.validate_properties(
+                        &self.items,
+                        pixi_required,
+                        self.settings.preferred_layer,
+                    )?;
+                    if self.settings.strictness.unique_properties_required() {
+                        item.validate_unique_properties()?;
+                    }
+                    self.tiles[category.usize()] = self.generate_tiles(item_id, category)?;
                 }
 
                 let color_item = self.items.get(&item_ids[Category::Color.usize()]).unwrap();
@@ -1038,7 +1701,13 @@ impl Decoder {
 
                 if color_item.progressive {
                     self.image.progressive_state = ProgressiveState::Available;
-                    let sample_count = self.tiles[Category::Color.usize()][0].input.samples.len();
+                    // The frame count is limited by the cell with the fewest layers.
+                    let sample_count = self.tiles[Category::Color.usize()]
+                        .iter()
+                        .filter(|tile| !tile.input.pinned)
+                        .map(|tile| tile.input.samples.len())
+                        .min()
+                        .unwrap_or(1);
                     if sample_count > 1 {
                         self.image.progressive_state = ProgressiveState::Active;
                         self.image_count = sample_count as u32;
@@ -1123,6 +1792,8 @@ impl Decoder {
             self.image.clap = find_property!(color_properties, CleanAperture);
             self.image.irot_angle = find_property!(color_properties, ImageRotation);
             self.image.imir_axis = find_property!(color_properties, ImageMirror);
+            self.image.tai_clock_info = find_property!(color_properties, TaiClockInfo);
+            self.image.tai_timestamp = find_property!(color_properties, TaiTimestamp);
 
             if let Some(gainmap_properties) = gainmap_properties {
                 // Ensure that the bitstream contains the same 'pasp', 'clap', 'irot and 'imir'
@@ -1141,6 +1812,18 @@ impl Decoder {
             self.image.depth = codec_config.depth();
             self.image.yuv_format = codec_config.pixel_format();
             self.image.chroma_sample_position = codec_config.chroma_sample_position();
+            if let Some(problem) = Self::icc_color_space_mismatch(&self.image) {
+                if self.settings.strictness.icc_color_space_validated() {
+                    return Err(AvifError::BmffParseFailed(problem));
+                }
+                self.diagnostics.push(problem);
+            }
+            if let Some(problem) = self.av1_level_mismatch() {
+                if self.settings.strictness.av1_level_validated() {
+                    return Err(AvifError::BmffParseFailed(problem));
+                }
+                self.diagnostics.push(problem);
+            }
             self.compression_format = if codec_config.is_avif() {
                 CompressionFormat::Avif
             } else {
@@ -1156,6 +1839,12 @@ impl Decoder {
 
         // If cicp was not set, try to harvest it from the sequence header.
         self.harvest_cicp_from_sequence_header()?;
+        if self.settings.assume_identity_when_444_lossless
+            && self.image.yuv_format == PixelFormat::Yuv444
+            && self.image.matrix_coefficients == MatrixCoefficients::Unspecified
+        {
+            self.image.matrix_coefficients = MatrixCoefficients::Identity;
+        }
         self.parse_state = ParseState::Complete;
 
         Ok(())
@@ -1176,11 +1865,14 @@ impl Decoder {
         self.populate_grid_item_ids(item_id, category)
     }
 
+    // Only the tiles of the categories to decode are considered. The other categories do not get
+    // any codec.
     fn can_use_single_codec(&self) -> AvifResult<bool> {
-        let total_tile_count = checked_add!(
-            checked_add!(self.tiles[0].len(), self.tiles[1].len())?,
-            self.tiles[2].len()
-        )?;
+        let categories = self.settings.image_content_to_decode.categories();
+        let mut total_tile_count: usize = 0;
+        for category in &categories {
+            checked_incr!(total_tile_count, self.tiles[category.usize()].len());
+        }
         if total_tile_count == 1 {
             return Ok(true);
         }
@@ -1189,11 +1881,11 @@ impl Decoder {
         }
         let mut image_buffers = 0;
         let mut stolen_image_buffers = 0;
-        for category in Category::ALL_USIZE {
-            if self.tile_info[category].tile_count > 0 {
+        for category in &categories {
+            if self.tile_info[category.usize()].tile_count > 0 {
                 image_buffers += 1;
             }
-            if self.tile_info[category].tile_count == 1 {
+            if self.tile_info[category.usize()].tile_count == 1 {
                 stolen_image_buffers += 1;
             }
         }
@@ -1201,16 +1893,16 @@ impl Decoder {
             // Stealing will cause problems. So we need separate codec instances.
             return Ok(false);
         }
-        let operating_point = self.tiles[0][0].operating_point;
-        let all_layers = self.tiles[0][0].input.all_layers;
-        for tiles in &self.tiles {
-            for tile in tiles {
-                if tile.operating_point != operating_point || tile.input.all_layers != all_layers {
-                    return Ok(false);
-                }
-            }
-        }
-        Ok(true)
+        let mut tiles = categories
+            .iter()
+            .flat_map(|category| self.tiles[category.usize()].iter());
+        let Some(first_tile) = tiles.next() else {
+            return Ok(false);
+        };
+        Ok(tiles.all(|tile| {
+            tile.operating_point == first_tile.operating_point
+                && tile.input.all_layers == first_tile.input.all_layers
+        }))
     }
 
     fn create_codec(&mut self, category: Category, tile_index: usize) -> AvifResult<()> {
@@ -1219,6 +1911,7 @@ impl Decoder {
             .settings
             .codec_choice
             .get_codec(tile.codec_config.is_avif())?;
+        let codec_settings = self.settings.codec_settings();
         let config = DecoderConfig {
             operating_point: tile.operating_point,
             all_layers: tile.input.all_layers,
@@ -1233,6 +1926,8 @@ impl Decoder {
             android_mediacodec_output_color_format: self
                 .settings
                 .android_mediacodec_output_color_format,
+            dav1d_frame_threads: codec_settings.dav1d_frame_threads(),
+            dav1d_tile_threads: codec_settings.dav1d_tile_threads(),
         };
         codec.initialize(&config)?;
         self.codecs.push(codec);
@@ -1260,8 +1955,17 @@ impl Decoder {
                 }
             }
         } else if self.can_use_single_codec()? {
+            // Configure the codec with the first tile to decode, which is not a color tile when
+            // only the alpha or gain map images are requested.
+            let first_category = *self
+                .settings
+                .image_content_to_decode
+                .categories()
+                .iter()
+                .find(|category| !self.tiles[category.usize()].is_empty())
+                .unwrap();
             self.codecs = create_vec_exact(1)?;
-            self.create_codec(Category::Color, 0)?;
+            self.create_codec(first_category, 0)?;
             for tiles in &mut self.tiles {
                 for tile in tiles {
                     tile.codec_index = 0;
@@ -1276,6 +1980,14 @@ impl Decoder {
                 }
             }
         }
+        self.codec_settings = Some(self.settings.codec_settings());
+        Ok(())
+    }
+
+    fn flush_codecs(&mut self) -> AvifResult<()> {
+        for codec in &mut self.codecs {
+            codec.flush()?;
+        }
         Ok(())
     }
 
@@ -1287,10 +1999,7 @@ impl Decoder {
         max_num_bytes: Option<usize>, // Bytes read past that size will be ignored.
     ) -> AvifResult<()> {
         let tile = &mut self.tiles[category.usize()][tile_index];
-        if tile.input.samples.len() <= image_index {
-            return Err(AvifError::NoImagesRemaining);
-        }
-        let sample = &tile.input.samples[image_index];
+        let sample = tile.input.sample(image_index)?;
         if sample.item_id == 0 {
             // Data comes from a track. Nothing to prepare.
             return Ok(());
@@ -1402,17 +2111,97 @@ impl Decoder {
         Ok(())
     }
 
+    // Returns a description of the problem if the ICC profile of the image is a grayscale profile
+    // and the image has chroma planes, or if it is an RGB profile and the image is monochrome.
+    fn icc_color_space_mismatch(image: &Image) -> Option<String> {
+        let color_space = icc_color_space(&image.icc)?;
+        let monochrome = image.yuv_format == PixelFormat::Yuv400;
+        if (color_space == b"GRAY" && !monochrome) || (color_space == b"RGB " && monochrome) {
+            return Some(format!(
+                "ICC profile color space {:?} does not match the {:?} image",
+                String::from_utf8_lossy(color_space),
+                image.yuv_format
+            ));
+        }
+        None
+    }
+
+    // Returns a description of the problem if the level declared by the av1C property of a tile is
+    // too low for the dimensions of that tile. Each tile of a grid is a separate AV1 bitstream, so
+    // the level only constrains the dimensions of a cell.
+    fn av1_level_mismatch(&self) -> Option<String> {
+        Category::ALL.iter().find_map(|category| {
+            self.tiles[category.usize()].iter().find_map(|tile| {
+                let (max_picture_size, max_width, max_height) =
+                    tile.codec_config.max_picture_size()?;
+                if tile.width <= max_width
+                    && tile.height <= max_height
+                    && (tile.width as u64 * tile.height as u64) <= max_picture_size as u64
+                {
+                    return None;
+                }
+                let level = tile.codec_config.level();
+                Some(format!(
+                    "{category:?} av1C level {}.{} is too low for a {}x{} image",
+                    2 + (level >> 2),
+                    level & 3,
+                    tile.width,
+                    tile.height
+                ))
+            })
+        })
+    }
+
+    // Returns a description of the difference between the depth or the format promised by the
+    // av1C property and the ones of the decoded frame, if any.
+    fn codec_config_mismatch(
+        codec_config: &CodecConfiguration,
+        image: &Image,
+        category: Category,
+    ) -> Option<String> {
+        if matches!(
+            image.yuv_format,
+            PixelFormat::AndroidP010 | PixelFormat::AndroidNv12 | PixelFormat::AndroidNv21
+        ) {
+            return None;
+        }
+        let property = if codec_config.is_avif() { "av1C" } else { "hvcC" };
+        if image.depth != codec_config.depth() {
+            return Some(format!(
+                "{category:?} {property} depth {} does not match the decoded depth {}",
+                codec_config.depth(),
+                image.depth
+            ));
+        }
+        // The format of alpha planes is not reported by the codecs. HEVC is only decoded by
+        // android_mediacodec, which always outputs 4:2:0 whatever the hvcC property says.
+        if category != Category::Alpha
+            && codec_config.is_avif()
+            && image.yuv_format != codec_config.pixel_format()
+        {
+            return Some(format!(
+                "{category:?} av1C format {:?} does not match the decoded format {:?}",
+                codec_config.pixel_format(),
+                image.yuv_format
+            ));
+        }
+        None
+    }
+
     fn decode_tile(
         &mut self,
         image_index: usize,
         category: Category,
         tile_index: usize,
     ) -> AvifResult<()> {
+        let skippable = self.settings.skip_corrupt_frames
+            && image_index > 0
+            && !self.is_keyframe(u32_from_usize(image_index)?);
         // Split the tiles array into two mutable arrays so that we can validate the
         // properties of tiles with index > 0 with that of the first tile.
         let (tiles_slice1, tiles_slice2) = self.tiles[category.usize()].split_at_mut(tile_index);
         let tile = &mut tiles_slice2[0];
-        let sample = &tile.input.samples[image_index];
+        let sample = tile.input.sample(image_index)?;
         let io = &mut self.io.unwrap_mut();
 
         let codec = &mut self.codecs[tile.codec_index];
@@ -1424,7 +2213,7 @@ impl Decoder {
         let data = sample.data(io, item_data_buffer)?;
         let next_image_result =
             codec.get_next_image(data, sample.spatial_id, &mut tile.image, category);
-        if next_image_result.is_err() {
+        if let Err(err) = &next_image_result {
             if cfg!(feature = "android_mediacodec")
                 && cfg!(feature = "heic")
                 && tile.codec_config.is_heic()
@@ -1434,6 +2223,25 @@ impl Decoder {
                 // ignore it and return the rest of the image.
                 checked_incr!(self.tile_info[category.usize()].decoded_tile_count, 1);
                 return Ok(());
+            } else if skippable {
+                self.diagnostics.push(format!(
+                    "image {image_index}: {category:?} tile {tile_index} could not be decoded: \
+                     {err:?}, replaced the frame by a gray frame"
+                ));
+                self.corrupt_frame = true;
+                checked_incr!(self.tile_info[category.usize()].decoded_tile_count, 1);
+                return Ok(());
+            } else if let AvifError::UnknownError(_) = err {
+                // A failure of the codec for this sample. See AvifError::is_recoverable().
+                self.diagnostics.push(format!(
+                    "image {image_index}: {category:?} tile {tile_index} could not be decoded: \
+                     {err:?}"
+                ));
+                return Err(match category {
+                    Category::Color => AvifError::DecodeColorFailed,
+                    Category::Alpha => AvifError::DecodeAlphaFailed,
+                    Category::Gainmap => AvifError::DecodeGainMapFailed,
+                });
             } else {
                 return next_image_result;
             }
@@ -1441,9 +2249,43 @@ impl Decoder {
 
         checked_incr!(self.tile_info[category.usize()].decoded_tile_count, 1);
 
+        if image_index == 0 {
+            if let Some(problem) =
+                Self::codec_config_mismatch(&tile.codec_config, &tile.image, category)
+            {
+                if self.settings.strictness.codec_config_validated() {
+                    return Err(AvifError::BmffParseFailed(problem));
+                }
+                // The properties of the decoded frame are used from here on.
+                self.diagnostics.push(problem);
+            }
+        }
+
+        if self.skip_output {
+            // The output planes may point to the buffers of the previous frame, which the codec
+            // has released. Grid images own their planes, so they can be kept.
+            if !self.tile_info[category.usize()].is_grid() {
+                let image = match category {
+                    Category::Gainmap => &mut self.gainmap.image,
+                    _ => &mut self.image,
+                };
+                for plane in category.planes() {
+                    let plane = plane.as_usize();
+                    image.planes[plane] = None;
+                    image.row_bytes[plane] = 0;
+                    image.image_owns_planes[plane] = false;
+                }
+            }
+            return Ok(());
+        }
+
         if category == Category::Alpha && tile.image.yuv_range == YuvRange::Limited {
             tile.image.alpha_to_full_range()?;
         }
+        if tile.layer_overridden {
+            tile.width = tile.image.width;
+            tile.height = tile.image.height;
+        }
         tile.image.scale(tile.width, tile.height, category)?;
 
         if self.tile_info[category.usize()].is_grid() {
@@ -1577,7 +2419,23 @@ impl Decoder {
                 }
                 Category::Alpha => {
                     if !self.image.has_same_properties(&tile.image) {
-                        return Err(AvifError::DecodeAlphaFailed);
+                        // NON-STANDARD: Some encoders store the alpha auxiliary image at a lower
+                        // resolution than the color image. Upscale it to the color dimensions.
+                        if self.settings.strictness.alpha_dimensions_validated()
+                            || self.image.depth != tile.image.depth
+                        {
+                            return Err(AvifError::DecodeAlphaFailed);
+                        }
+                        self.diagnostics.push(format!(
+                            "alpha image is {}x{} but color image is {}x{}, scaled alpha to the \
+                             color dimensions",
+                            tile.image.width,
+                            tile.image.height,
+                            self.image.width,
+                            self.image.height
+                        ));
+                        tile.image
+                            .scale(self.image.width, self.image.height, category)?;
                     }
                     self.image
                         .steal_or_copy_planes_from(&tile.image, category)?;
@@ -1595,6 +2453,35 @@ impl Decoder {
         Ok(())
     }
 
+    // Replaces the color and alpha planes of the current image by mid-gray opaque planes. The
+    // planes are owned by the image, so that they do not reference the buffers of a codec that
+    // failed.
+    fn replace_with_gray_frame(&mut self) -> AvifResult<()> {
+        let gray = 1u16 << (self.image.depth - 1);
+        for category in [Category::Color, Category::Alpha] {
+            if !self
+                .settings
+                .image_content_to_decode
+                .categories()
+                .contains(&category)
+                || self.tiles[category.usize()].is_empty()
+            {
+                continue;
+            }
+            for plane in category.planes() {
+                let plane = plane.as_usize();
+                self.image.planes[plane] = None;
+                self.image.row_bytes[plane] = 0;
+                self.image.image_owns_planes[plane] = false;
+            }
+            self.image.allocate_planes_with_default_values(
+                category,
+                [gray, gray, gray, self.image.max_channel()],
+            )?;
+        }
+        Ok(())
+    }
+
     fn decode_tiles(&mut self, image_index: usize) -> AvifResult<()> {
         let mut decoded_something = false;
         for category in self.settings.image_content_to_decode.categories() {
@@ -1613,6 +2500,51 @@ impl Decoder {
         }
     }
 
+    // Returns AvifError::CannotChangeSetting if a setting was changed since it was read. See
+    // Settings.
+    fn validate_settings(&self) -> AvifResult<()> {
+        if self.settings.parse_settings() != self.parse_settings {
+            return Err(AvifError::CannotChangeSetting);
+        }
+        let same_plane_allocator =
+            match (&self.settings.plane_allocator, &self.image.plane_allocator) {
+                (None, None) => true,
+                (Some(allocator1), Some(allocator2)) => Arc::ptr_eq(allocator1, allocator2),
+                _ => false,
+            };
+        if !same_plane_allocator {
+            return Err(AvifError::CannotChangeSetting);
+        }
+        if self.settings.image_content_to_decode.gainmap()
+            && self.gainmap_present
+            && self.tiles[Category::Gainmap.usize()].is_empty()
+        {
+            // The gain map was not requested when parse() was called.
+            return Err(AvifError::CannotChangeSetting);
+        }
+        if self
+            .codec_settings
+            .as_ref()
+            .is_some_and(|codec_settings| *codec_settings != self.settings.codec_settings())
+        {
+            return Err(AvifError::CannotChangeSetting);
+        }
+        Ok(())
+    }
+
+    fn start_new_frame(&mut self) {
+        for category in Category::ALL_USIZE {
+            self.tile_info[category].decoded_tile_count = 0;
+        }
+        self.corrupt_frame = false;
+        if self.full_range_forced {
+            self.image.yuv_range = YuvRange::Limited;
+            self.full_range_forced = false;
+        }
+    }
+
+    // Decoding requires one of the codec features. When none is enabled, parsing and the metadata
+    // getters still work but this returns AvifError::NoCodecAvailable.
     pub fn next_image(&mut self) -> AvifResult<()> {
         if self.io.is_none() {
             return Err(AvifError::IoNotSet);
@@ -1620,21 +2552,164 @@ impl Decoder {
         if !self.parsing_complete() {
             return Err(AvifError::NoContent);
         }
+        self.validate_settings()?;
         if self.is_current_frame_fully_decoded() {
-            for category in Category::ALL_USIZE {
-                self.tile_info[category].decoded_tile_count = 0;
-            }
+            self.start_new_frame();
         }
 
         let next_image_index = checked_add!(self.image_index, 1)?;
         self.create_codecs()?;
         self.prepare_samples(next_image_index as usize)?;
         self.decode_tiles(next_image_index as usize)?;
+        if self.skip_output {
+            self.image_index = next_image_index;
+            return Ok(());
+        }
+        if self.corrupt_frame && self.is_current_frame_fully_decoded() {
+            self.replace_with_gray_frame()?;
+        }
+        if self.settings.force_full_range_output
+            && self.image.yuv_range == YuvRange::Limited
+            && self.is_current_frame_fully_decoded()
+        {
+            self.image.to_full_range()?;
+            self.full_range_forced = true;
+        }
+        #[cfg(feature = "conversion")]
+        if self.settings.force_8bit_output && self.is_current_frame_fully_decoded() {
+            self.image
+                .reduce_to_8bit(self.settings.force_8bit_output_dither)?;
+        }
         self.image_index = next_image_index;
         self.image_timing = self.nth_image_timing(self.image_index as u32)?;
         Ok(())
     }
 
+    // Gives up on the image after the current one, typically after next_image() failed to decode
+    // it with an error for which AvifError::is_recoverable() is true, so that the following call
+    // to next_image() decodes the image after it. The skipped image yields no image: image_index()
+    // and image_timing() refer to it but decoder.image() must not be used until the next image is
+    // decoded. The codecs are flushed so that decoding restarts cleanly at the next keyframe. The
+    // images between the skipped image and that keyframe may depend on the skipped image and fail
+    // to decode as well, in which case they can be skipped the same way.
+    pub fn skip_current_image(&mut self) -> AvifResult<()> {
+        if !self.parsing_complete() {
+            return Err(AvifError::NoContent);
+        }
+        let skipped_image_index = checked_add!(self.image_index, 1)?;
+        if skipped_image_index >= i32_from_u32(self.image_count)? {
+            return Err(AvifError::NoImagesRemaining);
+        }
+        self.start_new_frame();
+        self.flush_codecs()?;
+        self.image_index = skipped_image_index;
+        self.image_timing = self.nth_image_timing(self.image_index as u32)?;
+        Ok(())
+    }
+
+    // Decodes the next image like next_image() and calls on_rows(row_count) every time more rows
+    // of decoder.image can be accessed (see decoded_row_count()), the last time with the full
+    // image height. If the IO does not have enough data yet, the rows decoded so far are reported
+    // and AvifError::WaitingOnIo is returned. Calling this function again once the IO has more
+    // data resumes decoding and only reports the rows that were not reported yet. allow_incremental
+    // must be set to true for partial rows to be reported.
+    pub fn next_image_incremental(&mut self, mut on_rows: impl FnMut(u32)) -> AvifResult<()> {
+        let res = self.next_image();
+        let row_count = self.decoded_row_count();
+        if row_count > self.reported_row_count {
+            on_rows(row_count);
+        }
+        self.reported_row_count = match res {
+            Err(AvifError::WaitingOnIo) => std::cmp::max(self.reported_row_count, row_count),
+            _ => 0,
+        };
+        res
+    }
+
+    // Decodes the next image into dst. The tiles of grid and overlay images are composited
+    // directly into the planes of dst, which are reused when they have the size of the decoded
+    // image, and those planes are then no longer part of decoder.image(). The other planes are
+    // copied from the frames returned by the codec into the planes of dst, which are also reused
+    // when possible. So decoding a sequence of frames into the same dst does not allocate new
+    // planes for every frame.
+    pub fn next_image_into(&mut self, dst: &mut Image) -> AvifResult<()> {
+        let starting_frame = self.is_current_frame_fully_decoded()
+            || self
+                .tile_info
+                .iter()
+                .all(|tile_info| tile_info.decoded_tile_count == 0);
+        if starting_frame {
+            for category in [Category::Color, Category::Alpha] {
+                let tile_info = &self.tile_info[category.usize()];
+                let owns_planes = category
+                    .planes()
+                    .iter()
+                    .any(|plane| self.image.image_owns_planes[plane.as_usize()]);
+                if (tile_info.is_grid() || tile_info.is_overlay()) && !owns_planes {
+                    // Lend the planes of dst to the compositing of the tiles.
+                    dst.move_owned_planes_to(&mut self.image, category);
+                }
+            }
+        }
+        self.next_image()?;
+        let mut composited = Image::default();
+        for category in [Category::Color, Category::Alpha] {
+            self.image.move_owned_planes_to(&mut composited, category);
+        }
+        dst.copy_from(&self.image)?;
+        for category in [Category::Color, Category::Alpha] {
+            composited.move_owned_planes_to(dst, category);
+        }
+        Ok(())
+    }
+
+    // Returns the ids of the image items that are not a thumbnail, an auxiliary image (such as
+    // alpha) or an input of a derived image, in 'iinf' order. The primary item is always
+    // included, even if it is an input of a 'tmap' item.
+    fn top_level_item_ids(&mut self) -> AvifResult<Vec<u32>> {
+        if self.io.is_none() {
+            return Err(AvifError::IoNotSet);
+        }
+        let avif_boxes = mp4box::parse(self.io.unwrap_mut())?;
+        let items = construct_items(&avif_boxes.meta)?;
+        Ok(avif_boxes
+            .meta
+            .iinf
+            .iter()
+            .map(|info| info.item_id)
+            .filter(|item_id| {
+                items.get(item_id).is_some_and(|item| {
+                    !item.should_skip()
+                        && item.aux_for_id == 0
+                        && (item.dimg_for_id == 0 || item.id == avif_boxes.meta.primary_item_id)
+                })
+            })
+            .collect())
+    }
+
+    // Decodes the first image of every top-level image item of the file (see
+    // top_level_item_ids()), for files that store a collection of images such as a burst.
+    // Grid and overlay items are decoded as a whole. parse() has to be called again before
+    // using the other decoding methods.
+    pub fn image_collection(&mut self) -> AvifResult<Vec<Image>> {
+        let item_ids = self.top_level_item_ids()?;
+        let mut images = create_vec_exact(item_ids.len())?;
+        let mut res = Ok(());
+        for item_id in item_ids {
+            self.collection_item_id = Some(item_id);
+            self.parse_state = ParseState::None;
+            let mut image = Image::default();
+            res = self.parse().and_then(|_| self.next_image_into(&mut image));
+            if res.is_err() {
+                break;
+            }
+            images.push(image);
+        }
+        self.collection_item_id = None;
+        self.parse_state = ParseState::None;
+        res.map(|_| images)
+    }
+
     fn is_current_frame_fully_decoded(&self) -> bool {
         if !self.parsing_complete() {
             return false;
@@ -1648,9 +2723,34 @@ impl Decoder {
     }
 
     pub fn nth_image(&mut self, index: u32) -> AvifResult<()> {
+        self.decode_nth_image(index, false)
+    }
+
+    // Decodes the image that should be displayed at pts (in seconds) according to the timings of
+    // the color track, as nth_image() would. The images between the nearest keyframe and the
+    // requested image are decoded but not copied into decoder.image. Timestamps outside of the
+    // sequence are clamped to its first or last image.
+    pub fn next_image_at(&mut self, pts: f64) -> AvifResult<()> {
+        if !self.parsing_complete() {
+            return Err(AvifError::NoContent);
+        }
+        let index = match self.color_track_id {
+            Some(color_track_id) => self
+                .tracks
+                .iter()
+                .find(|x| x.id == color_track_id)
+                .ok_or(AvifError::NoContent)?
+                .image_index_at(pts, self.image_count)?,
+            None => 0,
+        };
+        self.decode_nth_image(index, true)
+    }
+
+    fn decode_nth_image(&mut self, index: u32, skip_intermediate_output: bool) -> AvifResult<()> {
         if !self.parsing_complete() {
             return Err(AvifError::NoContent);
         }
+        self.validate_settings()?;
         if index >= self.image_count {
             return Err(AvifError::NoImagesRemaining);
         }
@@ -1666,11 +2766,18 @@ impl Decoder {
         if nearest_keyframe > checked_add!(self.image_index, 1)?
             || requested_index <= self.image_index
         {
-            // Start decoding from the nearest keyframe.
+            // Start decoding from the nearest keyframe. The codecs may still hold references to
+            // frames (or, for layered items, to higher layers) decoded after that keyframe, so
+            // flush them first.
+            self.flush_codecs()?;
             self.image_index = nearest_keyframe - 1;
         }
         loop {
-            self.next_image()?;
+            self.skip_output =
+                skip_intermediate_output && checked_add!(self.image_index, 1)? < requested_index;
+            let res = self.next_image();
+            self.skip_output = false;
+            res?;
             if requested_index == self.image_index {
                 break;
             }
@@ -1678,6 +2785,10 @@ impl Decoder {
         Ok(())
     }
 
+    // The decoded image has the pixel format of the coded image and its chroma planes are never
+    // upsampled: with Yuv420 and Yuv422 the U and V planes are half as wide (and half as tall for
+    // Yuv420) as the Y plane, rounded up. Conversion to RGB (see rgb::Image) upsamples the chroma
+    // as needed.
     pub fn image(&self) -> Option<&Image> {
         if self.parsing_complete() {
             Some(&self.image)
@@ -1686,6 +2797,25 @@ impl Decoder {
         }
     }
 
+    // Returns Image::is_effectively_monochrome() for the current image. The result is computed on
+    // the first call and reused until another image is decoded.
+    pub fn is_effectively_monochrome(&mut self, tolerance: u16) -> AvifResult<bool> {
+        if !self.parsing_complete() || self.image_index < 0 {
+            return Err(AvifError::NoContent);
+        }
+        if let Some((image_index, cached_tolerance, result)) = self.effectively_monochrome {
+            if image_index == self.image_index && cached_tolerance == tolerance {
+                return Ok(result);
+            }
+        }
+        let result = self.image.is_effectively_monochrome(tolerance);
+        // Partially decoded frames may still change, so do not cache their result.
+        if self.is_current_frame_fully_decoded() {
+            self.effectively_monochrome = Some((self.image_index, tolerance, result));
+        }
+        Ok(result)
+    }
+
     pub fn nth_image_timing(&self, n: u32) -> AvifResult<ImageTiming> {
         if !self.parsing_complete() {
             return Err(AvifError::NoContent);
@@ -1720,7 +2850,8 @@ impl Decoder {
     // returned AvifResult::Ok. Returns 0 in all other cases.
     pub fn decoded_row_count(&self) -> u32 {
         let mut min_row_count = self.image.height;
-        for category in Category::ALL_USIZE {
+        for category in self.settings.image_content_to_decode.categories() {
+            let category = category.usize();
             if self.tiles[category].is_empty() {
                 continue;
             }
@@ -1766,7 +2897,7 @@ impl Decoder {
         // All the tiles for the requested index must be a keyframe.
         for category in Category::ALL_USIZE {
             for tile in &self.tiles[category] {
-                if index >= tile.input.samples.len() || !tile.input.samples[index].sync {
+                if !tile.input.sample(index).is_ok_and(|sample| sample.sync) {
                     return false;
                 }
             }
@@ -1798,10 +2929,7 @@ impl Decoder {
         for current_index in start_index..=end_index {
             for category in Category::ALL_USIZE {
                 for tile in &self.tiles[category] {
-                    if current_index >= tile.input.samples.len() {
-                        return Err(AvifError::NoImagesRemaining);
-                    }
-                    let sample = &tile.input.samples[current_index];
+                    let sample = tile.input.sample(current_index)?;
                     let sample_extent = if sample.item_id != 0 {
                         let item = self.items.get(&sample.item_id).unwrap();
                         item.max_extent(sample)?
@@ -1818,9 +2946,136 @@ impl Decoder {
         Ok(extent)
     }
 
+    // Reads the payloads of all the tiles of all the frames without decoding them and reports, for
+    // each item and track sample, whether the bytes declared by the container are available.
+    // This distinguishes truncated files from corrupted bitstreams. Items referenced by several
+    // samples are reported once.
+    pub fn verify_payload_integrity(&mut self) -> AvifResult<IntegrityReport> {
+        if !self.parsing_complete() {
+            return Err(AvifError::NoContent);
+        }
+        fn available_size(io: &mut GenericIO, offset: u64, size: usize) -> AvifResult<usize> {
+            let size_hint = io.size_hint();
+            match io.read(offset, size) {
+                Ok(data) => Ok(data.len()),
+                // The offset is beyond the end of the data.
+                Err(AvifError::IoError) if offset >= size_hint => Ok(0),
+                Err(err) => Err(err),
+            }
+        }
+        let mdat_ranges = mp4box::parse_mdat_ranges(self.io.unwrap_mut())?;
+        let mut report = IntegrityReport::default();
+        let mut seen_item_ids: Vec<u32> = Vec::new();
+        for category in Category::ALL {
+            for tile in &self.tiles[category.usize()] {
+                for (sample_index, sample) in tile.input.samples.iter().enumerate() {
+                    let io = self.io.unwrap_mut();
+                    if sample.item_id == 0 {
+                        let read_size = available_size(io, sample.offset, sample.size)?;
+                        let sample_end = checked_add!(sample.offset, sample.size as u64)?;
+                        let outside_mdat = !mdat_ranges.iter().any(|mdat_range| {
+                            mdat_range.start <= sample.offset && sample_end <= mdat_range.end
+                        });
+                        report.payloads.push(PayloadIntegrity {
+                            category,
+                            sample_index,
+                            declared_size: sample.size,
+                            extents_size: sample.size,
+                            read_size,
+                            short_read: read_size < sample.size,
+                            outside_mdat,
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+                    if seen_item_ids.contains(&sample.item_id) {
+                        continue;
+                    }
+                    seen_item_ids.push(sample.item_id);
+                    let item = self
+                        .items
+                        .get(&sample.item_id)
+                        .ok_or(AvifError::MissingImageItem)?;
+                    let mut payload = PayloadIntegrity {
+                        category,
+                        item_id: item.id,
+                        declared_size: item.size,
+                        ..Default::default()
+                    };
+                    for extent in &item.extents {
+                        let read_size = if item.idat.is_empty() {
+                            available_size(io, extent.offset, extent.size)?
+                        } else {
+                            let offset = usize_from_u64(extent.offset)?;
+                            min(item.idat.len().saturating_sub(offset), extent.size)
+                        };
+                        checked_incr!(payload.extents_size, extent.size);
+                        checked_incr!(payload.read_size, read_size);
+                        payload.short_read |= read_size < extent.size;
+                    }
+                    report.payloads.push(payload);
+                }
+            }
+        }
+        Ok(report)
+    }
+
     pub fn peek_compatible_file_type(data: &[u8]) -> bool {
         mp4box::peek_compatible_file_type(data).unwrap_or(false)
     }
+
+    // Returns the width and height of the primary image if the beginning of a file is enough to
+    // determine them. See peek_sequence_header_info().
+    pub fn peek_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+        let info = Self::peek_sequence_header_info(data).ok()?;
+        Some((info.width?, info.height?))
+    }
+
+    // Extracts the dimensions, depth and format of the primary image from the beginning of a
+    // file, without parsing it entirely. They are read from the primary item properties if the
+    // MetaBox is fully contained in data. Otherwise, if the MediaDataBox comes first (as in some
+    // progressive layouts), they are read from the first AV1 sequence header in its payload, which
+    // is assumed to belong to the primary item. The fields that could not be found are None.
+    pub fn peek_sequence_header_info(data: &[u8]) -> AvifResult<SequenceHeaderInfo> {
+        match mp4box::peek_meta_or_mdat(data)? {
+            Some(PeekedBox::Meta(meta)) => {
+                let items = construct_items(&meta)?;
+                let primary_item = items
+                    .get(&meta.primary_item_id)
+                    .ok_or(AvifError::MissingImageItem)?;
+                let ispe = find_property!(primary_item.properties, ImageSpatialExtents);
+                // Derived items such as grids have no codec configuration of their own, so use
+                // the one of their first input.
+                let codec_config = primary_item.codec_config().or_else(|| {
+                    items
+                        .values()
+                        .filter(|item| item.dimg_for_id == primary_item.id)
+                        .min_by_key(|item| item.dimg_index)
+                        .and_then(|item| item.codec_config())
+                });
+                Ok(SequenceHeaderInfo {
+                    source: SequenceHeaderInfoSource::PrimaryItem,
+                    width: ispe.as_ref().map(|ispe| ispe.width),
+                    height: ispe.as_ref().map(|ispe| ispe.height),
+                    depth: codec_config.map(|config| config.depth()),
+                    yuv_format: codec_config.map(|config| config.pixel_format()),
+                })
+            }
+            Some(PeekedBox::MediaData(payload)) => {
+                match Av1SequenceHeader::parse_from_obus(payload) {
+                    Ok(sequence_header) => Ok(SequenceHeaderInfo {
+                        source: SequenceHeaderInfoSource::SequenceHeader,
+                        width: Some(sequence_header.max_width),
+                        height: Some(sequence_header.max_height),
+                        depth: Some(sequence_header.bit_depth),
+                        yuv_format: Some(sequence_header.yuv_format),
+                    }),
+                    Err(_) => Ok(SequenceHeaderInfo::default()),
+                }
+            }
+            None => Ok(SequenceHeaderInfo::default()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1850,4 +3105,29 @@ mod tests {
         assert_eq!(e1.offset, expected_offset);
         assert_eq!(e1.size, expected_size);
     }
+
+    #[test_case(1, None, None, 1, 1)]
+    #[test_case(2, None, None, 2, 1)]
+    #[test_case(8, None, None, 8, 1)]
+    #[test_case(64, None, None, 64, 1)]
+    #[test_case(4, None, Some(1), 1, 1)]
+    #[test_case(4, Some(1), None, 4, 1)]
+    #[test_case(1, Some(3), Some(4), 4, 3)]
+    fn dav1d_threads(
+        max_threads: u32,
+        dav1d_frame_threads: Option<u32>,
+        dav1d_tile_threads: Option<u32>,
+        expected_tile_threads: u32,
+        expected_frame_threads: u32,
+    ) {
+        let settings = Settings {
+            max_threads,
+            dav1d_frame_threads,
+            dav1d_tile_threads,
+            ..Default::default()
+        }
+        .codec_settings();
+        assert_eq!(settings.dav1d_tile_threads(), expected_tile_threads);
+        assert_eq!(settings.dav1d_frame_threads(), expected_frame_threads);
+    }
 }
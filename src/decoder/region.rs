@@ -0,0 +1,142 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::*;
+
+// Geometries of a region item. See Section 6.10 of ISO/IEC 23008-12.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegionGeometry {
+    Point {
+        x: i32,
+        y: i32,
+    },
+    Rectangle {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+    Ellipse {
+        x: i32,
+        y: i32,
+        radius_x: u32,
+        radius_y: u32,
+    },
+    Polygon(Vec<(i32, i32)>),
+    Polyline(Vec<(i32, i32)>),
+    // The mask itself is stored in a separate image item referenced with a 'mask' reference.
+    ReferencedMask {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+    // Uncompressed mask with one bit per pixel. Scaling does not apply to data, whose dimensions
+    // are given by data_width and data_height.
+    InlineMask {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        data_width: u32,
+        data_height: u32,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegionAnnotation {
+    pub item_id: u32,
+    pub reference_width: u32,
+    pub reference_height: u32,
+    pub geometries: Vec<RegionGeometry>,
+}
+
+fn scale_i32(value: i32, to: u32, from: u32) -> AvifResult<i32> {
+    i32::try_from(value as i64 * to as i64 / from as i64)
+        .or(Err(AvifError::BmffParseFailed("".into())))
+}
+
+fn scale_u32(value: u32, to: u32, from: u32) -> AvifResult<u32> {
+    u32::try_from(value as u64 * to as u64 / from as u64)
+        .or(Err(AvifError::BmffParseFailed("".into())))
+}
+
+impl RegionAnnotation {
+    // Converts all the coordinates from the reference space of the region item into the space
+    // of an image of the given dimensions.
+    pub(crate) fn scale(&mut self, width: u32, height: u32) -> AvifResult<()> {
+        if self.reference_width == 0 || self.reference_height == 0 {
+            return Err(AvifError::BmffParseFailed(
+                "invalid region item reference dimensions".into(),
+            ));
+        }
+        let (rw, rh) = (self.reference_width, self.reference_height);
+        let sx = |x: i32| scale_i32(x, width, rw);
+        let sy = |y: i32| scale_i32(y, height, rh);
+        let sw = |w: u32| scale_u32(w, width, rw);
+        let sh = |h: u32| scale_u32(h, height, rh);
+        for geometry in &mut self.geometries {
+            match geometry {
+                RegionGeometry::Point { x, y } => {
+                    *x = sx(*x)?;
+                    *y = sy(*y)?;
+                }
+                RegionGeometry::Rectangle {
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+                | RegionGeometry::ReferencedMask {
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+                | RegionGeometry::InlineMask {
+                    x,
+                    y,
+                    width,
+                    height,
+                    ..
+                } => {
+                    *x = sx(*x)?;
+                    *y = sy(*y)?;
+                    *width = sw(*width)?;
+                    *height = sh(*height)?;
+                }
+                RegionGeometry::Ellipse {
+                    x,
+                    y,
+                    radius_x,
+                    radius_y,
+                } => {
+                    *x = sx(*x)?;
+                    *y = sy(*y)?;
+                    *radius_x = sw(*radius_x)?;
+                    *radius_y = sh(*radius_y)?;
+                }
+                RegionGeometry::Polygon(points) | RegionGeometry::Polyline(points) => {
+                    for point in points {
+                        *point = (sx(point.0)?, sy(point.1)?);
+                    }
+                }
+            }
+        }
+        self.reference_width = width;
+        self.reference_height = height;
+        Ok(())
+    }
+}
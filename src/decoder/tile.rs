@@ -66,6 +66,16 @@ pub struct DecodeInput {
     pub samples: Vec<DecodeSample>,
     pub all_layers: bool,
     pub category: Category,
+    // True for the cells of a progressive grid that are not progressive themselves (for example
+    // because their layer is selected with lsel). Their only sample is used for all the frames.
+    pub pinned: bool,
+}
+
+impl DecodeInput {
+    pub(crate) fn sample(&self, image_index: usize) -> AvifResult<&DecodeSample> {
+        let index = if self.pinned { 0 } else { image_index };
+        self.samples.get(index).ok_or(AvifError::NoImagesRemaining)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -114,7 +124,13 @@ impl TileInfo {
         if self.decoded_tile_count == 0 {
             return 0;
         }
-        if self.decoded_tile_count == self.tile_count || !self.is_grid() {
+        if self.decoded_tile_count == self.tile_count {
+            return image_height;
+        }
+        if self.is_overlay() {
+            return self.overlay_decoded_row_count(image_height, tile_height);
+        }
+        if !self.is_grid() {
             return image_height;
         }
         std::cmp::min(
@@ -123,6 +139,28 @@ impl TileInfo {
         )
     }
 
+    // Overlay cells are composited onto the canvas in z-order, so a row is final once none of
+    // the cells that are not decoded yet intersect it. All the rows above the topmost remaining
+    // cell are final.
+    fn overlay_decoded_row_count(&self, image_height: u32, tile_height: u32) -> u32 {
+        let mut row_count = image_height;
+        for vertical_offset in self
+            .overlay
+            .vertical_offsets
+            .iter()
+            .skip(self.decoded_tile_count as usize)
+        {
+            let vertical_offset = *vertical_offset as i64;
+            if vertical_offset + tile_height as i64 <= 0 {
+                // The cell is entirely above the canvas.
+                continue;
+            }
+            let first_row = std::cmp::max(vertical_offset, 0) as u64;
+            row_count = std::cmp::min(row_count as u64, first_row) as u32;
+        }
+        row_count
+    }
+
     pub(crate) fn is_fully_decoded(&self) -> bool {
         self.tile_count == self.decoded_tile_count
     }
@@ -137,12 +175,16 @@ pub struct Tile {
     pub input: DecodeInput,
     pub codec_index: usize,
     pub codec_config: CodecConfiguration,
+    // True if the layer requested by the 'lsel' property was overridden. In that case, the
+    // dimensions from the 'ispe' property do not apply to the decoded layer.
+    pub layer_overridden: bool,
 }
 
 impl Tile {
     pub(crate) fn create_from_item(
         item: &mut Item,
         allow_progressive: bool,
+        preferred_layer: Option<u16>,
         image_count_limit: u32,
         size_hint: u64,
     ) -> AvifResult<Tile> {
@@ -193,8 +235,9 @@ impl Tile {
         let has_lsel;
         match item.lsel() {
             Some(x) => {
-                lsel = *x;
+                lsel = preferred_layer.unwrap_or(*x);
                 has_lsel = true;
+                tile.layer_overridden = lsel != *x;
             }
             None => {
                 lsel = 0;
@@ -212,6 +255,11 @@ impl Tile {
             tile.input.all_layers = true;
             let mut sample_size: usize = 0;
             let layer_id = usize_from_u16(lsel)?;
+            if layer_id >= MAX_AV1_LAYER_COUNT {
+                return Err(AvifError::InvalidImageGrid(
+                    "lsel layer index is out of range.".into(),
+                ));
+            }
             if layer_count > 0 {
                 // Optimization: If we're selecting a layer that doesn't require the entire image's
                 // payload (hinted via the a1lx box).
@@ -369,3 +417,70 @@ impl Tile {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay_tile_info(vertical_offsets: Vec<i32>, decoded_tile_count: u32) -> TileInfo {
+        TileInfo {
+            tile_count: vertical_offsets.len() as u32,
+            decoded_tile_count,
+            overlay: Overlay {
+                width: 200,
+                height: 180,
+                horizontal_offsets: vec![0; vertical_offsets.len()],
+                vertical_offsets,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn overlay_decoded_row_count() {
+        let offsets = vec![20, 60, 100];
+        let row_counts: Vec<u32> = (0..=3)
+            .map(|n| overlay_tile_info(offsets.clone(), n).decoded_row_count(180, 60))
+            .collect();
+        assert_eq!(row_counts, vec![0, 60, 100, 180]);
+
+        // The cells are not sorted by vertical offset. The last cell covers the top rows.
+        let offsets = vec![100, 60, 0];
+        let row_counts: Vec<u32> = (0..=3)
+            .map(|n| overlay_tile_info(offsets.clone(), n).decoded_row_count(180, 60))
+            .collect();
+        assert_eq!(row_counts, vec![0, 0, 0, 180]);
+
+        // Cells that are entirely above the canvas do not hold back any row.
+        let offsets = vec![0, -60, 100];
+        let row_counts: Vec<u32> = (0..=3)
+            .map(|n| overlay_tile_info(offsets.clone(), n).decoded_row_count(180, 60))
+            .collect();
+        assert_eq!(row_counts, vec![0, 100, 100, 180]);
+    }
+
+    #[test]
+    fn pinned_sample() {
+        let sample = |size| DecodeSample {
+            size,
+            ..Default::default()
+        };
+        let mut input = DecodeInput {
+            samples: vec![sample(10)],
+            ..Default::default()
+        };
+        assert_eq!(input.sample(0).unwrap().size, 10);
+        assert!(matches!(input.sample(1), Err(AvifError::NoImagesRemaining)));
+        // A pinned cell of a progressive grid uses its only sample for all the frames.
+        input.pinned = true;
+        assert_eq!(input.sample(2).unwrap().size, 10);
+
+        let input = DecodeInput {
+            samples: vec![sample(10), sample(20)],
+            ..Default::default()
+        };
+        assert_eq!(input.sample(1).unwrap().size, 20);
+        assert!(matches!(input.sample(2), Err(AvifError::NoImagesRemaining)));
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::decoder::GenericIO;
 use crate::internal_utils::*;
 use crate::parser::mp4box::ItemProperty;
 use crate::parser::mp4box::MetaBox;
@@ -30,6 +31,15 @@ impl Default for RepetitionCount {
     }
 }
 
+// Creation and modification times of a movie (mvhd) or of a track (tkhd), in seconds since
+// 1970-01-01 UTC. The file stores them in seconds since 1904-01-01 UTC, so a time that was left
+// unset (0) becomes negative.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MediaTimes {
+    pub creation_time: i64,
+    pub modification_time: i64,
+}
+
 #[derive(Debug, Default)]
 pub struct Track {
     pub id: u32,
@@ -45,6 +55,16 @@ pub struct Track {
     pub sample_table: Option<SampleTable>,
     pub elst_seen: bool,
     pub meta: Option<MetaBox>,
+    pub handler_type: String,
+    pub times: MediaTimes,
+}
+
+// A sample of a timed metadata track. The payload is passed through as is.
+#[derive(Debug, Default)]
+pub struct MetadataSample {
+    pub track_id: u32,
+    pub timing: ImageTiming,
+    pub data: Vec<u8>,
 }
 
 impl Track {
@@ -66,10 +86,64 @@ impl Track {
         self.has_av1_samples() && self.aux_for_id.is_none()
     }
 
+    pub(crate) fn is_metadata(&self) -> bool {
+        // Section 12.3.1 of ISO/IEC 14496-12: timed metadata tracks use the 'meta' handler.
+        self.handler_type == "meta" && self.sample_table.is_some()
+    }
+
     pub(crate) fn get_properties(&self) -> Option<&Vec<ItemProperty>> {
         self.sample_table.as_ref()?.get_properties()
     }
 
+    pub(crate) fn read_metadata_samples(
+        &self,
+        io: &mut GenericIO,
+        mut sample_count_limit: u32,
+    ) -> AvifResult<Vec<MetadataSample>> {
+        let sample_table = self.sample_table.as_ref().ok_or(AvifError::NoContent)?;
+        let mut samples: Vec<MetadataSample> = Vec::new();
+        let mut pts_in_timescales: u64 = 0;
+        for (chunk_index, chunk_offset) in sample_table.chunk_offsets.iter().enumerate() {
+            let sample_count = sample_table.get_sample_count_of_chunk(chunk_index as u32);
+            if sample_count == 0 {
+                return Err(AvifError::BmffParseFailed(
+                    "chunk with 0 samples found".into(),
+                ));
+            }
+            if sample_count_limit != 0 {
+                if sample_count > sample_count_limit {
+                    return Err(AvifError::BmffParseFailed(
+                        "exceeded image_count_limit".into(),
+                    ));
+                }
+                sample_count_limit -= sample_count;
+            }
+            let mut sample_offset = *chunk_offset;
+            for _ in 0..sample_count {
+                let sample_size = sample_table.sample_size(samples.len())?;
+                let duration_in_timescales = sample_table.image_delta(samples.len())? as u64;
+                let mut timing = ImageTiming {
+                    timescale: self.media_timescale as u64,
+                    pts_in_timescales,
+                    duration_in_timescales,
+                    ..ImageTiming::default()
+                };
+                if timing.timescale > 0 {
+                    timing.pts = pts_in_timescales as f64 / timing.timescale as f64;
+                    timing.duration = duration_in_timescales as f64 / timing.timescale as f64;
+                }
+                samples.push(MetadataSample {
+                    track_id: self.id,
+                    timing,
+                    data: io.read_exact(sample_offset, sample_size)?.to_vec(),
+                });
+                checked_incr!(sample_offset, sample_size as u64);
+                checked_incr!(pts_in_timescales, duration_in_timescales);
+            }
+        }
+        Ok(samples)
+    }
+
     pub(crate) fn repetition_count(&self) -> AvifResult<RepetitionCount> {
         if !self.elst_seen {
             return Ok(RepetitionCount::Unknown);
@@ -130,6 +204,27 @@ impl Track {
         }
         Ok(image_timing)
     }
+
+    // Returns the index of the image that is displayed at pts (in seconds), among the first
+    // image_count images. Timestamps before the first image map to the first image and timestamps
+    // after the last image map to the last image.
+    pub(crate) fn image_index_at(&self, pts: f64, image_count: u32) -> AvifResult<u32> {
+        let sample_table = self.sample_table.as_ref().ok_or(AvifError::NoContent)?;
+        if self.media_timescale == 0 {
+            return Ok(0);
+        }
+        let mut next_pts_in_timescales: u64 = 0;
+        for index in 0..image_count.saturating_sub(1) {
+            checked_incr!(
+                next_pts_in_timescales,
+                sample_table.image_delta(index as usize)? as u64
+            );
+            if next_pts_in_timescales as f64 / self.media_timescale as f64 > pts {
+                return Ok(index);
+            }
+        }
+        Ok(image_count.saturating_sub(1))
+    }
 }
 
 #[derive(Debug)]
@@ -226,16 +321,43 @@ impl SampleTable {
         })
     }
 
+    // Returns the duration of the sample at index. The samples that are not covered by the stts
+    // box use the sample_delta of its last entry, or 1 if it has no entry. A sample_delta of 0 is
+    // returned as is.
     pub(crate) fn image_delta(&self, index: usize) -> AvifResult<u32> {
-        let mut max_index: u32 = 0;
+        let mut max_index: u64 = 0;
         for (i, time_to_sample) in self.time_to_sample.iter().enumerate() {
-            checked_incr!(max_index, time_to_sample.sample_count);
-            if index < max_index as usize || i == self.time_to_sample.len() - 1 {
+            max_index += time_to_sample.sample_count as u64;
+            if (index as u64) < max_index || i == self.time_to_sample.len() - 1 {
                 return Ok(time_to_sample.sample_delta);
             }
         }
         Ok(1)
     }
+
+    // Returns a description of each problem of the stts box for a track of sample_count samples.
+    // None of them prevents computing the timing of the samples (see image_delta()).
+    pub(crate) fn time_to_sample_problems(&self, sample_count: usize) -> Vec<String> {
+        let mut problems = Vec::new();
+        let covered_sample_count: u64 = self
+            .time_to_sample
+            .iter()
+            .map(|x| x.sample_count as u64)
+            .sum();
+        if covered_sample_count != sample_count as u64 {
+            problems.push(format!(
+                "stts covers {covered_sample_count} samples but the track has {sample_count} samples"
+            ));
+        }
+        if self
+            .time_to_sample
+            .iter()
+            .any(|x| x.sample_count != 0 && x.sample_delta == 0)
+        {
+            problems.push("stts has samples with a sample_delta of 0".into());
+        }
+        problems
+    }
 }
 
 /// cbindgen:rename-all=CamelCase
@@ -248,3 +370,98 @@ pub struct ImageTiming {
     pub duration: f64,
     pub duration_in_timescales: u64,
 }
+
+impl ImageTiming {
+    // Presentation timestamp in seconds, computed from pts_in_timescales. Returns 0 if the
+    // timescale is unknown.
+    pub fn pts_seconds(&self) -> f64 {
+        Self::seconds(self.pts_in_timescales, self.timescale)
+    }
+
+    // Duration in seconds, computed from duration_in_timescales. Returns 0 if the timescale is
+    // unknown.
+    pub fn duration_seconds(&self) -> f64 {
+        Self::seconds(self.duration_in_timescales, self.timescale)
+    }
+
+    fn seconds(value_in_timescales: u64, timescale: u64) -> f64 {
+        if timescale == 0 {
+            0.0
+        } else {
+            value_in_timescales as f64 / timescale as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case(30, 45, 3, 1.5, 0.1)]
+    #[test_case(1000, 2500, 40, 2.5, 0.04)]
+    #[test_case(90000, 0, 3003, 0.0, 3003.0 / 90000.0)]
+    #[test_case(0, 10, 10, 0.0, 0.0)]
+    fn image_timing_seconds(
+        timescale: u64,
+        pts_in_timescales: u64,
+        duration_in_timescales: u64,
+        expected_pts: f64,
+        expected_duration: f64,
+    ) {
+        let timing = ImageTiming {
+            timescale,
+            pts_in_timescales,
+            duration_in_timescales,
+            ..Default::default()
+        };
+        assert_eq!(timing.pts_seconds(), expected_pts);
+        assert_eq!(timing.duration_seconds(), expected_duration);
+    }
+
+    fn track_with_stts(time_to_sample: &[(u32, u32)]) -> Track {
+        Track {
+            media_timescale: 10,
+            sample_table: Some(SampleTable {
+                time_to_sample: time_to_sample
+                    .iter()
+                    .map(|(sample_count, sample_delta)| TimeToSample {
+                        sample_count: *sample_count,
+                        sample_delta: *sample_delta,
+                    })
+                    .collect(),
+                ..SampleTable::default()
+            }),
+            ..Track::default()
+        }
+    }
+
+    // Each case lists the stts entries and the expected (pts_in_timescales,
+    // duration_in_timescales) of the first 5 samples.
+    #[test_case(&[(5, 2)], [(0, 2), (2, 2), (4, 2), (6, 2), (8, 2)], 0 ; "matching stts")]
+    #[test_case(&[(2, 3), (1, 4)], [(0, 3), (3, 3), (6, 4), (10, 4), (14, 4)], 1 ; "short stts")]
+    #[test_case(&[], [(0, 1), (1, 1), (2, 1), (3, 1), (4, 1)], 1 ; "missing stts")]
+    #[test_case(&[(2, 0), (3, 5)], [(0, 0), (0, 0), (0, 5), (5, 5), (10, 5)], 1 ; "zero deltas")]
+    #[test_case(&[(2, 1), (0, 7), (u32::MAX, 2)], [(0, 1), (1, 1), (2, 2), (4, 2), (6, 2)], 1 ; "long stts")]
+    fn image_timing_with_incomplete_stts(
+        time_to_sample: &[(u32, u32)],
+        expected: [(u64, u64); 5],
+        problem_count: usize,
+    ) {
+        let track = track_with_stts(time_to_sample);
+        for (index, (pts_in_timescales, duration_in_timescales)) in expected.iter().enumerate() {
+            let timing = track.image_timing(index as u32).unwrap();
+            assert_eq!(timing.pts_in_timescales, *pts_in_timescales);
+            assert_eq!(timing.duration_in_timescales, *duration_in_timescales);
+            assert_eq!(timing.pts, *pts_in_timescales as f64 / 10.0);
+            assert_eq!(timing.duration, *duration_in_timescales as f64 / 10.0);
+        }
+        let problems = track
+            .sample_table
+            .as_ref()
+            .unwrap()
+            .time_to_sample_problems(5);
+        assert_eq!(problems.len(), problem_count, "{problems:?}");
+    }
+}
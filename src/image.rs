@@ -21,8 +21,12 @@ use crate::internal_utils::*;
 use crate::parser::mp4box::*;
 use crate::reformat::coeffs::*;
 use crate::utils::clap::CleanAperture;
+use crate::utils::clap::CropRect;
 use crate::*;
 
+use std::alloc::Layout;
+use std::sync::Arc;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Plane {
     Y = 0,
@@ -68,6 +72,25 @@ pub enum YuvRange {
     Full = 1,
 }
 
+/// Provides the memory of the planes allocated by the library, for example to place them in a
+/// DMA-capable region.
+///
+/// # Safety
+/// The library writes to and reads from the returned buffers, like with `GlobalAlloc`:
+/// - A non-null pointer returned by `allocate(layout)` must point to `layout.size()` bytes that
+///   are valid for reads and writes and aligned to `layout.align()`. Misaligned buffers are
+///   rejected, but only as a safety net.
+/// - The buffer must not be accessed by anything else, and must stay valid until it is passed
+///   to `deallocate()`, which may happen on another thread.
+/// - The contents of the buffer may be uninitialized.
+pub unsafe trait PlaneAllocator: std::fmt::Debug + Send + Sync {
+    // Returns a buffer matching layout, or null on failure.
+    fn allocate(&self, layout: Layout) -> *mut u8;
+    /// # Safety
+    /// `ptr` must have been returned by `allocate()` on this allocator with the same `layout`.
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout);
+}
+
 #[derive(Default)]
 pub struct Image {
     pub width: u32,
@@ -90,11 +113,16 @@ pub struct Image {
     pub transfer_characteristics: TransferCharacteristics,
     pub matrix_coefficients: MatrixCoefficients,
 
+    // From the properties of the color item, or from the boxes of the sample entry of the color
+    // track when decoding a sequence. See display_transform() for how to apply them.
     pub clli: Option<ContentLightLevelInformation>,
     pub pasp: Option<PixelAspectRatio>,
     pub clap: Option<CleanAperture>,
     pub irot_angle: Option<u8>,
     pub imir_axis: Option<u8>,
+    // From the 'taic' and 'itai' properties of the color item, if present.
+    pub tai_clock_info: Option<TaiClockInfo>,
+    pub tai_timestamp: Option<TaiTimestamp>,
 
     pub exif: Vec<u8>,
     pub icc: Vec<u8>,
@@ -102,6 +130,9 @@ pub struct Image {
 
     pub image_sequence_track_present: bool,
     pub progressive_state: ProgressiveState,
+
+    // Used by allocate_planes() when set. Otherwise the planes use the Rust allocator.
+    pub plane_allocator: Option<Arc<dyn PlaneAllocator>>,
 }
 
 pub struct PlaneData {
@@ -111,6 +142,20 @@ pub struct PlaneData {
     pub pixel_size: u32,
 }
 
+// The combined effect of the clap, irot and imir properties. To display the image, rotate it
+// counter-clockwise by rotation degrees, then mirror it, then crop the result to crop_rect.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DisplayTransform {
+    // Counter-clockwise rotation in degrees: 0, 90, 180 or 270.
+    pub rotation: u16,
+    // Swap the left and right sides (imir axis 0).
+    pub mirror_horizontally: bool,
+    // Swap the top and bottom sides (imir axis 1).
+    pub mirror_vertically: bool,
+    // Region of the rotated and mirrored image to display.
+    pub crop_rect: CropRect,
+}
+
 #[derive(Clone, Copy)]
 pub enum PlaneRow<'a> {
     Depth8(&'a [u8]),
@@ -146,6 +191,136 @@ impl Image {
         self.has_plane(Plane::A)
     }
 
+    // Returns true if the image is signaled as high dynamic range, i.e. it uses the PQ or HLG
+    // transfer function. BT.2020 primaries with a depth above 8 and no known transfer function are
+    // also treated as HDR.
+    pub fn is_hdr(&self) -> bool {
+        match self.transfer_characteristics {
+            TransferCharacteristics::Pq | TransferCharacteristics::Hlg => true,
+            TransferCharacteristics::Unspecified => {
+                self.color_primaries == ColorPrimaries::Bt2020 && self.depth > 8
+            }
+            _ => false,
+        }
+    }
+
+    // Returns how the image should be displayed according to its clap, irot and imir properties,
+    // which MIAF mandates to be applied in that order. An invalid clap is ignored.
+    pub fn display_transform(&self) -> DisplayTransform {
+        let mut width = self.width;
+        let mut height = self.height;
+        let mut rect = self
+            .clap
+            .and_then(|clap| CropRect::create_from(&clap, width, height, self.yuv_format).ok())
+            .unwrap_or(CropRect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            });
+        let rotation = self.irot_angle.unwrap_or(0) % 4;
+        for _ in 0..rotation {
+            // A counter-clockwise rotation by 90 degrees moves the pixel (x, y) to
+            // (y, width - 1 - x).
+            rect = CropRect {
+                x: rect.y,
+                y: width - rect.x - rect.width,
+                width: rect.height,
+                height: rect.width,
+            };
+            std::mem::swap(&mut width, &mut height);
+        }
+        let mut transform = DisplayTransform {
+            rotation: rotation as u16 * 90,
+            ..Default::default()
+        };
+        match self.imir_axis {
+            Some(0) => {
+                transform.mirror_horizontally = true;
+                rect.x = width - rect.x - rect.width;
+            }
+            Some(1) => {
+                transform.mirror_vertically = true;
+                rect.y = height - rect.y - rect.height;
+            }
+            _ => {}
+        }
+        transform.crop_rect = rect;
+        transform
+    }
+
+    // Returns true if all the chroma samples are within tolerance of the neutral chroma value,
+    // meaning that the image is grayscale content even if it has chroma planes. The neutral value
+    // is 1 << (depth - 1) in both full and limited range (128 is also the middle of [16, 240]).
+    // Images without chroma planes are always monochrome.
+    pub fn is_effectively_monochrome(&self, tolerance: u16) -> bool {
+        let neutral = 1u32 << (self.depth.max(1) - 1);
+        let is_neutral = |value: u32| value.abs_diff(neutral) <= tolerance as u32;
+        for plane in [Plane::U, Plane::V] {
+            if !self.has_plane(plane) {
+                continue;
+            }
+            let width = self.width(plane);
+            for y in 0..self.height(plane) as u32 {
+                let neutral_row = if self.depth == 8 {
+                    self.row(plane, y)
+                        .is_ok_and(|row| row[..width].iter().all(|x| is_neutral(*x as u32)))
+                } else {
+                    self.row16(plane, y)
+                        .is_ok_and(|row| row[..width].iter().all(|x| is_neutral(*x as u32)))
+                };
+                if !neutral_row {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // Moves the luma plane of a monochrome image into the alpha plane, for files that store a
+    // mask (such as a segmentation mask) as a monochrome color image. Limited range samples are
+    // expanded to full range, as for alpha auxiliary images. The image must have a luma plane and
+    // no chroma planes.
+    pub fn to_mask(&mut self) -> AvifResult<()> {
+        if !self.has_plane(Plane::Y) || self.has_plane(Plane::U) || self.has_plane(Plane::V) {
+            return Err(AvifError::InvalidArgument);
+        }
+        let y = Plane::Y.as_usize();
+        let a = Plane::A.as_usize();
+        self.planes[a] = self.planes[y].take();
+        self.row_bytes[a] = self.row_bytes[y];
+        self.row_bytes[y] = 0;
+        self.image_owns_planes[a] = self.image_owns_planes[y];
+        self.image_owns_planes[y] = false;
+        if self.yuv_range == YuvRange::Limited {
+            self.alpha_to_full_range()?;
+        }
+        Ok(())
+    }
+
+    // Turns a monochrome image into a color image of the given format by adding U and V planes
+    // filled with the neutral chroma value (1 << (depth - 1) in both full and limited range). The
+    // luma and alpha planes are left untouched.
+    pub fn add_neutral_chroma(&mut self, format: PixelFormat) -> AvifResult<()> {
+        if !matches!(
+            format,
+            PixelFormat::Yuv420 | PixelFormat::Yuv422 | PixelFormat::Yuv444
+        ) || !self.depth_valid()
+            || !self.has_plane(Plane::Y)
+            || self.has_plane(Plane::U)
+            || self.has_plane(Plane::V)
+        {
+            return Err(AvifError::InvalidArgument);
+        }
+        self.yuv_format = format;
+        let neutral = 1u16 << (self.depth - 1);
+        for plane in [Plane::U, Plane::V] {
+            self.planes[plane.as_usize()] = None;
+            self.allocate_plane_with_default_value(plane, neutral)?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn has_same_properties(&self, other: &Image) -> bool {
         self.width == other.width && self.height == other.height && self.depth == other.depth
     }
@@ -243,6 +418,7 @@ impl Image {
             .slice16_mut(start, row_bytes)
     }
 
+    #[cfg(feature = "conversion")]
     pub(crate) fn row_generic(&self, plane: Plane, row: u32) -> AvifResult<PlaneRow> {
         Ok(if self.depth == 8 {
             PlaneRow::Depth8(self.row(plane, row)?)
@@ -266,32 +442,55 @@ impl Image {
         category: Category,
         default_values: [u16; 4],
     ) -> AvifResult<()> {
-        let pixel_size: usize = if self.depth == 8 { 1 } else { 2 };
         for plane in category.planes() {
-            let plane = *plane;
-            let plane_index = plane.as_usize();
-            let width = self.width(plane);
-            let plane_size = checked_mul!(width, self.height(plane))?;
-            if self.planes[plane_index].is_some()
-                && self.planes[plane_index].unwrap_ref().size() == plane_size
-                && (self.planes[plane_index].unwrap_ref().pixel_bit_size() == 0
-                    || self.planes[plane_index].unwrap_ref().pixel_bit_size() == pixel_size * 8)
-            {
-                continue;
-            }
-            self.planes[plane_index] = Some(if self.depth == 8 {
-                Pixels::Buffer(Vec::new())
-            } else {
-                Pixels::Buffer16(Vec::new())
-            });
-            let pixels = self.planes[plane_index].unwrap_mut();
-            pixels.resize(plane_size, default_values[plane_index])?;
-            self.row_bytes[plane_index] = u32_from_usize(checked_mul!(width, pixel_size)?)?;
-            self.image_owns_planes[plane_index] = true;
+            self.allocate_plane_with_default_value(*plane, default_values[plane.as_usize()])?;
         }
         Ok(())
     }
 
+    fn allocate_plane_with_default_value(
+        &mut self,
+        plane: Plane,
+        default_value: u16,
+    ) -> AvifResult<()> {
+        let pixel_size: usize = if self.depth == 8 { 1 } else { 2 };
+        let plane_index = plane.as_usize();
+        let width = self.width(plane);
+        let plane_size = checked_mul!(width, self.height(plane))?;
+        if self.planes[plane_index].is_some()
+            && self.planes[plane_index].unwrap_ref().size() == plane_size
+            && (self.planes[plane_index].unwrap_ref().pixel_bit_size() == 0
+                || self.planes[plane_index].unwrap_ref().pixel_bit_size() == pixel_size * 8)
+        {
+            return Ok(());
+        }
+        self.planes[plane_index] = Some(match (&self.plane_allocator, self.depth == 8) {
+            (Some(allocator), true) => Pixels::Allocated(AllocatedSlice::create(
+                allocator,
+                plane_size,
+                default_value as u8,
+            )?),
+            (Some(allocator), false) => Pixels::Allocated16(AllocatedSlice::create(
+                allocator,
+                plane_size,
+                default_value,
+            )?),
+            (None, true) => {
+                let mut pixels = Pixels::Buffer(Vec::new());
+                pixels.resize(plane_size, default_value)?;
+                pixels
+            }
+            (None, false) => {
+                let mut pixels = Pixels::Buffer16(Vec::new());
+                pixels.resize(plane_size, default_value)?;
+                pixels
+            }
+        });
+        self.row_bytes[plane_index] = u32_from_usize(checked_mul!(width, pixel_size)?)?;
+        self.image_owns_planes[plane_index] = true;
+        Ok(())
+    }
+
     pub(crate) fn allocate_planes(&mut self, category: Category) -> AvifResult<()> {
         self.allocate_planes_with_default_values(category, [0, 0, 0, self.max_channel()])
     }
@@ -310,8 +509,9 @@ impl Image {
     }
 
     // If src contains pointers, this function will simply make a copy of the pointer without
-    // copying the actual pixels (stealing). If src contains buffer, this function will clone the
-    // buffers (copying).
+    // copying the actual pixels (stealing). If src contains buffer, this function will copy the
+    // buffers into the existing buffers of self when they have the same size and clone them
+    // otherwise (copying).
     pub(crate) fn steal_or_copy_planes_from(
         &mut self,
         src: &Image,
@@ -319,14 +519,100 @@ impl Image {
     ) -> AvifResult<()> {
         for plane in category.planes() {
             let plane = plane.as_usize();
-            (self.planes[plane], self.row_bytes[plane]) = match &src.planes[plane] {
-                Some(src_plane) => (Some(src_plane.try_clone()?), src.row_bytes[plane]),
-                None => (None, 0),
+            let src_plane = match &src.planes[plane] {
+                Some(src_plane) => src_plane,
+                None => {
+                    self.planes[plane] = None;
+                    self.row_bytes[plane] = 0;
+                    continue;
+                }
+            };
+            match (&mut self.planes[plane], src_plane) {
+                (Some(Pixels::Buffer(dst)), Pixels::Buffer(src)) if dst.len() == src.len() => {
+                    dst.copy_from_slice(src)
+                }
+                (Some(Pixels::Buffer16(dst)), Pixels::Buffer16(src)) if dst.len() == src.len() => {
+                    dst.copy_from_slice(src)
+                }
+                _ => self.planes[plane] = Some(src_plane.try_clone()?),
+            }
+            self.row_bytes[plane] = src.row_bytes[plane];
+        }
+        Ok(())
+    }
+
+    // Copies the properties and the pixels of src into self. The planes of self are reused (and
+    // overwritten) when they already have the required size. Otherwise, they are allocated.
+    pub(crate) fn copy_from(&mut self, src: &Image) -> AvifResult<()> {
+        self.width = src.width;
+        self.height = src.height;
+        self.depth = src.depth;
+        self.yuv_format = src.yuv_format;
+        self.yuv_range = src.yuv_range;
+        self.chroma_sample_position = src.chroma_sample_position;
+        self.alpha_present = src.alpha_present;
+        self.alpha_premultiplied = src.alpha_premultiplied;
+        self.color_primaries = src.color_primaries;
+        self.transfer_characteristics = src.transfer_characteristics;
+        self.matrix_coefficients = src.matrix_coefficients;
+        self.clli = src.clli;
+        self.pasp = src.pasp;
+        self.clap = src.clap;
+        self.irot_angle = src.irot_angle;
+        self.imir_axis = src.imir_axis;
+        self.tai_clock_info = src.tai_clock_info;
+        self.tai_timestamp = src.tai_timestamp;
+        self.exif.clone_from(&src.exif);
+        self.icc.clone_from(&src.icc);
+        self.xmp.clone_from(&src.xmp);
+        self.image_sequence_track_present = src.image_sequence_track_present;
+        self.progressive_state = src.progressive_state;
+        for category in [Category::Color, Category::Alpha] {
+            if !category.planes().iter().any(|plane| src.has_plane(*plane)) {
+                for plane in category.planes() {
+                    let plane = plane.as_usize();
+                    self.planes[plane] = None;
+                    self.row_bytes[plane] = 0;
+                    self.image_owns_planes[plane] = false;
+                }
+                continue;
+            }
+            self.allocate_planes(category)?;
+            for plane in category.planes() {
+                let plane = *plane;
+                if !src.has_plane(plane) {
+                    continue;
+                }
+                let width = src.width(plane);
+                for y in 0..u32_from_usize(src.height(plane))? {
+                    if self.depth == 8 {
+                        self.row_mut(plane, y)?[..width]
+                            .copy_from_slice(&src.row(plane, y)?[..width]);
+                    } else {
+                        self.row16_mut(plane, y)?[..width]
+                            .copy_from_slice(&src.row16(plane, y)?[..width]);
+                    }
+                }
             }
         }
         Ok(())
     }
 
+    // Moves the planes of category that self owns to dst, replacing the planes of dst. The planes
+    // that self does not own are left in place.
+    pub(crate) fn move_owned_planes_to(&mut self, dst: &mut Image, category: Category) {
+        for plane in category.planes() {
+            let plane = plane.as_usize();
+            if !self.image_owns_planes[plane] || self.planes[plane].is_none() {
+                continue;
+            }
+            dst.planes[plane] = self.planes[plane].take();
+            dst.row_bytes[plane] = std::mem::take(&mut self.row_bytes[plane]);
+            dst.image_owns_planes[plane] = true;
+            self.image_owns_planes[plane] = false;
+        }
+    }
+
     pub(crate) fn copy_from_tile(
         &mut self,
         tile: &Image,
@@ -495,6 +781,102 @@ impl Image {
         Ok(())
     }
 
+    // Expands the limited range alpha plane to full range and marks the image as full range, so
+    // that the expansion is applied once per decoded frame. The codecs set yuv_range for every
+    // frame they output.
+    pub(crate) fn alpha_to_full_range(&mut self) -> AvifResult<()> {
+        debug_assert_eq!(
+            self.yuv_range,
+            YuvRange::Limited,
+            "alpha_to_full_range() called twice on the same image"
+        );
+        self.yuv_range = YuvRange::Full;
+        self.map_plane(Plane::A, RangeConversion::LimitedToFullY)
+    }
+
+    // Converts the YUV planes from limited to full range at the depth of the image and marks the
+    // image as full range. Does nothing if the image is already full range. Planes that point to
+    // the codec's buffers are replaced by planes owned by the image.
+    pub fn to_full_range(&mut self) -> AvifResult<()> {
+        if self.yuv_range == YuvRange::Full {
+            return Ok(());
+        }
+        self.convert_range(
+            RangeConversion::LimitedToFullY,
+            RangeConversion::LimitedToFullUv,
+        )?;
+        self.yuv_range = YuvRange::Full;
+        Ok(())
+    }
+
+    // Inverse of to_full_range(). Does nothing if the image is already limited range.
+    pub fn to_limited_range(&mut self) -> AvifResult<()> {
+        if self.yuv_range == YuvRange::Limited {
+            return Ok(());
+        }
+        self.convert_range(
+            RangeConversion::FullToLimitedY,
+            RangeConversion::FullToLimitedUv,
+        )?;
+        self.yuv_range = YuvRange::Limited;
+        Ok(())
+    }
+
+    fn convert_range(&mut self, y: RangeConversion, uv: RangeConversion) -> AvifResult<()> {
+        if !matches!(self.depth, 8 | 10 | 12) {
+            return Err(AvifError::NotImplemented);
+        }
+        // With the identity matrix, all three planes use the luma range (ISO/IEC 23091-2).
+        let uv = if self.matrix_coefficients == MatrixCoefficients::Identity { y } else { uv };
+        self.map_plane(Plane::Y, y)?;
+        self.map_plane(Plane::U, uv)?;
+        self.map_plane(Plane::V, uv)
+    }
+
+    // Applies the conversion to each row of the plane. If the plane points to the codec's buffer,
+    // the result is written to a plane owned by the image instead.
+    fn map_plane(&mut self, plane: Plane, conversion: RangeConversion) -> AvifResult<()> {
+        if !self.has_plane(plane) {
+            return Ok(());
+        }
+        let plane_index = plane.as_usize();
+        let width = self.width(plane);
+        let height = u32_from_usize(self.height(plane))?;
+        let depth = self.depth;
+        if self.planes[plane_index].unwrap_ref().is_pointer() {
+            let mut src = Image {
+                width: self.width,
+                height: self.height,
+                depth: self.depth,
+                yuv_format: self.yuv_format,
+                ..Image::default()
+            };
+            src.planes[plane_index] = Some(self.planes[plane_index].unwrap_ref().try_clone()?);
+            src.row_bytes[plane_index] = self.row_bytes[plane_index];
+            self.allocate_plane_with_default_value(plane, 0)?;
+            for y in 0..height {
+                if depth > 8 {
+                    let dst_row = &mut self.row16_mut(plane, y)?[..width];
+                    dst_row.copy_from_slice(&src.row16(plane, y)?[..width]);
+                    conversion.apply16(depth, dst_row);
+                } else {
+                    let dst_row = &mut self.row_mut(plane, y)?[..width];
+                    dst_row.copy_from_slice(&src.row(plane, y)?[..width]);
+                    conversion.apply8(dst_row);
+                }
+            }
+        } else {
+            for y in 0..height {
+                if depth > 8 {
+                    conversion.apply16(depth, &mut self.row16_mut(plane, y)?[..width]);
+                } else {
+                    conversion.apply8(&mut self.row_mut(plane, y)?[..width]);
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn convert_rgba16_to_yuva(&self, rgba: [u16; 4]) -> [u16; 4] {
         let r = rgba[0] as f32 / 65535.0;
         let g = rgba[1] as f32 / 65535.0;
@@ -513,3 +895,523 @@ impl Image {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::tile::Grid;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    fn image_with_value(width: u32, height: u32, depth: u8, value: u16) -> Image {
+        let mut image = Image {
+            width,
+            height,
+            depth,
+            yuv_format: PixelFormat::Yuv420,
+            ..Default::default()
+        };
+        image
+            .allocate_planes_with_default_values(Category::Color, [value; 4])
+            .unwrap();
+        image
+    }
+
+    #[test_case::test_case(9, 16, 10, true; "bt2020 pq")]
+    #[test_case::test_case(9, 18, 10, true; "bt2020 hlg")]
+    #[test_case::test_case(1, 16, 8, true; "bt709 pq 8bit")]
+    #[test_case::test_case(9, 2, 10, true; "bt2020 unspecified 10bit")]
+    #[test_case::test_case(9, 2, 8, false; "bt2020 unspecified 8bit")]
+    #[test_case::test_case(9, 14, 10, false; "bt2020 sdr 10bit")]
+    #[test_case::test_case(1, 13, 8, false; "srgb")]
+    #[test_case::test_case(2, 2, 12, false; "unspecified")]
+    fn is_hdr(color_primaries: u16, transfer_characteristics: u16, depth: u8, expected: bool) {
+        let image = Image {
+            color_primaries: color_primaries.into(),
+            transfer_characteristics: transfer_characteristics.into(),
+            depth,
+            ..Default::default()
+        };
+        assert_eq!(image.is_hdr(), expected);
+    }
+
+    #[test]
+    fn copy_from_reuses_planes() {
+        let mut dst = Image::default();
+        dst.copy_from(&image_with_value(5, 3, 8, 10)).unwrap();
+        let pointers = YUV_PLANES.map(|plane| dst.planes[plane.as_usize()].unwrap_ref().ptr());
+        for value in [20, 30] {
+            dst.copy_from(&image_with_value(5, 3, 8, value)).unwrap();
+            for plane in YUV_PLANES {
+                assert_eq!(
+                    dst.planes[plane.as_usize()].unwrap_ref().ptr(),
+                    pointers[plane.as_usize()]
+                );
+                for y in 0..dst.height(plane) as u32 {
+                    assert!(dst.row(plane, y).unwrap().iter().all(|x| *x == value as u8));
+                }
+            }
+        }
+        assert!(!dst.has_alpha());
+
+        // Changing the depth reallocates the planes.
+        dst.copy_from(&image_with_value(5, 3, 10, 1000)).unwrap();
+        assert_eq!(dst.depth, 10);
+        for plane in YUV_PLANES {
+            assert_eq!(dst.row16(plane, 0).unwrap()[0], 1000);
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct CountingAllocator {
+        allocations: AtomicUsize,
+        deallocations: AtomicUsize,
+    }
+
+    // SAFETY: The buffers come from the global allocator.
+    unsafe impl PlaneAllocator for CountingAllocator {
+        fn allocate(&self, layout: Layout) -> *mut u8 {
+            self.allocations.fetch_add(1, Ordering::SeqCst);
+            unsafe { std::alloc::alloc(layout) }
+        }
+
+        unsafe fn deallocate(&self, ptr: *mut u8, layout: Layout) {
+            self.deallocations.fetch_add(1, Ordering::SeqCst);
+            unsafe { std::alloc::dealloc(ptr, layout) };
+        }
+    }
+
+    #[test_case::test_case(8)]
+    #[test_case::test_case(10)]
+    fn plane_allocator(depth: u8) {
+        let allocator = Arc::new(CountingAllocator::default());
+        let mut image = Image {
+            width: 5,
+            height: 3,
+            depth,
+            yuv_format: PixelFormat::Yuv420,
+            plane_allocator: Some(allocator.clone()),
+            ..Default::default()
+        };
+        image.allocate_planes(Category::Color).unwrap();
+        image.allocate_planes(Category::Alpha).unwrap();
+        assert_eq!(allocator.allocations.load(Ordering::SeqCst), 4);
+        for plane in ALL_PLANES {
+            let expected = if plane == Plane::A { image.max_channel() } else { 0 };
+            for y in 0..image.height(plane) as u32 {
+                if depth == 8 {
+                    assert!(image
+                        .row(plane, y)
+                        .unwrap()
+                        .iter()
+                        .all(|x| *x as u16 == expected));
+                } else {
+                    assert!(image
+                        .row16(plane, y)
+                        .unwrap()
+                        .iter()
+                        .all(|x| *x == expected));
+                }
+            }
+        }
+        // Planes of the same size are reused.
+        image.allocate_planes(Category::Color).unwrap();
+        assert_eq!(allocator.allocations.load(Ordering::SeqCst), 4);
+        // Copies are owned by the Rust allocator.
+        let mut copy = Image::default();
+        copy.copy_from(&image).unwrap();
+        assert_eq!(allocator.allocations.load(Ordering::SeqCst), 4);
+        drop(image);
+        assert_eq!(allocator.deallocations.load(Ordering::SeqCst), 4);
+    }
+
+    #[test_case::test_case(8, YuvRange::Full)]
+    #[test_case::test_case(8, YuvRange::Limited)]
+    #[test_case::test_case(10, YuvRange::Limited)]
+    #[test_case::test_case(12, YuvRange::Full)]
+    fn effectively_monochrome(depth: u8, yuv_range: YuvRange) {
+        let neutral = 1u16 << (depth - 1);
+        // Odd dimensions, so that the last chroma column and row cover a single luma sample.
+        let mut image = Image {
+            width: 5,
+            height: 3,
+            depth,
+            yuv_format: PixelFormat::Yuv420,
+            yuv_range,
+            ..Default::default()
+        };
+        image
+            .allocate_planes_with_default_values(Category::Color, [10, neutral, neutral, 0])
+            .unwrap();
+        assert!(image.is_effectively_monochrome(0));
+
+        // Change the last chroma sample of the V plane.
+        let (x, y) = (image.width(Plane::V) - 1, image.height(Plane::V) as u32 - 1);
+        if depth == 8 {
+            image.row_mut(Plane::V, y).unwrap()[x] = neutral as u8 + 2;
+        } else {
+            image.row16_mut(Plane::V, y).unwrap()[x] = neutral - 2;
+        }
+        assert!(!image.is_effectively_monochrome(0));
+        assert!(!image.is_effectively_monochrome(1));
+        assert!(image.is_effectively_monochrome(2));
+    }
+
+    #[test]
+    fn effectively_monochrome_without_chroma() {
+        let mut image = Image {
+            width: 4,
+            height: 4,
+            depth: 8,
+            yuv_format: PixelFormat::Yuv400,
+            ..Default::default()
+        };
+        image.allocate_planes(Category::Color).unwrap();
+        assert!(image.is_effectively_monochrome(0));
+    }
+
+    #[test_case::test_case(YuvRange::Full, 200, 200)]
+    #[test_case::test_case(YuvRange::Limited, 235, 255)]
+    fn to_mask(yuv_range: YuvRange, luma: u16, expected_alpha: u8) {
+        let mut image = Image {
+            width: 3,
+            height: 2,
+            depth: 8,
+            yuv_format: PixelFormat::Yuv400,
+            yuv_range,
+            ..Default::default()
+        };
+        image
+            .allocate_planes_with_default_values(Category::Color, [luma; 4])
+            .unwrap();
+        image.to_mask().unwrap();
+        assert!(!image.has_plane(Plane::Y));
+        assert!(image.has_alpha());
+        for y in 0..image.height {
+            assert!(image
+                .row(Plane::A, y)
+                .unwrap()
+                .iter()
+                .all(|x| *x == expected_alpha));
+        }
+        // There is no luma plane anymore.
+        assert!(image.to_mask().is_err());
+    }
+
+    #[test_case::test_case(8, 16, 0)]
+    #[test_case::test_case(8, 235, 255)]
+    #[test_case::test_case(10, 64, 0)]
+    #[test_case::test_case(10, 940, 1023)]
+    #[test_case::test_case(12, 3760, 4095)]
+    fn alpha_to_full_range(depth: u8, limited: u16, expected_full: u16) {
+        let mut image = Image {
+            width: 3,
+            height: 2,
+            depth,
+            yuv_range: YuvRange::Limited,
+            ..Default::default()
+        };
+        image
+            .allocate_planes_with_default_values(Category::Alpha, [limited; 4])
+            .unwrap();
+        image.alpha_to_full_range().unwrap();
+        assert_eq!(image.yuv_range, YuvRange::Full);
+        for y in 0..image.height {
+            if depth > 8 {
+                assert!(image
+                    .row16(Plane::A, y)
+                    .unwrap()
+                    .iter()
+                    .all(|x| *x == expected_full));
+            } else {
+                assert!(image
+                    .row(Plane::A, y)
+                    .unwrap()
+                    .iter()
+                    .all(|x| *x as u16 == expected_full));
+            }
+        }
+    }
+
+    fn image_with_rows(
+        depth: u8,
+        matrix_coefficients: MatrixCoefficients,
+        rows: [&[u16]; 3],
+    ) -> Image {
+        let mut image = Image {
+            width: rows[0].len() as u32,
+            height: 1,
+            depth,
+            yuv_format: PixelFormat::Yuv444,
+            yuv_range: YuvRange::Limited,
+            matrix_coefficients,
+            ..Default::default()
+        };
+        fill_color_planes(&mut image, |plane, x, _| rows[plane.as_usize()][x]);
+        image
+    }
+
+    fn row_values(image: &Image, plane: Plane) -> Vec<u16> {
+        if image.depth > 8 {
+            image.row16(plane, 0).unwrap().to_vec()
+        } else {
+            image
+                .row(plane, 0)
+                .unwrap()
+                .iter()
+                .map(|v| *v as u16)
+                .collect()
+        }
+    }
+
+    #[test_case::test_case(8, MatrixCoefficients::Bt601, [16, 126, 235], [16, 128, 240], [0, 128, 255], [0, 128, 255]; "8 bit")]
+    #[test_case::test_case(10, MatrixCoefficients::Bt709, [64, 502, 940], [64, 512, 960], [0, 512, 1023], [0, 512, 1023]; "10 bit")]
+    #[test_case::test_case(12, MatrixCoefficients::Bt2020Ncl, [256, 2008, 3760], [256, 2048, 3840], [0, 2048, 4095], [0, 2048, 4095]; "12 bit")]
+    #[test_case::test_case(8, MatrixCoefficients::Identity, [16, 126, 235], [16, 126, 235], [0, 128, 255], [0, 128, 255]; "identity")]
+    fn to_full_range(
+        depth: u8,
+        matrix_coefficients: MatrixCoefficients,
+        y: [u16; 3],
+        uv: [u16; 3],
+        expected_y: [u16; 3],
+        expected_uv: [u16; 3],
+    ) {
+        let mut image = image_with_rows(depth, matrix_coefficients, [&y, &uv, &uv]);
+        image.to_full_range().unwrap();
+        assert_eq!(image.yuv_range, YuvRange::Full);
+        assert_eq!(row_values(&image, Plane::Y), expected_y);
+        assert_eq!(row_values(&image, Plane::U), expected_uv);
+        assert_eq!(row_values(&image, Plane::V), expected_uv);
+        // Already full range.
+        image.to_full_range().unwrap();
+        assert_eq!(row_values(&image, Plane::Y), expected_y);
+        image.to_limited_range().unwrap();
+        assert_eq!(image.yuv_range, YuvRange::Limited);
+        assert_eq!(row_values(&image, Plane::Y), y);
+        assert_eq!(row_values(&image, Plane::U), uv);
+    }
+
+    #[test_case::test_case(8)]
+    #[test_case::test_case(10)]
+    #[test_case::test_case(12)]
+    fn limited_to_full_to_limited_range(depth: u8) {
+        // Every value in the limited range of luma and chroma respectively.
+        let scale = 1u16 << (depth - 8);
+        let y: Vec<u16> = (16 * scale..=235 * scale).collect();
+        let mut uv: Vec<u16> = (16 * scale..=240 * scale).collect();
+        uv.truncate(y.len());
+        for matrix_coefficients in [MatrixCoefficients::Bt709, MatrixCoefficients::Identity] {
+            let mut image = image_with_rows(depth, matrix_coefficients, [&y, &uv, &uv]);
+            image.to_full_range().unwrap();
+            image.to_limited_range().unwrap();
+            for (plane, expected) in [(Plane::Y, &y), (Plane::U, &uv), (Plane::V, &uv)] {
+                for (actual, expected) in row_values(&image, plane).iter().zip(expected.iter()) {
+                    assert!(actual.abs_diff(*expected) <= 1, "{actual} {expected}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "alpha_to_full_range() called twice")]
+    fn alpha_to_full_range_twice() {
+        let mut image = Image {
+            width: 3,
+            height: 2,
+            depth: 10,
+            yuv_range: YuvRange::Limited,
+            ..Default::default()
+        };
+        image
+            .allocate_planes_with_default_values(Category::Alpha, [100; 4])
+            .unwrap();
+        image.alpha_to_full_range().unwrap();
+        let _ = image.alpha_to_full_range();
+    }
+
+    #[test_case::test_case(8, PixelFormat::Yuv420)]
+    #[test_case::test_case(10, PixelFormat::Yuv420)]
+    #[test_case::test_case(12, PixelFormat::Yuv422)]
+    #[test_case::test_case(8, PixelFormat::Yuv444)]
+    fn add_neutral_chroma(depth: u8, format: PixelFormat) {
+        let mut image = Image {
+            width: 5,
+            height: 3,
+            depth,
+            yuv_format: PixelFormat::Yuv400,
+            ..Default::default()
+        };
+        // Horizontal gradient.
+        let luma = |x: usize| (x as u16 * 50) << (depth - 8);
+        fill_color_planes(&mut image, |_, x, _| luma(x));
+        image.add_neutral_chroma(format).unwrap();
+        assert_eq!(image.yuv_format, format);
+        assert!(image.is_effectively_monochrome(0));
+        for plane in [Plane::U, Plane::V] {
+            assert!(image.has_plane(plane));
+            assert_eq!(
+                image.width(plane),
+                if format == PixelFormat::Yuv444 { 5 } else { 3 }
+            );
+            assert_eq!(
+                image.height(plane),
+                if format == PixelFormat::Yuv420 { 2 } else { 3 }
+            );
+        }
+        for y in 0..image.height {
+            let row: Vec<u16> = if depth == 8 {
+                let row = image.row(Plane::Y, y).unwrap();
+                row.iter().map(|v| *v as u16).collect()
+            } else {
+                image.row16(Plane::Y, y).unwrap().to_vec()
+            };
+            assert_eq!(row, (0..5).map(luma).collect::<Vec<_>>());
+        }
+        // There are chroma planes now.
+        assert!(image.add_neutral_chroma(format).is_err());
+    }
+
+    #[test]
+    fn to_mask_with_chroma() {
+        let mut image = image_with_value(3, 2, 8, 100);
+        assert!(image.to_mask().is_err());
+        assert!(image.has_plane(Plane::Y));
+        assert!(!image.has_alpha());
+    }
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> CropRect {
+        CropRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // 722x1024 image with a 386x330 clean aperture at (271, 39).
+    #[test_case::test_case(None, None, rect(271, 39, 386, 330))]
+    #[test_case::test_case(Some(1), None, rect(39, 65, 330, 386))]
+    #[test_case::test_case(Some(2), None, rect(65, 655, 386, 330))]
+    #[test_case::test_case(Some(3), None, rect(655, 271, 330, 386))]
+    #[test_case::test_case(None, Some(0), rect(65, 39, 386, 330))]
+    #[test_case::test_case(None, Some(1), rect(271, 655, 386, 330))]
+    #[test_case::test_case(Some(1), Some(0), rect(655, 65, 330, 386))]
+    #[test_case::test_case(Some(1), Some(1), rect(39, 271, 330, 386))]
+    fn display_transform(irot_angle: Option<u8>, imir_axis: Option<u8>, expected_rect: CropRect) {
+        let image = Image {
+            width: 722,
+            height: 1024,
+            yuv_format: PixelFormat::Yuv444,
+            clap: Some(CleanAperture {
+                width: UFraction(386, 1),
+                height: UFraction(330, 1),
+                horiz_off: UFraction(103, 1),
+                vert_off: UFraction(-308i32 as u32, 1),
+            }),
+            irot_angle,
+            imir_axis,
+            ..Default::default()
+        };
+        assert_eq!(
+            image.display_transform(),
+            DisplayTransform {
+                rotation: irot_angle.unwrap_or(0) as u16 * 90,
+                mirror_horizontally: imir_axis == Some(0),
+                mirror_vertically: imir_axis == Some(1),
+                crop_rect: expected_rect,
+            }
+        );
+    }
+
+    #[test]
+    fn display_transform_without_valid_clap() {
+        let mut image = Image {
+            width: 722,
+            height: 1024,
+            yuv_format: PixelFormat::Yuv420,
+            irot_angle: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(image.display_transform().crop_rect, rect(0, 0, 1024, 722));
+        // The clean aperture is larger than the image.
+        image.clap = Some(CleanAperture {
+            width: UFraction(800, 1),
+            height: UFraction(330, 1),
+            horiz_off: UFraction(0, 1),
+            vert_off: UFraction(0, 1),
+        });
+        assert_eq!(image.display_transform().crop_rect, rect(0, 0, 1024, 722));
+    }
+
+    // Grid tiles of the last column and row may overhang the right and bottom edges of the
+    // canvas. Only the part of those tiles that is inside the canvas is copied.
+    #[test_case::test_case(PixelFormat::Yuv420, 8, 128, 170)]
+    #[test_case::test_case(PixelFormat::Yuv422, 10, 128, 171)]
+    #[test_case::test_case(PixelFormat::Yuv444, 12, 127, 133)]
+    #[test_case::test_case(PixelFormat::Yuv400, 8, 71, 133)]
+    fn copy_from_overhanging_grid_tiles(
+        yuv_format: PixelFormat,
+        depth: u8,
+        width: u32,
+        height: u32,
+    ) {
+        // Tiles of 70x66 in 2 columns and 3 rows. The last column and row overhang the canvas.
+        let tile_info = TileInfo {
+            tile_count: 6,
+            grid: Grid {
+                rows: 3,
+                columns: 2,
+                width,
+                height,
+            },
+            ..Default::default()
+        };
+        let sample = |tile_index: usize, plane: Plane, x: usize, y: usize| {
+            ((tile_index * 61 + plane.as_usize() * 17 + x * 3 + y * 7) % (1 << depth)) as u16
+        };
+        let mut canvas = Image {
+            width,
+            height,
+            depth,
+            yuv_format,
+            ..Default::default()
+        };
+        canvas.allocate_planes(Category::Color).unwrap();
+        for tile_index in 0..6 {
+            let mut tile = Image {
+                width: 70,
+                height: 66,
+                depth,
+                yuv_format,
+                ..Default::default()
+            };
+            fill_color_planes(&mut tile, |plane, x, y| sample(tile_index, plane, x, y));
+            assert!(canvas
+                .copy_from_tile(&tile, &tile_info, tile_index as u32, Category::Color)
+                .is_ok());
+        }
+        for plane in YUV_PLANES {
+            if !canvas.has_plane(plane) {
+                continue;
+            }
+            let (tile_width, tile_height) = match (plane, yuv_format) {
+                (Plane::Y, _) | (_, PixelFormat::Yuv444) => (70, 66),
+                (_, PixelFormat::Yuv422) => (35, 66),
+                _ => (35, 33),
+            };
+            for y in 0..canvas.height(plane) {
+                for x in 0..canvas.width(plane) {
+                    let tile_index = (y / tile_height) * 2 + x / tile_width;
+                    let expected = sample(tile_index, plane, x % tile_width, y % tile_height);
+                    let value = if depth == 8 {
+                        canvas.row(plane, y as u32).unwrap()[x] as u16
+                    } else {
+                        canvas.row16(plane, y as u32).unwrap()[x]
+                    };
+                    assert_eq!(value, expected, "{plane:?} ({x}, {y})");
+                }
+            }
+        }
+    }
+}
@@ -12,12 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::decoder::GenericIO;
 use crate::internal_utils::*;
 
+use std::cell::Cell;
 use std::fs::File;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
+use std::rc::Rc;
 
 #[derive(Debug, Default)]
 pub struct DecoderFileIO {
@@ -79,6 +82,29 @@ impl decoder::IO for DecoderFileIO {
     }
 }
 
+// Forwards the reads to io and adds the size of the returned data to bytes_read.
+pub(crate) struct CountingIO {
+    pub io: GenericIO,
+    pub bytes_read: Rc<Cell<u64>>,
+}
+
+impl decoder::IO for CountingIO {
+    fn read(&mut self, offset: u64, max_read_size: usize) -> AvifResult<&[u8]> {
+        let data = self.io.read(offset, max_read_size)?;
+        self.bytes_read
+            .set(self.bytes_read.get().saturating_add(data.len() as u64));
+        Ok(data)
+    }
+
+    fn size_hint(&self) -> u64 {
+        self.io.size_hint()
+    }
+
+    fn persistent(&self) -> bool {
+        self.io.persistent()
+    }
+}
+
 pub struct DecoderRawIO<'a> {
     pub data: &'a [u8],
 }
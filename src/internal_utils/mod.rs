@@ -14,6 +14,8 @@
 
 pub mod io;
 pub mod pixels;
+#[cfg(feature = "simd")]
+pub(crate) mod simd;
 pub mod stream;
 
 use crate::parser::mp4box::*;
@@ -178,7 +180,9 @@ macro_rules! clamp_function {
     };
 }
 
+#[cfg(feature = "conversion")]
 clamp_function!(clamp_u16, u16);
+#[cfg(feature = "conversion")]
 clamp_function!(clamp_f32, f32);
 clamp_function!(clamp_i32, i32);
 
@@ -211,16 +215,22 @@ pub(crate) fn find_nclx(properties: &[ItemProperty]) -> AvifResult<Option<&Nclx>
 pub(crate) fn find_icc(properties: &[ItemProperty]) -> AvifResult<Option<&Vec<u8>>> {
     let mut single_icc: Option<&Vec<u8>> = None;
     for property in properties {
-        if let ItemProperty::ColorInformation(ColorInformation::Icc(icc)) = property {
+        if let ItemProperty::ColorInformation(ColorInformation::Icc { profile, .. }) = property {
             if single_icc.is_some() {
                 return Err(AvifError::BmffParseFailed("multiple icc were found".into()));
             }
-            single_icc = Some(icc);
+            single_icc = Some(profile);
         }
     }
     Ok(single_icc)
 }
 
+// Returns the data color space signature of the ICC profile header (such as "RGB " or "GRAY"),
+// see Section 7.2.6 of ICC.1:2010.
+pub(crate) fn icc_color_space(icc: &[u8]) -> Option<&[u8]> {
+    icc.get(16..20)
+}
+
 pub(crate) fn check_limits(width: u32, height: u32, size_limit: u32, dimension_limit: u32) -> bool {
     if height == 0 {
         return false;
@@ -252,6 +262,109 @@ pub(crate) fn limited_to_full_y(depth: u8, v: u16) -> u16 {
     }
 }
 
+// Rounds n / d to the nearest integer, halfway cases away from zero. d must be positive.
+fn div_round(n: i32, d: i32) -> i32 {
+    if n >= 0 {
+        (n + d / 2) / d
+    } else {
+        -((-n + d / 2) / d)
+    }
+}
+
+// Chroma samples are centered on (full + 1) / 2 in both ranges (ISO/IEC 23091-2).
+fn limited_to_full_centered(min: i32, max: i32, full: i32, v: u16) -> u16 {
+    let bias = (full + 1) / 2;
+    clamp_i32(
+        div_round((v as i32 - bias) * full, max - min) + bias,
+        0,
+        full,
+    ) as u16
+}
+
+fn full_to_limited(min: i32, max: i32, full: i32, v: u16) -> u16 {
+    clamp_i32(div_round(v as i32 * (max - min), full) + min, min, max) as u16
+}
+
+fn full_to_limited_centered(min: i32, max: i32, full: i32, v: u16) -> u16 {
+    let bias = (full + 1) / 2;
+    clamp_i32(
+        div_round((v as i32 - bias) * (max - min), full) + bias,
+        min,
+        max,
+    ) as u16
+}
+
+pub(crate) fn limited_to_full_uv(depth: u8, v: u16) -> u16 {
+    match depth {
+        8 => limited_to_full_centered(16, 240, 255, v),
+        10 => limited_to_full_centered(64, 960, 1023, v),
+        12 => limited_to_full_centered(256, 3840, 4095, v),
+        _ => 0,
+    }
+}
+
+pub(crate) fn full_to_limited_y(depth: u8, v: u16) -> u16 {
+    match depth {
+        8 => full_to_limited(16, 235, 255, v),
+        10 => full_to_limited(64, 940, 1023, v),
+        12 => full_to_limited(256, 3760, 4095, v),
+        _ => 0,
+    }
+}
+
+pub(crate) fn full_to_limited_uv(depth: u8, v: u16) -> u16 {
+    match depth {
+        8 => full_to_limited_centered(16, 240, 255, v),
+        10 => full_to_limited_centered(64, 960, 1023, v),
+        12 => full_to_limited_centered(256, 3840, 4095, v),
+        _ => 0,
+    }
+}
+
+// The range conversions of Image, applied to a row of samples in place.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RangeConversion {
+    LimitedToFullY,
+    LimitedToFullUv,
+    FullToLimitedY,
+    FullToLimitedUv,
+}
+
+impl RangeConversion {
+    fn sample(self, depth: u8, v: u16) -> u16 {
+        match self {
+            Self::LimitedToFullY => limited_to_full_y(depth, v),
+            Self::LimitedToFullUv => limited_to_full_uv(depth, v),
+            Self::FullToLimitedY => full_to_limited_y(depth, v),
+            Self::FullToLimitedUv => full_to_limited_uv(depth, v),
+        }
+    }
+
+    pub(crate) fn apply8(self, row: &mut [u8]) {
+        #[cfg(feature = "simd")]
+        match self {
+            Self::LimitedToFullY => return simd::limited_to_full_y8(row),
+            Self::LimitedToFullUv => return simd::limited_to_full_uv8(row),
+            _ => {}
+        }
+        for v in row {
+            *v = self.sample(8, *v as u16) as u8;
+        }
+    }
+
+    pub(crate) fn apply16(self, depth: u8, row: &mut [u16]) {
+        #[cfg(feature = "simd")]
+        match self {
+            Self::LimitedToFullY => return simd::limited_to_full_y16(depth, row),
+            Self::LimitedToFullUv => return simd::limited_to_full_uv16(depth, row),
+            _ => {}
+        }
+        for v in row {
+            *v = self.sample(depth, *v);
+        }
+    }
+}
+
 pub(crate) fn create_vec_exact<T>(size: usize) -> AvifResult<Vec<T>> {
     let mut v = Vec::<T>::new();
     let allocation_size = size
@@ -272,6 +385,33 @@ pub(crate) fn create_vec_exact<T>(size: usize) -> AvifResult<Vec<T>> {
     Ok(v)
 }
 
+// Allocates the color planes of image and sets each of their samples to sample(plane, x, y), for
+// test images of any depth.
+#[cfg(test)]
+pub(crate) fn fill_color_planes(
+    image: &mut image::Image,
+    sample: impl Fn(image::Plane, usize, usize) -> u16,
+) {
+    image
+        .allocate_planes(crate::decoder::Category::Color)
+        .unwrap();
+    for plane in image::YUV_PLANES {
+        if !image.has_plane(plane) {
+            continue;
+        }
+        for y in 0..image.height(plane) {
+            for x in 0..image.width(plane) {
+                let value = sample(plane, x, y);
+                if image.depth == 8 {
+                    image.row_mut(plane, y as u32).unwrap()[x] = value as u8;
+                } else {
+                    image.row16_mut(plane, y as u32).unwrap()[x] = value;
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 pub(crate) fn assert_eq_f32_array(a: &[f32], b: &[f32]) {
     assert_eq!(a.len(), b.len());
@@ -12,9 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::image::PlaneAllocator;
 use crate::internal_utils::*;
 use crate::*;
 
+use std::alloc::Layout;
+use std::sync::Arc;
+
 #[derive(Clone, Copy, Debug)]
 pub struct PointerSlice<T> {
     ptr: *mut [T],
@@ -74,6 +78,88 @@ impl<T> PointerSlice<T> {
     }
 }
 
+// Memory obtained from a PlaneAllocator. It is returned to the allocator when dropped.
+pub struct AllocatedSlice<T> {
+    slice: PointerSlice<T>,
+    layout: Layout,
+    allocator: Arc<dyn PlaneAllocator>,
+}
+
+impl<T: Copy> AllocatedSlice<T> {
+    pub(crate) fn create(
+        allocator: &Arc<dyn PlaneAllocator>,
+        size: usize,
+        default: T,
+    ) -> AvifResult<Self> {
+        let layout = Layout::array::<T>(size).or(Err(AvifError::OutOfMemory))?;
+        if layout.size() == 0 {
+            return Err(AvifError::InvalidArgument);
+        }
+        let ptr = allocator.allocate(layout);
+        if ptr.is_null() {
+            return Err(AvifError::OutOfMemory);
+        }
+        if ptr.align_offset(layout.align()) != 0 {
+            unsafe { allocator.deallocate(ptr, layout) };
+            return Err(AvifError::UnknownError(
+                "plane allocator returned a misaligned buffer".into(),
+            ));
+        }
+        let ptr = ptr as *mut T;
+        for i in 0..size {
+            // SAFETY: ptr points to a region of at least `size` elements of T.
+            unsafe { ptr.add(i).write(default) };
+        }
+        Ok(Self {
+            slice: unsafe { PointerSlice::create(ptr, size)? },
+            layout,
+            allocator: allocator.clone(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.slice.slice_impl().len()
+    }
+
+    fn as_slice(&self) -> &[T] {
+        self.slice.slice_impl()
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> AvifResult<&[T]> {
+        self.slice.slice(range)
+    }
+
+    pub fn slice_mut(&mut self, range: Range<usize>) -> AvifResult<&mut [T]> {
+        self.slice.slice_mut(range)
+    }
+
+    pub fn ptr(&self) -> *const T {
+        self.slice.ptr()
+    }
+
+    pub fn ptr_mut(&mut self) -> *mut T {
+        self.slice.ptr_mut()
+    }
+}
+
+impl<T> Drop for AllocatedSlice<T> {
+    fn drop(&mut self) {
+        // SAFETY: The memory was obtained from this allocator with this layout.
+        unsafe {
+            self.allocator
+                .deallocate(self.slice.ptr_mut() as *mut u8, self.layout)
+        };
+    }
+}
+
+impl<T> std::fmt::Debug for AllocatedSlice<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AllocatedSlice")
+            .field("layout", &self.layout)
+            .finish_non_exhaustive()
+    }
+}
+
 // This struct must not be derived from the default `Clone` trait as it has to be cloned with error
 // checking using the `try_clone` function.
 #[derive(Debug)]
@@ -87,6 +173,10 @@ pub enum Pixels {
     Buffer(Vec<u8>),
     // Used for 10-bit, 12-bit and 16-bit images.
     Buffer16(Vec<u16>),
+    // Allocated with a PlaneAllocator. Used for 8-bit images.
+    Allocated(AllocatedSlice<u8>),
+    // Allocated with a PlaneAllocator. Used for 10-bit, 12-bit and 16-bit images.
+    Allocated16(AllocatedSlice<u16>),
 }
 
 impl Pixels {
@@ -115,6 +205,8 @@ impl Pixels {
             Pixels::Pointer16(_) => 0,
             Pixels::Buffer(buffer) => buffer.len(),
             Pixels::Buffer16(buffer) => buffer.len(),
+            Pixels::Allocated(buffer) => buffer.len(),
+            Pixels::Allocated16(buffer) => buffer.len(),
         }
     }
 
@@ -124,6 +216,8 @@ impl Pixels {
             Pixels::Pointer16(_) => 0,
             Pixels::Buffer(_) => 8,
             Pixels::Buffer16(_) => 16,
+            Pixels::Allocated(_) => 8,
+            Pixels::Allocated16(_) => 16,
         }
     }
 
@@ -133,6 +227,8 @@ impl Pixels {
             Pixels::Pointer16(ptr) => !ptr.is_empty(),
             Pixels::Buffer(buffer) => !buffer.is_empty(),
             Pixels::Buffer16(buffer) => !buffer.is_empty(),
+            Pixels::Allocated(buffer) => buffer.len() != 0,
+            Pixels::Allocated16(buffer) => buffer.len() != 0,
         }
     }
 
@@ -152,6 +248,16 @@ impl Pixels {
                 }
                 buffer.resize(size, default);
             }
+            // Allocated buffers cannot grow. They can only be reset to the default value.
+            Pixels::Allocated(buffer) if buffer.len() == size => {
+                buffer.slice_mut(0..size)?.fill(default as u8);
+            }
+            Pixels::Allocated16(buffer) if buffer.len() == size => {
+                buffer.slice_mut(0..size)?.fill(default);
+            }
+            Pixels::Allocated(_) | Pixels::Allocated16(_) => {
+                return Err(AvifError::InvalidArgument)
+            }
         }
         Ok(())
     }
@@ -164,6 +270,7 @@ impl Pixels {
         match self {
             Pixels::Pointer(ptr) => ptr.ptr(),
             Pixels::Buffer(buffer) => buffer.as_ptr(),
+            Pixels::Allocated(buffer) => buffer.ptr(),
             _ => std::ptr::null_mut(),
         }
     }
@@ -172,6 +279,7 @@ impl Pixels {
         match self {
             Pixels::Pointer16(ptr) => ptr.ptr(),
             Pixels::Buffer16(buffer) => buffer.as_ptr(),
+            Pixels::Allocated16(buffer) => buffer.ptr(),
             _ => std::ptr::null_mut(),
         }
     }
@@ -180,6 +288,7 @@ impl Pixels {
         match self {
             Pixels::Pointer(ptr) => ptr.ptr_mut(),
             Pixels::Buffer(buffer) => buffer.as_mut_ptr(),
+            Pixels::Allocated(buffer) => buffer.ptr_mut(),
             _ => std::ptr::null_mut(),
         }
     }
@@ -188,6 +297,7 @@ impl Pixels {
         match self {
             Pixels::Pointer16(ptr) => ptr.ptr_mut(),
             Pixels::Buffer16(buffer) => buffer.as_mut_ptr(),
+            Pixels::Allocated16(buffer) => buffer.ptr_mut(),
             _ => std::ptr::null_mut(),
         }
     }
@@ -212,6 +322,23 @@ impl Pixels {
                 cloned_buffer16.extend_from_slice(buffer16);
                 Ok(Pixels::Buffer16(cloned_buffer16))
             }
+            // Copies of allocated buffers are made with the Rust allocator.
+            Pixels::Allocated(buffer) => {
+                let mut cloned_buffer: Vec<u8> = vec![];
+                cloned_buffer
+                    .try_reserve_exact(buffer.len())
+                    .or(Err(AvifError::OutOfMemory))?;
+                cloned_buffer.extend_from_slice(buffer.as_slice());
+                Ok(Pixels::Buffer(cloned_buffer))
+            }
+            Pixels::Allocated16(buffer16) => {
+                let mut cloned_buffer16: Vec<u16> = vec![];
+                cloned_buffer16
+                    .try_reserve_exact(buffer16.len())
+                    .or(Err(AvifError::OutOfMemory))?;
+                cloned_buffer16.extend_from_slice(buffer16.as_slice());
+                Ok(Pixels::Buffer16(cloned_buffer16))
+            }
         }
     }
 
@@ -231,6 +358,11 @@ impl Pixels {
                 Ok(&buffer[range])
             }
             Pixels::Buffer16(_) => Err(AvifError::NoContent),
+            Pixels::Allocated(buffer) => {
+                let end = offset.checked_add(size).ok_or(AvifError::NoContent)?;
+                buffer.slice(offset..end)
+            }
+            Pixels::Allocated16(_) => Err(AvifError::NoContent),
         }
     }
 
@@ -250,6 +382,11 @@ impl Pixels {
                 Ok(&mut buffer[range])
             }
             Pixels::Buffer16(_) => Err(AvifError::NoContent),
+            Pixels::Allocated(buffer) => {
+                let end = offset.checked_add(size).ok_or(AvifError::NoContent)?;
+                buffer.slice_mut(offset..end)
+            }
+            Pixels::Allocated16(_) => Err(AvifError::NoContent),
         }
     }
 
@@ -269,6 +406,11 @@ impl Pixels {
                 check_slice_range(buffer.len(), &range)?;
                 Ok(&buffer[range])
             }
+            Pixels::Allocated(_) => Err(AvifError::NoContent),
+            Pixels::Allocated16(buffer) => {
+                let end = offset.checked_add(size).ok_or(AvifError::NoContent)?;
+                buffer.slice(offset..end)
+            }
         }
     }
 
@@ -288,6 +430,11 @@ impl Pixels {
                 check_slice_range(buffer.len(), &range)?;
                 Ok(&mut buffer[range])
             }
+            Pixels::Allocated(_) => Err(AvifError::NoContent),
+            Pixels::Allocated16(buffer) => {
+                let end = offset.checked_add(size).ok_or(AvifError::NoContent)?;
+                buffer.slice_mut(offset..end)
+            }
         }
     }
 }
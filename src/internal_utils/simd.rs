@@ -0,0 +1,240 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Vectorizable versions of the limited to full range expansions of RangeConversion. The scalar
+// versions divide every sample by the size of the limited range, which keeps compilers from
+// vectorizing the loop. Here the division is replaced by a multiplication and a shift that give
+// the same quotient for every input (checked exhaustively by the tests below), the clamping is
+// branch free, and rows are processed in chunks of LANES samples. This lets the compiler emit
+// the SIMD instructions of the target without unsafe code or runtime feature detection. The
+// samples left over after the last full chunk go through the same arithmetic one at a time.
+
+const LANES: usize = 16;
+
+// Large enough for floor(n * reciprocal(d) / 2^SHIFT) to be floor(n / d) for all n < 2^24 and
+// d < 2^12, which covers the numerators and ranges of depths up to 12.
+const SHIFT: u32 = 36;
+
+#[derive(Clone, Copy)]
+struct Range {
+    min: u32,
+    max: u32,
+    full: u32,
+}
+
+fn y_range(depth: u8) -> Option<Range> {
+    match depth {
+        8 => Some(Range {
+            min: 16,
+            max: 235,
+            full: 255,
+        }),
+        10 => Some(Range {
+            min: 64,
+            max: 940,
+            full: 1023,
+        }),
+        12 => Some(Range {
+            min: 256,
+            max: 3760,
+            full: 4095,
+        }),
+        _ => None,
+    }
+}
+
+fn uv_range(depth: u8) -> Option<Range> {
+    match depth {
+        8 => Some(Range {
+            min: 16,
+            max: 240,
+            full: 255,
+        }),
+        10 => Some(Range {
+            min: 64,
+            max: 960,
+            full: 1023,
+        }),
+        12 => Some(Range {
+            min: 256,
+            max: 3840,
+            full: 4095,
+        }),
+        _ => None,
+    }
+}
+
+fn reciprocal(d: u32) -> u64 {
+    (1u64 << SHIFT) / d as u64 + 1
+}
+
+#[inline(always)]
+fn divide(n: u32, reciprocal: u64) -> u32 {
+    ((n as u64 * reciprocal) >> SHIFT) as u32
+}
+
+// Samples below min (above max) expand to 0 (full) in the scalar version, as min (max) does.
+fn expand(range: Range) -> impl Fn(u32) -> u32 {
+    let d = range.max - range.min;
+    let reciprocal = reciprocal(d);
+    move |v| {
+        divide(
+            (v.clamp(range.min, range.max) - range.min) * range.full + d / 2,
+            reciprocal,
+        )
+    }
+}
+
+// The expansion is symmetric around bias, with the rounding of div_round(). Samples above full
+// expand to full in the scalar version, as full does.
+fn expand_centered(range: Range) -> impl Fn(u32) -> u32 {
+    let d = range.max - range.min;
+    let reciprocal = reciprocal(d);
+    let bias = range.full.div_ceil(2);
+    move |v| {
+        let v = v.min(range.full);
+        let magnitude = divide(v.abs_diff(bias) * range.full + d / 2, reciprocal);
+        let expanded = if v >= bias { bias + magnitude } else { bias.saturating_sub(magnitude) };
+        expanded.min(range.full)
+    }
+}
+
+#[inline(always)]
+fn map_row<T: Copy + Into<u32>>(row: &mut [T], f: impl Fn(u32) -> T) {
+    let mut chunks = row.chunks_exact_mut(LANES);
+    for chunk in &mut chunks {
+        let mut values = [0u32; LANES];
+        for (value, sample) in values.iter_mut().zip(chunk.iter()) {
+            *value = (*sample).into();
+        }
+        for (sample, value) in chunk.iter_mut().zip(values) {
+            *sample = f(value);
+        }
+    }
+    for sample in chunks.into_remainder() {
+        *sample = f((*sample).into());
+    }
+}
+
+pub(crate) fn limited_to_full_y8(row: &mut [u8]) {
+    let f = expand(y_range(8).unwrap());
+    map_row(row, |v| f(v) as u8);
+}
+
+pub(crate) fn limited_to_full_uv8(row: &mut [u8]) {
+    let f = expand_centered(uv_range(8).unwrap());
+    map_row(row, |v| f(v) as u8);
+}
+
+// Other depths are mapped to 0, as in the scalar version.
+pub(crate) fn limited_to_full_y16(depth: u8, row: &mut [u16]) {
+    match y_range(depth) {
+        Some(range) => {
+            let f = expand(range);
+            map_row(row, |v| f(v) as u16);
+        }
+        None => row.fill(0),
+    }
+}
+
+pub(crate) fn limited_to_full_uv16(depth: u8, row: &mut [u16]) {
+    match uv_range(depth) {
+        Some(range) => {
+            let f = expand_centered(range);
+            map_row(row, |v| f(v) as u16);
+        }
+        None => row.fill(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal_utils::*;
+
+    // Returns pseudo-random samples so that neighbouring lanes hold unrelated values.
+    fn samples(count: usize, seed: u32) -> Vec<u16> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn all_8bit_values() {
+        let values: Vec<u8> = (0..=255).collect();
+        let mut y = values.clone();
+        limited_to_full_y8(&mut y);
+        let mut uv = values.clone();
+        limited_to_full_uv8(&mut uv);
+        for v in values {
+            assert_eq!(y[v as usize] as u16, limited_to_full_y(8, v as u16), "{v}");
+            assert_eq!(
+                uv[v as usize] as u16,
+                limited_to_full_uv(8, v as u16),
+                "{v}"
+            );
+        }
+    }
+
+    // Covers the whole u16 domain, including the values above the maximum of the depth.
+    #[test_case::test_case(8)]
+    #[test_case::test_case(10)]
+    #[test_case::test_case(12)]
+    #[test_case::test_case(16)]
+    fn all_16bit_values(depth: u8) {
+        let values: Vec<u16> = (0..=u16::MAX).collect();
+        let mut y = values.clone();
+        limited_to_full_y16(depth, &mut y);
+        let mut uv = values.clone();
+        limited_to_full_uv16(depth, &mut uv);
+        for v in values {
+            assert_eq!(y[v as usize], limited_to_full_y(depth, v), "{v}");
+            assert_eq!(uv[v as usize], limited_to_full_uv(depth, v), "{v}");
+        }
+    }
+
+    // Row lengths around multiples of LANES, so that both the chunks and the leftover samples
+    // are compared with the scalar version.
+    #[test]
+    fn row_lengths() {
+        for length in 0..=3 * LANES + 1 {
+            let row = samples(length, length as u32);
+            for depth in [10, 12] {
+                let max = (1u16 << depth) - 1;
+                let row: Vec<u16> = row.iter().map(|v| v & max).collect();
+                let mut y = row.clone();
+                limited_to_full_y16(depth, &mut y);
+                let mut uv = row.clone();
+                limited_to_full_uv16(depth, &mut uv);
+                for i in 0..length {
+                    assert_eq!(y[i], limited_to_full_y(depth, row[i]));
+                    assert_eq!(uv[i], limited_to_full_uv(depth, row[i]));
+                }
+            }
+            let row8: Vec<u8> = row.iter().map(|v| *v as u8).collect();
+            let mut y = row8.clone();
+            limited_to_full_y8(&mut y);
+            let mut uv = row8.clone();
+            limited_to_full_uv8(&mut uv);
+            for i in 0..length {
+                assert_eq!(y[i] as u16, limited_to_full_y(8, row8[i] as u16));
+                assert_eq!(uv[i] as u16, limited_to_full_uv(8, row8[i] as u16));
+            }
+        }
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::internal_utils::*;
+use crate::parser::limits::MAX_BOX_DEPTH;
 use crate::parser::mp4box::BoxSize;
 
 #[derive(Debug)]
@@ -77,11 +78,17 @@ impl IBitStream<'_> {
 pub struct IStream<'a> {
     pub data: &'a [u8],
     pub offset: usize,
+    // Number of sub_stream() calls that led to this stream.
+    pub depth: usize,
 }
 
 impl IStream<'_> {
     pub(crate) fn create(data: &[u8]) -> IStream {
-        IStream { data, offset: 0 }
+        IStream {
+            data,
+            offset: 0,
+            depth: 0,
+        }
     }
 
     fn check(&self, size: usize) -> AvifResult<()> {
@@ -92,6 +99,12 @@ impl IStream<'_> {
     }
 
     pub(crate) fn sub_stream(&mut self, size: &BoxSize) -> AvifResult<IStream> {
+        let depth = checked_add!(self.depth, 1)?;
+        if depth > MAX_BOX_DEPTH {
+            return Err(AvifError::BmffParseFailed(format!(
+                "box nesting depth exceeds MAX_BOX_DEPTH ({MAX_BOX_DEPTH})"
+            )));
+        }
         let offset = self.offset;
         checked_incr!(
             self.offset,
@@ -106,6 +119,7 @@ impl IStream<'_> {
         Ok(IStream {
             data: &self.data[offset..self.offset],
             offset: 0,
+            depth,
         })
     }
 
@@ -327,4 +341,24 @@ mod tests {
         ));
         assert_eq!(IStream::create(bytes).read_c_string(), Ok("abcd".into()));
     }
+
+    fn nest(stream: &mut IStream, levels: usize) -> AvifResult<()> {
+        if levels == 0 {
+            return Ok(());
+        }
+        let depth = stream.depth;
+        let mut sub_stream = stream.sub_stream(&BoxSize::UntilEndOfStream)?;
+        assert_eq!(sub_stream.depth, depth + 1);
+        nest(&mut sub_stream, levels - 1)
+    }
+
+    #[test]
+    fn sub_stream_depth() {
+        let data = [0u8; 8];
+        assert!(nest(&mut IStream::create(&data), MAX_BOX_DEPTH).is_ok());
+        assert!(matches!(
+            nest(&mut IStream::create(&data), MAX_BOX_DEPTH + 1),
+            Err(AvifError::BmffParseFailed(_))
+        ));
+    }
 }
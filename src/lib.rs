@@ -21,6 +21,7 @@ pub mod decoder;
 pub mod image;
 pub mod reformat;
 pub mod utils;
+pub mod validate;
 
 #[cfg(feature = "capi")]
 pub mod capi;
@@ -120,6 +121,7 @@ impl ChromaSamplePosition {
     // The AV1 Specification (Version 1.0.0 with Errata 1) does not have a CSP_CENTER value
     // for chroma_sample_position, so we are forced to signal CSP_UNKNOWN in the AV1 bitstream
     // when the chroma sample position is CENTER.
+    #[cfg(feature = "conversion")]
     const CENTER: ChromaSamplePosition = ChromaSamplePosition::Unknown; // JPEG/"center"
 }
 
@@ -330,8 +332,23 @@ pub enum AvifError {
 
 pub type AvifResult<T> = Result<T, AvifError>;
 
+impl AvifError {
+    // Returns true for the errors that only concern the sample of one image, such as a bitstream
+    // error reported by the codec for a corrupt frame of a sequence. After such an error from
+    // Decoder::next_image(), Decoder::skip_current_image() allows decoding to continue with the
+    // following images. Other errors (parsing, IO, memory, settings) are fatal for the decoder.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            AvifError::DecodeColorFailed
+                | AvifError::DecodeAlphaFailed
+                | AvifError::DecodeGainMapFailed
+        )
+    }
+}
+
 #[repr(i32)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum AndroidMediaCodecOutputColorFormat {
     // Flexible YUV 420 format used for 8-bit images:
     // https://developer.android.com/reference/android/media/MediaCodecInfo.CodecCapabilities#COLOR_FormatYUV420Flexible
@@ -0,0 +1,28 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Limits enforced while parsing the boxes of a file. Valid files stay far below them. They only
+// bound the amount of work and memory spent on malformed or malicious files, and can be lowered
+// when fuzzing.
+
+// Maximum nesting depth of a box below a top-level box.
+pub const MAX_BOX_DEPTH: usize = 32;
+// Maximum number of entries in an iinf box.
+pub const MAX_ITEM_COUNT: usize = 65536;
+// Maximum number of properties in an ipco box.
+pub const MAX_PROPERTY_COUNT: usize = 65536;
+// Maximum number of property associations across all the ipma boxes of a meta box.
+pub const MAX_PROPERTY_ASSOCIATION_COUNT: usize = 1 << 20;
+// Maximum number of item references in an iref box.
+pub const MAX_ITEM_REFERENCE_COUNT: usize = 1 << 20;
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::decoder::gainmap::GainMapMetadata;
+use crate::decoder::region::*;
 use crate::decoder::track::*;
 use crate::decoder::Extent;
 use crate::decoder::GenericIO;
@@ -20,9 +21,12 @@ use crate::image::YuvRange;
 use crate::image::MAX_PLANE_COUNT;
 use crate::internal_utils::stream::*;
 use crate::internal_utils::*;
+use crate::parser::limits::*;
 use crate::utils::clap::CleanAperture;
 use crate::*;
 
+use std::ops::Range;
+
 #[derive(Debug, PartialEq)]
 pub enum BoxSize {
     FixedSize(usize), // In bytes, header exclusive.
@@ -67,6 +71,14 @@ impl FileTypeBox {
         brands.iter().any(|brand| self.has_brand(brand))
     }
 
+    // The generic MIAF still image brands do not identify the codec. The primary item may be
+    // AV1 or, with the heic feature, HEVC. Files that also claim an HEVC brand are not accepted
+    // without the heic feature.
+    fn has_miaf_brand(&self) -> bool {
+        self.has_brand_any(&["mif1", "mif2"])
+            && (cfg!(feature = "heic") || !self.has_brand_any(&["heic", "heix", "heim", "heis"]))
+    }
+
     pub(crate) fn is_avif(&self) -> bool {
         // "avio" also exists but does not identify the file as AVIF on its own. See
         // https://aomediacodec.github.io/av1-avif/v1.1.0.html#image-and-image-collection-brand
@@ -77,9 +89,7 @@ impl FileTypeBox {
             "heic",
             #[cfg(feature = "heic")]
             "heix",
-            #[cfg(feature = "heic")]
-            "mif1",
-        ])
+        ]) || self.has_miaf_brand()
     }
 
     pub(crate) fn needs_meta(&self) -> bool {
@@ -89,9 +99,7 @@ impl FileTypeBox {
             "heic",
             #[cfg(feature = "heic")]
             "heix",
-            #[cfg(feature = "heic")]
-            "mif1",
-        ])
+        ]) || self.has_miaf_brand()
     }
 
     pub(crate) fn needs_moov(&self) -> bool {
@@ -154,11 +162,46 @@ pub struct Av1CodecConfiguration {
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct HevcCodecConfiguration {
+    pub general_profile_idc: u8,
+    pub general_tier_flag: bool,
+    pub general_level_idc: u8,
+    // 0 for monochrome, 1 for 4:2:0, 2 for 4:2:2 and 3 for 4:4:4.
+    pub chroma_format_idc: u8,
     pub bitdepth: u8,
+    pub bitdepth_chroma: u8,
     pub nal_length_size: u8,
-    pub vps: Vec<u8>,
-    pub sps: Vec<u8>,
-    pub pps: Vec<u8>,
+    // Each parameter set array may contain more than one NAL unit.
+    pub vps: Vec<Vec<u8>>,
+    pub sps: Vec<Vec<u8>>,
+    pub pps: Vec<Vec<u8>>,
+    // SEI NAL units, in the order they appear in the hvcC box.
+    pub sei: Vec<Vec<u8>>,
+    // NAL unit types of the arrays that were ignored because they are not supported.
+    pub skipped_nal_unit_types: Vec<u8>,
+}
+
+impl HevcCodecConfiguration {
+    // Returns the codec specific data expected by MediaCodec: all the VPS, SPS and PPS NAL units
+    // in that order, followed by the SEI NAL units. Each unit is preceded by a start code of
+    // "\x00\x00\x00\x01".
+    // https://developer.android.com/reference/android/media/MediaCodec#CSD
+    #[cfg(any(feature = "android_mediacodec", test))]
+    pub(crate) fn codec_specific_data(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        for nal_unit in self
+            .vps
+            .iter()
+            .chain(&self.sps)
+            .chain(&self.pps)
+            .chain(&self.sei)
+        {
+            // Start code.
+            data.extend_from_slice(&[0, 0, 0, 1]);
+            // Data.
+            data.extend_from_slice(&nal_unit[..]);
+        }
+        data
+    }
 }
 
 impl CodecConfiguration {
@@ -188,13 +231,14 @@ impl CodecConfiguration {
                     PixelFormat::Yuv444
                 }
             }
-            Self::Hevc(_) => {
-                // It is okay to always return Yuv420 here since that is the only format that
-                // android_mediacodec returns.
-                // TODO: b/370549923 - Identify the correct YUV subsampling type from the codec
-                // configuration data.
-                PixelFormat::Yuv420
-            }
+            // This is the format of the coded image. The frames decoded by android_mediacodec
+            // are always 4:2:0 since that is the only format that it returns.
+            Self::Hevc(config) => match config.chroma_format_idc {
+                0 => PixelFormat::Yuv400,
+                1 => PixelFormat::Yuv420,
+                2 => PixelFormat::Yuv422,
+                _ => PixelFormat::Yuv444,
+            },
         }
     }
 
@@ -215,31 +259,40 @@ impl CodecConfiguration {
     pub(crate) fn raw_data(&self) -> Vec<u8> {
         match self {
             Self::Av1(config) => config.raw_data.clone(),
-            Self::Hevc(config) => {
-                // For HEVC, the codec specific data consists of the following 3 NAL units in
-                // order: VPS, SPS and PPS. Each unit should be preceded by a start code of
-                // "\x00\x00\x00\x01".
-                // https://developer.android.com/reference/android/media/MediaCodec#CSD
-                let mut data: Vec<u8> = Vec::new();
-                for nal_unit in [&config.vps, &config.sps, &config.pps] {
-                    // Start code.
-                    data.extend_from_slice(&[0, 0, 0, 1]);
-                    // Data.
-                    data.extend_from_slice(&nal_unit[..]);
-                }
-                data
-            }
+            Self::Hevc(config) => config.codec_specific_data(),
         }
     }
 
     pub fn profile(&self) -> u8 {
         match self {
             Self::Av1(config) => config.seq_profile,
-            Self::Hevc(_) => {
-                // TODO: b/370549923 - Identify the correct profile from the codec configuration
-                // data.
-                0
-            }
+            Self::Hevc(config) => config.general_profile_idc,
+        }
+    }
+
+    pub fn level(&self) -> u8 {
+        match self {
+            Self::Av1(config) => config.seq_level_idx0,
+            Self::Hevc(config) => config.general_level_idc,
+        }
+    }
+
+    // Returns the (MaxPicSize, MaxHSize, MaxVSize) limits of the level of an AV1 configuration,
+    // from Annex A.3 of the AV1 specification. Returns None for HEVC, for the reserved levels and
+    // for level 31, which has no limits.
+    pub(crate) fn max_picture_size(&self) -> Option<(u32, u32, u32)> {
+        match self {
+            Self::Av1(config) => match config.seq_level_idx0 {
+                0 => Some((147456, 2048, 1152)),
+                1 => Some((278784, 2816, 1584)),
+                4 => Some((665856, 4352, 2448)),
+                5 => Some((1065024, 5504, 3096)),
+                8 | 9 => Some((2359296, 6144, 3456)),
+                12..=15 => Some((8912896, 8192, 4352)),
+                16..=19 => Some((35651584, 16384, 8704)),
+                _ => None,
+            },
+            Self::Hevc(_) => None,
         }
     }
 
@@ -270,7 +323,12 @@ pub struct Nclx {
 
 #[derive(Clone, Debug)]
 pub enum ColorInformation {
-    Icc(Vec<u8>),
+    // color_type is "rICC" (restricted to monochrome or three-component matrix-based profiles)
+    // or "prof" (unrestricted).
+    Icc {
+        color_type: String,
+        profile: Vec<u8>,
+    },
     Nclx(Nclx),
     Unknown,
 }
@@ -291,6 +349,28 @@ pub struct ContentLightLevelInformation {
     pub max_pall: u16,
 }
 
+// TAIClockInfoBox, Section 5.2 of ISO/IEC 23001-17 Amd 2.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TaiClockInfo {
+    // Uncertainty of the clock in nanoseconds.
+    pub time_uncertainty: u64,
+    // Resolution of the clock in nanoseconds.
+    pub clock_resolution: u32,
+    // Difference between the synchronized and the free running clock in parts per billion.
+    pub clock_drift_rate: i32,
+    pub clock_type: u8,
+}
+
+// TAITimestampBox, Section 5.3 of ISO/IEC 23001-17 Amd 2.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TaiTimestamp {
+    // Nanoseconds since the TAI epoch (1958-01-01T00:00:00).
+    pub tai_timestamp: u64,
+    pub synchronization_state: bool,
+    pub timestamp_generation_failure: bool,
+    pub timestamp_is_modified: bool,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum CodecConfiguration {
     Av1(Av1CodecConfiguration),
@@ -318,6 +398,8 @@ pub enum ItemProperty {
     LayerSelector(u16),
     AV1LayeredImageIndexing([usize; 3]),
     ContentLightLevelInformation(ContentLightLevelInformation),
+    TaiClockInfo(TaiClockInfo),
+    TaiTimestamp(TaiTimestamp),
     Unknown(String),
 }
 
@@ -356,6 +438,13 @@ pub struct ItemReference {
     pub index: u32, // 0-based index of the reference within the iref type.
 }
 
+#[derive(Debug)]
+pub struct EntityGroup {
+    pub grouping_type: String, // For example "altr" for a group of alternatives.
+    pub group_id: u32,
+    pub entity_ids: Vec<u32>,
+}
+
 #[derive(Debug, Default)]
 pub struct MetaBox {
     pub iinf: Vec<ItemInfo>,
@@ -364,6 +453,7 @@ pub struct MetaBox {
     pub iprp: ItemPropertyBox,
     pub iref: Vec<ItemReference>,
     pub idat: Vec<u8>,
+    pub grpl: Vec<EntityGroup>,
 }
 
 #[derive(Debug)]
@@ -371,6 +461,9 @@ pub struct AvifBoxes {
     pub ftyp: FileTypeBox,
     pub meta: MetaBox,
     pub tracks: Vec<Track>,
+    pub movie_times: Option<MediaTimes>,
+    // Byte ranges (including the box headers) of the parsed top level ftyp, meta and moov boxes.
+    pub box_ranges: Vec<(String, Range<u64>)>,
 }
 
 fn parse_header(stream: &mut IStream, top_level: bool) -> AvifResult<BoxHeader> {
@@ -585,13 +678,20 @@ fn parse_iloc(stream: &mut IStream) -> AvifResult<ItemLocationBox> {
         entry.extent_count = stream.read_u16()?;
         for _j in 0..entry.extent_count {
             // unsigned int(index_size*8) item_reference_index;
-            stream.skip(iloc.index_size as usize)?; // Only used for construction_method 2.
-            let extent = Extent {
-                // unsigned int(offset_size*8) extent_offset;
-                offset: stream.read_uxx(iloc.offset_size)?,
-                // unsigned int(length_size*8) extent_length;
-                size: usize_from_u64(stream.read_uxx(iloc.length_size)?)?,
-            };
+            // Only used for construction_method 2.
+            stream.skip(iloc.index_size as usize)?;
+            // unsigned int(offset_size*8) extent_offset;
+            let offset = stream.read_uxx(iloc.offset_size)?;
+            // unsigned int(length_size*8) extent_length;
+            let length = stream.read_uxx(iloc.length_size)?;
+            // Offsets are kept as u64 but extents are read into memory, so their length must fit
+            // in usize (which matters on 32-bit targets).
+            let size = usize::try_from(length).map_err(|_| {
+                AvifError::BmffParseFailed(format!(
+                    "Box[iloc] has an extent length too large for this platform: {length}"
+                ))
+            })?;
+            let extent = Extent { offset, size };
             entry.extents.push(extent);
         }
         iloc.items.push(entry);
@@ -740,65 +840,86 @@ fn parse_hvcC(stream: &mut IStream) -> AvifResult<ItemProperty> {
     }
     let mut bits = stream.sub_bit_stream(21)?;
     // unsigned int(2) general_profile_space;
+    bits.skip(2)?;
     // unsigned int(1) general_tier_flag;
+    let general_tier_flag = bits.read_bool()?;
     // unsigned int(5) general_profile_idc;
+    let general_profile_idc = bits.read(5)? as u8;
     // unsigned int(32) general_profile_compatibility_flags;
     // unsigned int(48) general_constraint_indicator_flags;
+    bits.skip(32 + 48)?;
     // unsigned int(8) general_level_idc;
+    let general_level_idc = bits.read(8)? as u8;
     // bit(4) reserved = '1111'b;
     // unsigned int(12) min_spatial_segmentation_idc;
     // bit(6) reserved = '111111'b;
     // unsigned int(2) parallelismType;
     // bit(6) reserved = '111111'b;
+    bits.skip(4 + 12 + 6 + 2 + 6)?;
     // unsigned int(2) chroma_format_idc;
+    let chroma_format_idc = bits.read(2)? as u8;
     // bit(5) reserved = '11111'b;
-    bits.skip(2 + 1 + 5 + 32 + 48 + 8 + 4 + 12 + 6 + 2 + 6 + 2 + 5)?;
+    bits.skip(5)?;
     // unsigned int(3) bit_depth_luma_minus8;
     let bitdepth = bits.read(3)? as u8 + 8;
     // bit(5) reserved = '11111'b;
+    bits.skip(5)?;
     // unsigned int(3) bit_depth_chroma_minus8;
+    let bitdepth_chroma = bits.read(3)? as u8 + 8;
     // unsigned int(16) avgFrameRate;
     // unsigned int(2) constantFrameRate;
     // unsigned int(3) numTemporalLayers;
     // unsigned int(1) temporalIdNested;
-    bits.skip(5 + 3 + 16 + 2 + 3 + 1)?;
+    bits.skip(16 + 2 + 3 + 1)?;
     // unsigned int(2) lengthSizeMinusOne;
     let nal_length_size = 1 + bits.read(2)? as u8;
     assert!(bits.remaining_bits()? == 0);
+    // ISO/IEC 14496-15, Section 8.3.3.1.2: The value 2 is not allowed for lengthSizeMinusOne.
+    if nal_length_size == 3 {
+        return Err(AvifError::BmffParseFailed(
+            "Invalid lengthSizeMinusOne(2) in hvcC".into(),
+        ));
+    }
 
+    let mut hvcc = HevcCodecConfiguration {
+        general_profile_idc,
+        general_tier_flag,
+        general_level_idc,
+        chroma_format_idc,
+        bitdepth,
+        bitdepth_chroma,
+        nal_length_size,
+        ..Default::default()
+    };
     // unsigned int(8) numOfArrays;
     let num_of_arrays = stream.read_u8()?;
-    let mut vps: Vec<u8> = Vec::new();
-    let mut sps: Vec<u8> = Vec::new();
-    let mut pps: Vec<u8> = Vec::new();
     for _i in 0..num_of_arrays {
         // unsigned int(1) array_completeness;
         // bit(1) reserved = 0;
         // unsigned int(6) NAL_unit_type;
-        stream.skip(1)?;
+        let nal_unit_type = stream.read_u8()? & 0x3f;
         // unsigned int(16) numNalus;
         let num_nalus = stream.read_u16()?;
+        let mut nal_units = Vec::new();
         for _j in 0..num_nalus {
             // unsigned int(16) nalUnitLength;
             let nal_unit_length = stream.read_u16()?;
             let nal_unit = stream.get_slice(nal_unit_length as usize)?;
-            let nal_unit_type = (nal_unit[0] >> 1) & 0x3f;
-            match nal_unit_type {
-                32 => vps = nal_unit.to_vec(),
-                33 => sps = nal_unit.to_vec(),
-                34 => pps = nal_unit.to_vec(),
-                _ => {}
+            if !nal_unit.is_empty() {
+                nal_units.push(nal_unit.to_vec());
             }
         }
+        match nal_unit_type {
+            32 => hvcc.vps.extend(nal_units),
+            33 => hvcc.sps.extend(nal_units),
+            34 => hvcc.pps.extend(nal_units),
+            // Prefix and suffix SEI.
+            39 | 40 => hvcc.sei.extend(nal_units),
+            _ => hvcc.skipped_nal_unit_types.push(nal_unit_type),
+        }
     }
     Ok(ItemProperty::CodecConfiguration(CodecConfiguration::Hevc(
-        HevcCodecConfiguration {
-            bitdepth,
-            nal_length_size,
-            vps,
-            pps,
-            sps,
-        },
+        hvcc,
     )))
 }
 
@@ -819,9 +940,10 @@ fn parse_colr(stream: &mut IStream) -> AvifResult<ItemProperty> {
             )));
         }
         // ICC_profile; // restricted ("rICC") or unrestricted ("prof") ICC profile
-        return Ok(ItemProperty::ColorInformation(ColorInformation::Icc(
-            stream.get_slice(stream.bytes_left()?)?.to_vec(),
-        )));
+        return Ok(ItemProperty::ColorInformation(ColorInformation::Icc {
+            profile: stream.get_slice(stream.bytes_left()?)?.to_vec(),
+            color_type,
+        }));
     }
     if color_type == "nclx" {
         let mut nclx = Nclx {
@@ -980,10 +1102,59 @@ fn parse_clli(stream: &mut IStream) -> AvifResult<ItemProperty> {
     Ok(ItemProperty::ContentLightLevelInformation(clli))
 }
 
+fn parse_taic(stream: &mut IStream) -> AvifResult<ItemProperty> {
+    // Section 5.2.2 of ISO/IEC 23001-17 Amd 2.
+    let (version, _flags) = stream.read_version_and_flags()?;
+    if version != 0 {
+        return Ok(ItemProperty::Unknown("taic".into()));
+    }
+    let mut taic = TaiClockInfo {
+        // unsigned int(64) time_uncertainty;
+        time_uncertainty: stream.read_u64()?,
+        // unsigned int(32) clock_resolution;
+        clock_resolution: stream.read_u32()?,
+        // signed int(32) clock_drift_rate;
+        clock_drift_rate: stream.read_i32()?,
+        ..Default::default()
+    };
+    let mut bits = stream.sub_bit_stream(1)?;
+    // unsigned int(2) clock_type;
+    taic.clock_type = bits.read(2)? as u8;
+    // bit(6) reserved = 0;
+    Ok(ItemProperty::TaiClockInfo(taic))
+}
+
+fn parse_itai(stream: &mut IStream) -> AvifResult<ItemProperty> {
+    // Section 5.3.2 of ISO/IEC 23001-17 Amd 2.
+    let (version, _flags) = stream.read_version_and_flags()?;
+    if version != 0 {
+        return Ok(ItemProperty::Unknown("itai".into()));
+    }
+    let mut itai = TaiTimestamp {
+        // unsigned int(64) TAI_timestamp;
+        tai_timestamp: stream.read_u64()?,
+        ..Default::default()
+    };
+    let mut bits = stream.sub_bit_stream(1)?;
+    // unsigned int(1) synchronization_state;
+    itai.synchronization_state = bits.read_bool()?;
+    // unsigned int(1) timestamp_generation_failure;
+    itai.timestamp_generation_failure = bits.read_bool()?;
+    // unsigned int(1) timestamp_is_modified;
+    itai.timestamp_is_modified = bits.read_bool()?;
+    // bit(5) reserved = 0;
+    Ok(ItemProperty::TaiTimestamp(itai))
+}
+
 fn parse_ipco(stream: &mut IStream) -> AvifResult<Vec<ItemProperty>> {
     // Section 8.11.14.2 of ISO/IEC 14496-12.
     let mut properties: Vec<ItemProperty> = Vec::new();
     while stream.has_bytes_left()? {
+        if properties.len() == MAX_PROPERTY_COUNT {
+            return Err(AvifError::BmffParseFailed(format!(
+                "ipco property count exceeds MAX_PROPERTY_COUNT ({MAX_PROPERTY_COUNT})"
+            )));
+        }
         let header = parse_header(stream, /*top_level=*/ false)?;
         let mut sub_stream = stream.sub_stream(&header.size)?;
         match header.box_type.as_str() {
@@ -1000,6 +1171,8 @@ fn parse_ipco(stream: &mut IStream) -> AvifResult<Vec<ItemProperty>> {
             "lsel" => properties.push(parse_lsel(&mut sub_stream)?),
             "a1lx" => properties.push(parse_a1lx(&mut sub_stream)?),
             "clli" => properties.push(parse_clli(&mut sub_stream)?),
+            "taic" => properties.push(parse_taic(&mut sub_stream)?),
+            "itai" => properties.push(parse_itai(&mut sub_stream)?),
             #[cfg(feature = "heic")]
             "hvcC" => properties.push(parse_hvcC(&mut sub_stream)?),
             _ => properties.push(ItemProperty::Unknown(header.box_type)),
@@ -1013,6 +1186,11 @@ fn parse_ipma(stream: &mut IStream) -> AvifResult<Vec<ItemPropertyAssociation>>
     let (version, flags) = stream.read_version_and_flags()?;
     // unsigned int(32) entry_count;
     let entry_count = stream.read_u32()?;
+    if usize_from_u32(entry_count)? > MAX_ITEM_COUNT {
+        return Err(AvifError::BmffParseFailed(format!(
+            "ipma entry count ({entry_count}) exceeds MAX_ITEM_COUNT ({MAX_ITEM_COUNT})"
+        )));
+    }
     let mut ipma: Vec<ItemPropertyAssociation> = create_vec_exact(usize_from_u32(entry_count)?)?;
     for _i in 0..entry_count {
         let mut entry = ItemPropertyAssociation::default();
@@ -1075,6 +1253,7 @@ fn parse_iprp(stream: &mut IStream) -> AvifResult<ItemPropertyBox> {
         iprp.properties = parse_ipco(&mut sub_stream)?;
     }
     // Parse ipma boxes.
+    let mut association_count: usize = 0;
     while stream.has_bytes_left()? {
         let header = parse_header(stream, /*top_level=*/ false)?;
         if header.box_type != "ipma" {
@@ -1083,7 +1262,17 @@ fn parse_iprp(stream: &mut IStream) -> AvifResult<ItemPropertyBox> {
             ));
         }
         let mut sub_stream = stream.sub_stream(&header.size)?;
-        iprp.associations.append(&mut parse_ipma(&mut sub_stream)?);
+        let mut ipma = parse_ipma(&mut sub_stream)?;
+        for entry in &ipma {
+            checked_incr!(association_count, entry.associations.len());
+        }
+        iprp.associations.append(&mut ipma);
+        if association_count > MAX_PROPERTY_ASSOCIATION_COUNT {
+            return Err(AvifError::BmffParseFailed(format!(
+                "ipma association count exceeds MAX_PROPERTY_ASSOCIATION_COUNT \
+                 ({MAX_PROPERTY_ASSOCIATION_COUNT})"
+            )));
+        }
     }
     Ok(iprp)
 }
@@ -1155,6 +1344,11 @@ fn parse_iinf(stream: &mut IStream) -> AvifResult<Vec<ItemInfo>> {
         // unsigned int(32) entry_count;
         stream.read_u32()?
     };
+    if usize_from_u32(entry_count)? > MAX_ITEM_COUNT {
+        return Err(AvifError::BmffParseFailed(format!(
+            "iinf entry count ({entry_count}) exceeds MAX_ITEM_COUNT ({MAX_ITEM_COUNT})"
+        )));
+    }
     let mut iinf: Vec<ItemInfo> = create_vec_exact(usize_from_u32(entry_count)?)?;
     for _i in 0..entry_count {
         let header = parse_header(stream, /*top_level=*/ false)?;
@@ -1206,6 +1400,12 @@ fn parse_iref(stream: &mut IStream) -> AvifResult<Vec<ItemReference>> {
                     "invalid to_item_id (0) in iref".into(),
                 ));
             }
+            if iref.len() == MAX_ITEM_REFERENCE_COUNT {
+                return Err(AvifError::BmffParseFailed(format!(
+                    "iref reference count exceeds MAX_ITEM_REFERENCE_COUNT \
+                     ({MAX_ITEM_REFERENCE_COUNT})"
+                )));
+            }
             iref.push(ItemReference {
                 from_item_id,
                 to_item_id,
@@ -1227,6 +1427,32 @@ fn parse_idat(stream: &mut IStream) -> AvifResult<Vec<u8>> {
     Ok(idat)
 }
 
+fn parse_grpl(stream: &mut IStream) -> AvifResult<Vec<EntityGroup>> {
+    // Section 8.18.3.2 of ISO/IEC 14496-12: GroupsListBox.
+    let mut groups = Vec::new();
+    while stream.has_bytes_left()? {
+        let header = parse_header(stream, /*top_level=*/ false)?;
+        let mut sub_stream = stream.sub_stream(&header.size)?;
+        // Section 8.18.3.2 of ISO/IEC 14496-12: EntityToGroupBox.
+        let (_version, _flags) = sub_stream.read_version_and_flags()?;
+        // unsigned int(32) group_id;
+        let group_id = sub_stream.read_u32()?;
+        // unsigned int(32) num_entities_in_group;
+        let num_entities_in_group = sub_stream.read_u32()?;
+        let mut entity_ids = Vec::new();
+        for _ in 0..num_entities_in_group {
+            // unsigned int(32) entity_id;
+            entity_ids.push(sub_stream.read_u32()?);
+        }
+        groups.push(EntityGroup {
+            grouping_type: header.box_type,
+            group_id,
+            entity_ids,
+        });
+    }
+    Ok(groups)
+}
+
 fn parse_meta(stream: &mut IStream) -> AvifResult<MetaBox> {
     // Section 8.11.1.2 of ISO/IEC 14496-12.
     let (_version, _flags) = stream.read_and_enforce_version_and_flags(0)?;
@@ -1248,7 +1474,12 @@ fn parse_meta(stream: &mut IStream) -> AvifResult<MetaBox> {
     while stream.has_bytes_left()? {
         let header = parse_header(stream, /*top_level=*/ false)?;
         match header.box_type.as_str() {
-            "hdlr" | "iloc" | "pitm" | "iprp" | "iinf" | "iref" | "idat" => {
+            // The entity groups are optional, so only the first grpl box is used.
+            "grpl" if boxes_seen.contains("grpl") => {
+                stream.sub_stream(&header.size)?;
+                continue;
+            }
+            "hdlr" | "iloc" | "pitm" | "iprp" | "iinf" | "iref" | "idat" | "grpl" => {
                 if boxes_seen.contains(&header.box_type) {
                     return Err(AvifError::BmffParseFailed(format!(
                         "duplicate {} box in meta.",
@@ -1267,20 +1498,57 @@ fn parse_meta(stream: &mut IStream) -> AvifResult<MetaBox> {
             "iinf" => meta.iinf = parse_iinf(&mut sub_stream)?,
             "iref" => meta.iref = parse_iref(&mut sub_stream)?,
             "idat" => meta.idat = parse_idat(&mut sub_stream)?,
+            "grpl" => meta.grpl = parse_grpl(&mut sub_stream)?,
             _ => {}
         }
     }
     Ok(meta)
 }
 
+// Number of seconds between 1904-01-01 and 1970-01-01 (both UTC), the epochs of the times stored
+// in ISO BMFF and of Unix time respectively.
+const ISO_BMFF_TO_UNIX_EPOCH_SECONDS: i64 = 2082844800;
+
+fn media_times(creation_time: u64, modification_time: u64) -> MediaTimes {
+    let to_unix = |time: u64| {
+        i64::try_from(time)
+            .unwrap_or(i64::MAX)
+            .saturating_sub(ISO_BMFF_TO_UNIX_EPOCH_SECONDS)
+    };
+    MediaTimes {
+        creation_time: to_unix(creation_time),
+        modification_time: to_unix(modification_time),
+    }
+}
+
+// Returns None for the versions of mvhd that are not known. The times are informative only, so
+// such boxes are ignored rather than failing the parsing of the file.
+fn parse_mvhd(stream: &mut IStream) -> AvifResult<Option<MediaTimes>> {
+    // Section 8.2.2.2 of ISO/IEC 14496-12.
+    let (version, _flags) = stream.read_version_and_flags()?;
+    // The remaining fields (timescale, duration, rate, volume, matrix and next_track_ID) are
+    // ignored.
+    match version {
+        // unsigned int(64) creation_time;
+        // unsigned int(64) modification_time;
+        1 => Ok(Some(media_times(stream.read_u64()?, stream.read_u64()?))),
+        // unsigned int(32) creation_time;
+        // unsigned int(32) modification_time;
+        0 => Ok(Some(media_times(
+            stream.read_u32()? as u64,
+            stream.read_u32()? as u64,
+        ))),
+        _ => Ok(None),
+    }
+}
+
 fn parse_tkhd(stream: &mut IStream, track: &mut Track) -> AvifResult<()> {
     // Section 8.3.2.2 of ISO/IEC 14496-12.
     let (version, _flags) = stream.read_version_and_flags()?;
     if version == 1 {
         // unsigned int(64) creation_time;
-        stream.skip_u64()?;
         // unsigned int(64) modification_time;
-        stream.skip_u64()?;
+        track.times = media_times(stream.read_u64()?, stream.read_u64()?);
         // unsigned int(32) track_ID;
         track.id = stream.read_u32()?;
         // const unsigned int(32) reserved = 0;
@@ -1293,9 +1561,8 @@ fn parse_tkhd(stream: &mut IStream, track: &mut Track) -> AvifResult<()> {
         track.track_duration = stream.read_u64()?;
     } else if version == 0 {
         // unsigned int(32) creation_time;
-        stream.skip_u32()?;
         // unsigned int(32) modification_time;
-        stream.skip_u32()?;
+        track.times = media_times(stream.read_u32()? as u64, stream.read_u32()? as u64);
         // unsigned int(32) track_ID;
         track.id = stream.read_u32()?;
         // const unsigned int(32) reserved = 0;
@@ -1338,12 +1605,6 @@ fn parse_tkhd(stream: &mut IStream, track: &mut Track) -> AvifResult<()> {
     track.width = stream.read_u32()? >> 16;
     // unsigned int(32) height;
     track.height = stream.read_u32()? >> 16;
-
-    if track.width == 0 || track.height == 0 {
-        return Err(AvifError::BmffParseFailed(
-            "invalid track dimensions".into(),
-        ));
-    }
     Ok(())
 }
 
@@ -1679,6 +1940,17 @@ fn parse_minf(stream: &mut IStream, track: &mut Track) -> AvifResult<()> {
     Ok(())
 }
 
+fn parse_track_hdlr(stream: &mut IStream, track: &mut Track) -> AvifResult<()> {
+    // Section 8.4.3.2 of ISO/IEC 14496-12.
+    let (_version, _flags) = stream.read_and_enforce_version_and_flags(0)?;
+    // unsigned int(32) pre_defined = 0;
+    stream.skip_u32()?;
+    // unsigned int(32) handler_type;
+    track.handler_type = stream.read_string(4)?;
+    // The remaining fields are not needed.
+    Ok(())
+}
+
 fn parse_mdia(stream: &mut IStream, track: &mut Track) -> AvifResult<()> {
     // Section 8.4.1.2 of ISO/IEC 14496-12.
     while stream.has_bytes_left()? {
@@ -1686,6 +1958,7 @@ fn parse_mdia(stream: &mut IStream, track: &mut Track) -> AvifResult<()> {
         let mut sub_stream = stream.sub_stream(&header.size)?;
         match header.box_type.as_str() {
             "mdhd" => parse_mdhd(&mut sub_stream, track)?,
+            "hdlr" => parse_track_hdlr(&mut sub_stream, track)?,
             "minf" => parse_minf(&mut sub_stream, track)?,
             _ => {}
         }
@@ -1832,17 +2105,27 @@ fn parse_trak(stream: &mut IStream) -> AvifResult<Track> {
             "trak box did not contain a tkhd box".into(),
         ));
     }
+    // Timed metadata tracks are not visual and may have zero dimensions.
+    if !track.is_metadata() && (track.width == 0 || track.height == 0) {
+        return Err(AvifError::BmffParseFailed(
+            "invalid track dimensions".into(),
+        ));
+    }
     Ok(track)
 }
 
-fn parse_moov(stream: &mut IStream) -> AvifResult<Vec<Track>> {
+fn parse_moov(stream: &mut IStream) -> AvifResult<(Vec<Track>, Option<MediaTimes>)> {
     let mut tracks: Vec<Track> = Vec::new();
+    let mut movie_times: Option<MediaTimes> = None;
     // Section 8.2.1.2 of ISO/IEC 14496-12.
     while stream.has_bytes_left()? {
         let header = parse_header(stream, /*top_level=*/ false)?;
         let mut sub_stream = stream.sub_stream(&header.size)?;
-        if header.box_type == "trak" {
-            tracks.push(parse_trak(&mut sub_stream)?);
+        match header.box_type.as_str() {
+            // Only the first mvhd box is used.
+            "mvhd" if movie_times.is_none() => movie_times = parse_mvhd(&mut sub_stream)?,
+            "trak" => tracks.push(parse_trak(&mut sub_stream)?),
+            _ => {}
         }
     }
     if tracks.is_empty() {
@@ -1850,13 +2133,15 @@ fn parse_moov(stream: &mut IStream) -> AvifResult<Vec<Track>> {
             "moov box does not contain any tracks".into(),
         ));
     }
-    Ok(tracks)
+    Ok((tracks, movie_times))
 }
 
 pub(crate) fn parse(io: &mut GenericIO) -> AvifResult<AvifBoxes> {
     let mut ftyp: Option<FileTypeBox> = None;
     let mut meta: Option<MetaBox> = None;
     let mut tracks: Option<Vec<Track>> = None;
+    let mut movie_times: Option<MediaTimes> = None;
+    let mut box_ranges: Vec<(String, Range<u64>)> = Vec::new();
     let mut parse_offset: u64 = 0;
     loop {
         // Read just enough to get the longest possible valid box header (4+4+8+16 bytes).
@@ -1867,6 +2152,7 @@ pub(crate) fn parse(io: &mut GenericIO) -> AvifResult<AvifBoxes> {
         }
         let mut header_stream = IStream::create(header_data);
         let header = parse_header(&mut header_stream, /*top_level=*/ true)?;
+        let box_offset = parse_offset;
         parse_offset = parse_offset
             .checked_add(header_stream.offset as u64)
             .ok_or(AvifError::BmffParseFailed("invalid parse offset".into()))?;
@@ -1887,6 +2173,7 @@ pub(crate) fn parse(io: &mut GenericIO) -> AvifResult<AvifBoxes> {
                     BoxSize::UntilEndOfStream => io.read(parse_offset, usize::MAX)?,
                     BoxSize::FixedSize(size) => io.read_exact(parse_offset, size)?,
                 };
+                let box_range = box_offset..checked_add!(parse_offset, box_data.len() as u64)?;
                 let mut box_stream = IStream::create(box_data);
                 match header.box_type.as_str() {
                     "ftyp" => {
@@ -1896,9 +2183,18 @@ pub(crate) fn parse(io: &mut GenericIO) -> AvifResult<AvifBoxes> {
                         }
                     }
                     "meta" => meta = Some(parse_meta(&mut box_stream)?),
-                    "moov" => tracks = Some(parse_moov(&mut box_stream)?),
+                    "moov" => {
+                        let (moov_tracks, moov_times) = parse_moov(&mut box_stream)?;
+                        tracks = Some(moov_tracks);
+                        movie_times = moov_times;
+                    }
                     _ => {} // Not reached.
                 }
+                // A box that goes until the end of the file also spans any item data that follows
+                // its payload, so only the boxes with an explicit size are recorded.
+                if header.size != BoxSize::UntilEndOfStream {
+                    box_ranges.push((header.box_type.clone(), box_range));
+                }
                 if ftyp.is_some() {
                     let ftyp = ftyp.unwrap_ref();
                     if (!ftyp.needs_meta() || meta.is_some())
@@ -1930,9 +2226,36 @@ pub(crate) fn parse(io: &mut GenericIO) -> AvifResult<AvifBoxes> {
         ftyp,
         meta: meta.unwrap_or_default(),
         tracks: tracks.unwrap_or_default(),
+        movie_times,
+        box_ranges,
     })
 }
 
+// Returns the payload ranges of the top-level MediaDataBoxes. The payload of a MediaDataBox that
+// goes until the end of the stream ends at u64::MAX.
+pub(crate) fn parse_mdat_ranges(io: &mut GenericIO) -> AvifResult<Vec<Range<u64>>> {
+    let mut mdat_ranges: Vec<Range<u64>> = Vec::new();
+    let mut parse_offset: u64 = 0;
+    while parse_offset < io.size_hint() {
+        let header_data = io.read(parse_offset, 32)?;
+        if header_data.is_empty() {
+            break;
+        }
+        let mut header_stream = IStream::create(header_data);
+        let header = parse_header(&mut header_stream, /*top_level=*/ true)?;
+        parse_offset = checked_add!(parse_offset, header_stream.offset as u64)?;
+        let payload_end = match header.size {
+            BoxSize::UntilEndOfStream => u64::MAX,
+            BoxSize::FixedSize(size) => checked_add!(parse_offset, size as u64)?,
+        };
+        if header.box_type == "mdat" {
+            mdat_ranges.push(parse_offset..payload_end);
+        }
+        parse_offset = payload_end;
+    }
+    Ok(mdat_ranges)
+}
+
 pub(crate) fn peek_compatible_file_type(data: &[u8]) -> AvifResult<bool> {
     let mut stream = IStream::create(data);
     let header = parse_header(&mut stream, /*top_level=*/ true)?;
@@ -1958,6 +2281,50 @@ pub(crate) fn peek_compatible_file_type(data: &[u8]) -> AvifResult<bool> {
     Ok(ftyp.is_avif())
 }
 
+// The first box after the FileTypeBox that may describe the primary image, as returned by
+// peek_meta_or_mdat().
+pub(crate) enum PeekedBox<'a> {
+    Meta(MetaBox),
+    // The available payload bytes of the MediaDataBox, which may be truncated.
+    MediaData(&'a [u8]),
+}
+
+// Best-effort parsing of the beginning of a file. Returns the MetaBox if it is fully contained in
+// data, or the available payload of the MediaDataBox if it comes before the MetaBox. Returns None
+// if data ends before either of them.
+pub(crate) fn peek_meta_or_mdat(data: &[u8]) -> AvifResult<Option<PeekedBox<'_>>> {
+    if !peek_compatible_file_type(data).unwrap_or(false) {
+        return Err(AvifError::InvalidFtyp);
+    }
+    let mut stream = IStream::create(data);
+    loop {
+        let header = match parse_header(&mut stream, /*top_level=*/ true) {
+            Ok(header) => header,
+            // Not enough data for the next box header.
+            Err(_) => return Ok(None),
+        };
+        let payload_size = match header.size {
+            BoxSize::FixedSize(size) => size,
+            BoxSize::UntilEndOfStream => stream.bytes_left()?,
+        };
+        let is_complete = payload_size <= stream.bytes_left()?;
+        match header.box_type.as_str() {
+            "meta" if is_complete => {
+                let mut sub_stream = stream.sub_stream(&header.size)?;
+                return Ok(Some(PeekedBox::Meta(parse_meta(&mut sub_stream)?)));
+            }
+            "mdat" => {
+                let available_size = std::cmp::min(payload_size, stream.bytes_left()?);
+                return Ok(Some(PeekedBox::MediaData(
+                    &data[stream.offset..stream.offset + available_size],
+                )));
+            }
+            _ if is_complete => stream.skip(payload_size)?,
+            _ => return Ok(None),
+        }
+    }
+}
+
 pub(crate) fn parse_tmap(stream: &mut IStream) -> AvifResult<Option<GainMapMetadata>> {
     // Experimental, not yet specified.
 
@@ -2025,11 +2392,168 @@ pub(crate) fn parse_tmap(stream: &mut IStream) -> AvifResult<Option<GainMapMetad
     Ok(Some(metadata))
 }
 
+pub(crate) fn parse_rgan(stream: &mut IStream) -> AvifResult<RegionAnnotation> {
+    // Section 6.10.1.1 of ISO/IEC 23008-12.
+
+    // unsigned int(8) version = 0;
+    let version = stream.read_u8()?;
+    if version != 0 {
+        return Err(AvifError::BmffParseFailed(
+            "unsupported region item version".into(),
+        ));
+    }
+    // unsigned int(8) flags;
+    let flags = stream.read_u8()?;
+    // field_size = ((flags & 1) + 1) * 16;
+    let field_bytes: u8 = if (flags & 1) == 1 { 4 } else { 2 };
+    let read_unsigned =
+        |stream: &mut IStream| -> AvifResult<u32> { Ok(stream.read_uxx(field_bytes)? as u32) };
+    let read_signed = |stream: &mut IStream| -> AvifResult<i32> {
+        if field_bytes == 4 {
+            stream.read_i32()
+        } else {
+            Ok(stream.read_i16()? as i32)
+        }
+    };
+    let mut region_annotation = RegionAnnotation {
+        // unsigned int(field_size) reference_width;
+        reference_width: read_unsigned(stream)?,
+        // unsigned int(field_size) reference_height;
+        reference_height: read_unsigned(stream)?,
+        ..Default::default()
+    };
+    if region_annotation.reference_width == 0 || region_annotation.reference_height == 0 {
+        return Err(AvifError::BmffParseFailed(
+            "invalid region item reference dimensions".into(),
+        ));
+    }
+    // unsigned int(8) region_count;
+    let region_count = stream.read_u8()?;
+    for _ in 0..region_count {
+        // unsigned int(8) geometry_type;
+        let geometry_type = stream.read_u8()?;
+        let geometry = match geometry_type {
+            0 => RegionGeometry::Point {
+                // signed int(field_size) x;
+                x: read_signed(stream)?,
+                // signed int(field_size) y;
+                y: read_signed(stream)?,
+            },
+            1 => RegionGeometry::Rectangle {
+                // signed int(field_size) x;
+                x: read_signed(stream)?,
+                // signed int(field_size) y;
+                y: read_signed(stream)?,
+                // unsigned int(field_size) width;
+                width: read_unsigned(stream)?,
+                // unsigned int(field_size) height;
+                height: read_unsigned(stream)?,
+            },
+            2 => RegionGeometry::Ellipse {
+                // signed int(field_size) x;
+                x: read_signed(stream)?,
+                // signed int(field_size) y;
+                y: read_signed(stream)?,
+                // unsigned int(field_size) radius_x;
+                radius_x: read_unsigned(stream)?,
+                // unsigned int(field_size) radius_y;
+                radius_y: read_unsigned(stream)?,
+            },
+            3 | 6 => {
+                // unsigned int(field_size) point_count;
+                let point_count = usize_from_u32(read_unsigned(stream)?)?;
+                let point_size = field_bytes as usize * 2;
+                if checked_mul!(point_count, point_size)? > stream.bytes_left()? {
+                    return Err(AvifError::BmffParseFailed(
+                        "not enough bytes for region polygon points".into(),
+                    ));
+                }
+                let mut points: Vec<(i32, i32)> = create_vec_exact(point_count)?;
+                for _ in 0..point_count {
+                    // signed int(field_size) px;
+                    // signed int(field_size) py;
+                    points.push((read_signed(stream)?, read_signed(stream)?));
+                }
+                if geometry_type == 3 {
+                    RegionGeometry::Polygon(points)
+                } else {
+                    RegionGeometry::Polyline(points)
+                }
+            }
+            4 => RegionGeometry::ReferencedMask {
+                // signed int(field_size) x;
+                x: read_signed(stream)?,
+                // signed int(field_size) y;
+                y: read_signed(stream)?,
+                // unsigned int(field_size) width;
+                width: read_unsigned(stream)?,
+                // unsigned int(field_size) height;
+                height: read_unsigned(stream)?,
+            },
+            5 => {
+                // signed int(field_size) x;
+                let x = read_signed(stream)?;
+                // signed int(field_size) y;
+                let y = read_signed(stream)?;
+                // unsigned int(field_size) width;
+                let width = read_unsigned(stream)?;
+                // unsigned int(field_size) height;
+                let height = read_unsigned(stream)?;
+                // unsigned int(8) mask_coding_method;
+                let mask_coding_method = stream.read_u8()?;
+                if mask_coding_method != 0 {
+                    // Only uncompressed masks are supported.
+                    return Err(AvifError::BmffParseFailed(
+                        "unsupported region mask coding method".into(),
+                    ));
+                }
+                // unsigned int(8) data[];
+                let data_size =
+                    usize_from_u64(checked_mul!(width as u64, height as u64)?.div_ceil(8))?;
+                if data_size > stream.bytes_left()? {
+                    return Err(AvifError::BmffParseFailed(
+                        "not enough bytes for region mask".into(),
+                    ));
+                }
+                RegionGeometry::InlineMask {
+                    x,
+                    y,
+                    width,
+                    height,
+                    data_width: width,
+                    data_height: height,
+                    data: stream.get_slice(data_size)?.to_vec(),
+                }
+            }
+            _ => {
+                return Err(AvifError::BmffParseFailed(format!(
+                    "unknown region geometry type {geometry_type}"
+                )))
+            }
+        };
+        region_annotation.geometries.push(geometry);
+    }
+    if stream.has_bytes_left()? {
+        return Err(AvifError::BmffParseFailed(
+            "invalid trailing bytes in region item".into(),
+        ));
+    }
+    Ok(region_annotation)
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::decoder::region::*;
+    use crate::decoder::track::MediaTimes;
+    use crate::decoder::track::SampleTable;
+    use crate::internal_utils::stream::IStream;
+    use crate::parser::limits::*;
     use crate::parser::mp4box;
+    use crate::AvifError;
     use crate::AvifResult;
 
+    use test_case::test_case;
+
     #[test]
     fn peek_compatible_file_type() -> AvifResult<()> {
         let buf = [
@@ -2054,4 +2578,463 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test_case(&[0x00, 0x00, 0x04, 0x00, 0x02], RegionGeometry::Point { x: 2, y: 1 }; "point")]
+    #[test_case(
+        &[0x01, 0xff, 0xfc, 0x00, 0x02, 0x00, 0x08, 0x00, 0x06],
+        RegionGeometry::Rectangle { x: -2, y: 1, width: 4, height: 3 }; "rectangle")]
+    #[test_case(
+        &[0x02, 0x00, 0x04, 0x00, 0x02, 0x00, 0x02, 0x00, 0x02],
+        RegionGeometry::Ellipse { x: 2, y: 1, radius_x: 1, radius_y: 1 }; "ellipse")]
+    #[test_case(
+        &[0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02],
+        RegionGeometry::Polygon(vec![(0, 0), (1, 1)]); "polygon")]
+    #[test_case(
+        &[0x06, 0x00, 0x01, 0x00, 0x04, 0x00, 0x04],
+        RegionGeometry::Polyline(vec![(2, 2)]); "polyline")]
+    #[test_case(
+        &[0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x04],
+        RegionGeometry::ReferencedMask { x: 0, y: 0, width: 2, height: 2 }; "referenced mask")]
+    #[test_case(
+        &[0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x04, 0x00, 0xa5, 0x5a],
+        RegionGeometry::InlineMask {
+            x: 0, y: 0, width: 2, height: 2, data_width: 4, data_height: 4, data: vec![0xa5, 0x5a]
+        }; "inline mask")]
+    fn parse_rgan(region: &[u8], expected_geometry: RegionGeometry) -> AvifResult<()> {
+        // version 0, 16-bit fields, reference dimensions 8x8 and one region.
+        let mut payload = vec![0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x01];
+        payload.extend_from_slice(region);
+        let mut stream = IStream::create(&payload);
+        let mut region_annotation = mp4box::parse_rgan(&mut stream)?;
+        assert_eq!(region_annotation.reference_width, 8);
+        assert_eq!(region_annotation.reference_height, 8);
+        assert_eq!(region_annotation.geometries.len(), 1);
+        // Map the reference space onto a 4x4 image.
+        region_annotation.scale(4, 4)?;
+        assert_eq!(region_annotation.geometries[0], expected_geometry);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_rgan_32bit_fields() -> AvifResult<()> {
+        let payload = [
+            0x00, 0x01, // version 0, 32-bit fields.
+            0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, // 65536x65536 reference.
+            0x01, // One region.
+            0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x80, 0x00, // Point (-1, 32768).
+        ];
+        let mut stream = IStream::create(&payload);
+        let mut region_annotation = mp4box::parse_rgan(&mut stream)?;
+        region_annotation.scale(2, 2)?;
+        assert_eq!(
+            region_annotation.geometries,
+            vec![RegionGeometry::Point { x: 0, y: 1 }]
+        );
+        Ok(())
+    }
+
+    #[test_case(&[0x01, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00]; "unsupported version")]
+    #[test_case(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00]; "zero reference width")]
+    #[test_case(&[0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x01, 0x07, 0x00, 0x00]; "unknown geometry")]
+    #[test_case(&[0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x01, 0x00, 0x00]; "truncated point")]
+    #[test_case(&[0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x01, 0x03, 0xff, 0xff]; "truncated polygon")]
+    #[test_case(&[0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00, 0x00]; "trailing bytes")]
+    #[test_case(
+        &[0x00, 0x00, 0x00, 0x08, 0x00, 0x08, 0x01, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+          0x00, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00]; "compressed mask")]
+    fn parse_rgan_malformed(payload: &[u8]) {
+        let mut stream = IStream::create(payload);
+        assert!(matches!(
+            mp4box::parse_rgan(&mut stream),
+            Err(AvifError::BmffParseFailed(_))
+        ));
+    }
+
+    #[test]
+    fn hevc_codec_specific_data() {
+        let hvcc = mp4box::HevcCodecConfiguration {
+            vps: vec![vec![0x40]],
+            sps: vec![vec![0x42, 0x01], vec![0x42, 0x02]],
+            pps: vec![vec![0x44]],
+            sei: vec![vec![0x4e]],
+            ..Default::default()
+        };
+        assert_eq!(
+            hvcc.codec_specific_data(),
+            vec![
+                0, 0, 0, 1, 0x40, //
+                0, 0, 0, 1, 0x42, 0x01, //
+                0, 0, 0, 1, 0x42, 0x02, //
+                0, 0, 0, 1, 0x44, //
+                0, 0, 0, 1, 0x4e,
+            ]
+        );
+    }
+
+    // Returns an hvcC payload with the given lengthSizeMinusOne and parameter set arrays. Each
+    // array is a NAL unit type followed by its NAL units.
+    #[cfg(feature = "heic")]
+    fn hvcc_payload(length_size_minus_one: u8, arrays: &[(u8, &[&[u8]])]) -> Vec<u8> {
+        // configurationVersion.
+        let mut payload = vec![1];
+        // All the fields up to lengthSizeMinusOne, with bit_depth_luma_minus8 set to 0.
+        payload.extend_from_slice(&[0; 20]);
+        payload.push(length_size_minus_one);
+        payload.push(arrays.len() as u8);
+        for (nal_unit_type, nal_units) in arrays {
+            payload.push(0x80 | nal_unit_type);
+            payload.extend_from_slice(&(nal_units.len() as u16).to_be_bytes());
+            for nal_unit in *nal_units {
+                payload.extend_from_slice(&(nal_unit.len() as u16).to_be_bytes());
+                payload.extend_from_slice(nal_unit);
+            }
+        }
+        payload
+    }
+
+    #[cfg(feature = "heic")]
+    #[test]
+    fn parse_hvcc_arrays() -> AvifResult<()> {
+        // Out of order arrays, an SEI array, an array with two NAL units, an empty NAL unit and
+        // an unsupported array type (AUD).
+        let payload = hvcc_payload(
+            3,
+            &[
+                (39, &[&[0x4e, 0x01]]),
+                (34, &[&[0x44, 0x01]]),
+                (33, &[&[0x42, 0x01], &[], &[0x42, 0x02]]),
+                (35, &[&[0x46, 0x01]]),
+                (32, &[&[0x40, 0x01]]),
+            ],
+        );
+        let mut stream = IStream::create(&payload);
+        let hvcc = match mp4box::parse_hvcC(&mut stream)? {
+            mp4box::ItemProperty::CodecConfiguration(mp4box::CodecConfiguration::Hevc(hvcc)) => {
+                hvcc
+            }
+            _ => panic!("unexpected property"),
+        };
+        assert_eq!(hvcc.bitdepth, 8);
+        assert_eq!(hvcc.nal_length_size, 4);
+        assert_eq!(hvcc.vps, vec![vec![0x40, 0x01]]);
+        assert_eq!(hvcc.sps, vec![vec![0x42, 0x01], vec![0x42, 0x02]]);
+        assert_eq!(hvcc.pps, vec![vec![0x44, 0x01]]);
+        assert_eq!(hvcc.sei, vec![vec![0x4e, 0x01]]);
+        assert_eq!(hvcc.skipped_nal_unit_types, vec![35]);
+        assert_eq!(
+            hvcc.codec_specific_data(),
+            vec![
+                0, 0, 0, 1, 0x40, 0x01, //
+                0, 0, 0, 1, 0x42, 0x01, //
+                0, 0, 0, 1, 0x42, 0x02, //
+                0, 0, 0, 1, 0x44, 0x01, //
+                0, 0, 0, 1, 0x4e, 0x01,
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "heic")]
+    #[test_case(0, true; "length size 1")]
+    #[test_case(1, true; "length size 2")]
+    #[test_case(2, false; "length size 3")]
+    #[test_case(3, true; "length size 4")]
+    fn parse_hvcc_length_size(length_size_minus_one: u8, valid: bool) {
+        let payload = hvcc_payload(length_size_minus_one, &[(32, &[&[0x40, 0x01]])]);
+        let mut stream = IStream::create(&payload);
+        assert_eq!(mp4box::parse_hvcC(&mut stream).is_ok(), valid);
+    }
+
+    #[cfg(feature = "heic")]
+    #[test]
+    fn parse_hvcc_truncated() {
+        let mut payload = hvcc_payload(3, &[(32, &[&[0x40, 0x01]])]);
+        payload.pop();
+        let mut stream = IStream::create(&payload);
+        assert!(mp4box::parse_hvcC(&mut stream).is_err());
+    }
+
+    #[cfg(feature = "heic")]
+    #[test_case(0, 8, mp4box::PixelFormat::Yuv400; "monochrome")]
+    #[test_case(1, 8, mp4box::PixelFormat::Yuv420; "yuv420")]
+    #[test_case(2, 10, mp4box::PixelFormat::Yuv422; "yuv422")]
+    #[test_case(3, 12, mp4box::PixelFormat::Yuv444; "yuv444")]
+    fn parse_hvcc_format(chroma_format_idc: u8, depth: u8, pixel_format: mp4box::PixelFormat) {
+        let mut payload = hvcc_payload(3, &[]);
+        // general_profile_space 0, general_tier_flag 1, general_profile_idc 4 (format range
+        // extensions).
+        payload[1] = 0x24;
+        // general_level_idc 5.1.
+        payload[12] = 153;
+        payload[16] = 0xfc | chroma_format_idc;
+        payload[17] = 0xf8 | (depth - 8);
+        payload[18] = 0xf8 | (depth - 8);
+        let mut stream = IStream::create(&payload);
+        let config = match mp4box::parse_hvcC(&mut stream).unwrap() {
+            mp4box::ItemProperty::CodecConfiguration(config) => config,
+            _ => panic!("expected a codec configuration"),
+        };
+        assert!(config.is_heic());
+        assert_eq!(config.depth(), depth);
+        assert_eq!(config.pixel_format(), pixel_format);
+        assert_eq!(config.profile(), 4);
+        let mp4box::CodecConfiguration::Hevc(hvcc) = config else {
+            panic!("expected an hvcC");
+        };
+        assert!(hvcc.general_tier_flag);
+        assert_eq!(hvcc.general_level_idc, 153);
+        assert_eq!(hvcc.chroma_format_idc, chroma_format_idc);
+        assert_eq!(hvcc.bitdepth_chroma, depth);
+    }
+
+    // Returns a box with the given type and payload.
+    fn make_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((8 + payload.len()) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test_case::test_case(b"prof")]
+    #[test_case::test_case(b"rICC")]
+    fn colr_icc(color_type: &[u8; 4]) {
+        let mut payload = color_type.to_vec();
+        payload.extend_from_slice(&[1, 2, 3]);
+        let mut stream = IStream::create(&payload);
+        match mp4box::parse_colr(&mut stream).unwrap() {
+            mp4box::ItemProperty::ColorInformation(mp4box::ColorInformation::Icc {
+                color_type: parsed_color_type,
+                profile,
+            }) => {
+                assert_eq!(parsed_color_type.as_bytes(), color_type);
+                assert_eq!(profile, [1, 2, 3]);
+            }
+            _ => panic!("expected an ICC profile"),
+        }
+    }
+
+    #[test]
+    fn deeply_nested_boxes() {
+        // A valid meta box whose deepest box is an ispe property in meta > iprp > ipco.
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"pict");
+        hdlr.extend_from_slice(&[0; 13]);
+        let mut ispe = vec![0; 4];
+        ispe.extend_from_slice(&1u32.to_be_bytes());
+        ispe.extend_from_slice(&1u32.to_be_bytes());
+        let ipco = make_box(b"ipco", &make_box(b"ispe", &ispe));
+        let mut meta = vec![0; 4];
+        meta.extend_from_slice(&make_box(b"hdlr", &hdlr));
+        meta.extend_from_slice(&make_box(b"iprp", &ipco));
+        // The ispe box is at depth 3 from the payload of meta.
+        let mut stream = IStream::create(&meta);
+        stream.depth = MAX_BOX_DEPTH - 3;
+        assert!(mp4box::parse_meta(&mut stream).is_ok());
+        let mut stream = IStream::create(&meta);
+        stream.depth = MAX_BOX_DEPTH - 2;
+        match mp4box::parse_meta(&mut stream) {
+            Err(AvifError::BmffParseFailed(message)) => {
+                assert!(message.contains("MAX_BOX_DEPTH"))
+            }
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn duplicate_grpl() {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"pict");
+        hdlr.extend_from_slice(&[0; 13]);
+        let altr = |entity_id: u32| {
+            let mut altr = vec![0; 8];
+            altr.extend_from_slice(&1u32.to_be_bytes());
+            altr.extend_from_slice(&entity_id.to_be_bytes());
+            make_box(b"grpl", &make_box(b"altr", &altr))
+        };
+        let mut meta = vec![0; 4];
+        meta.extend_from_slice(&make_box(b"hdlr", &hdlr));
+        meta.extend_from_slice(&altr(1));
+        meta.extend_from_slice(&altr(2));
+        meta.extend_from_slice(&make_box(b"pitm", &[0, 0, 0, 0, 0, 1]));
+        // The second grpl box is ignored and the boxes after it are still parsed.
+        let meta = mp4box::parse_meta(&mut IStream::create(&meta)).unwrap();
+        assert_eq!(meta.grpl.len(), 1);
+        assert_eq!(meta.grpl[0].entity_ids, [1]);
+        assert_eq!(meta.primary_item_id, 1);
+    }
+
+    #[test]
+    fn too_many_items() {
+        // iinf version 1 with an entry_count of MAX_ITEM_COUNT + 1.
+        let mut payload = vec![1, 0, 0, 0];
+        payload.extend_from_slice(&(MAX_ITEM_COUNT as u32 + 1).to_be_bytes());
+        let mut stream = IStream::create(&payload);
+        match mp4box::parse_iinf(&mut stream) {
+            Err(AvifError::BmffParseFailed(message)) => {
+                assert!(message.contains("MAX_ITEM_COUNT"))
+            }
+            _ => panic!("expected an error"),
+        }
+        // ipma with an entry_count of MAX_ITEM_COUNT + 1.
+        let mut payload = vec![0, 0, 0, 0];
+        payload.extend_from_slice(&(MAX_ITEM_COUNT as u32 + 1).to_be_bytes());
+        let mut stream = IStream::create(&payload);
+        match mp4box::parse_ipma(&mut stream) {
+            Err(AvifError::BmffParseFailed(message)) => {
+                assert!(message.contains("MAX_ITEM_COUNT"))
+            }
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn too_many_properties() {
+        let property = make_box(b"abcd", &[]);
+        let payload = property.repeat(MAX_PROPERTY_COUNT);
+        let mut stream = IStream::create(&payload);
+        assert_eq!(
+            mp4box::parse_ipco(&mut stream).unwrap().len(),
+            MAX_PROPERTY_COUNT
+        );
+        let payload = property.repeat(MAX_PROPERTY_COUNT + 1);
+        let mut stream = IStream::create(&payload);
+        match mp4box::parse_ipco(&mut stream) {
+            Err(AvifError::BmffParseFailed(message)) => {
+                assert!(message.contains("MAX_PROPERTY_COUNT"))
+            }
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn tai_properties() {
+        let mut taic = vec![0, 0, 0, 0];
+        taic.extend_from_slice(&0x0102030405060708u64.to_be_bytes());
+        taic.extend_from_slice(&1000u32.to_be_bytes());
+        taic.extend_from_slice(&(-25i32).to_be_bytes());
+        taic.push(0b1000_0000); // clock_type 2.
+        let mut itai = vec![0, 0, 0, 0];
+        itai.extend_from_slice(&u64::MAX.to_be_bytes());
+        itai.push(0b1010_0000); // synchronized and modified.
+        let mut taic_v1 = taic.clone();
+        taic_v1[0] = 1;
+        let payload = [
+            make_box(b"taic", &taic),
+            make_box(b"itai", &itai),
+            make_box(b"taic", &taic_v1),
+        ]
+        .concat();
+        let mut stream = IStream::create(&payload);
+        let properties = mp4box::parse_ipco(&mut stream).unwrap();
+        assert_eq!(properties.len(), 3);
+        assert!(matches!(
+            properties[0],
+            mp4box::ItemProperty::TaiClockInfo(mp4box::TaiClockInfo {
+                time_uncertainty: 0x0102030405060708,
+                clock_resolution: 1000,
+                clock_drift_rate: -25,
+                clock_type: 2,
+            })
+        ));
+        assert!(matches!(
+            properties[1],
+            mp4box::ItemProperty::TaiTimestamp(mp4box::TaiTimestamp {
+                tai_timestamp: u64::MAX,
+                synchronization_state: true,
+                timestamp_generation_failure: false,
+                timestamp_is_modified: true,
+            })
+        ));
+        // Unknown versions are skipped.
+        assert!(
+            matches!(&properties[2], mp4box::ItemProperty::Unknown(box_type) if box_type == "taic")
+        );
+
+        // Truncated boxes are rejected.
+        let payload = make_box(b"itai", &itai[..itai.len() - 1]);
+        let mut stream = IStream::create(&payload);
+        assert!(mp4box::parse_ipco(&mut stream).is_err());
+    }
+
+    #[test]
+    fn parse_mvhd() {
+        // Version 0 with an unset creation_time.
+        let mut mvhd = vec![0, 0, 0, 0];
+        mvhd.extend_from_slice(&0u32.to_be_bytes());
+        mvhd.extend_from_slice(&2082844801u32.to_be_bytes());
+        let mut stream = IStream::create(&mvhd);
+        assert_eq!(
+            mp4box::parse_mvhd(&mut stream),
+            Ok(Some(MediaTimes {
+                creation_time: -2082844800,
+                modification_time: 1,
+            }))
+        );
+        // Version 1 with times past the i64 range.
+        let mut mvhd = vec![1, 0, 0, 0];
+        mvhd.extend_from_slice(&u64::MAX.to_be_bytes());
+        mvhd.extend_from_slice(&2082844800u64.to_be_bytes());
+        let mut stream = IStream::create(&mvhd);
+        assert_eq!(
+            mp4box::parse_mvhd(&mut stream),
+            Ok(Some(MediaTimes {
+                creation_time: i64::MAX - 2082844800,
+                modification_time: 0,
+            }))
+        );
+        // Unknown versions are ignored.
+        mvhd[0] = 2;
+        let mut stream = IStream::create(&mvhd);
+        assert_eq!(mp4box::parse_mvhd(&mut stream), Ok(None));
+    }
+
+    #[test]
+    fn parse_iloc_64bit_fields() -> AvifResult<()> {
+        let base_offset = 0x1_0000_0000u64;
+        let extent_offset = 0x2_0000_0010u64;
+        let extent_length = 0x1_0000_0001u64;
+        // Version 1, offset_size 8, length_size 8, base_offset_size 8, index_size 0.
+        let mut iloc = vec![1, 0, 0, 0, 0x88, 0x80];
+        iloc.extend_from_slice(&1u16.to_be_bytes()); // item_count
+        iloc.extend_from_slice(&1u16.to_be_bytes()); // item_ID
+        iloc.extend_from_slice(&0u16.to_be_bytes()); // construction_method 0
+        iloc.extend_from_slice(&0u16.to_be_bytes()); // data_reference_index
+        iloc.extend_from_slice(&base_offset.to_be_bytes());
+        iloc.extend_from_slice(&1u16.to_be_bytes()); // extent_count
+        iloc.extend_from_slice(&extent_offset.to_be_bytes());
+        iloc.extend_from_slice(&extent_length.to_be_bytes());
+        let mut stream = IStream::create(&iloc);
+        let result = mp4box::parse_iloc(&mut stream);
+        if cfg!(target_pointer_width = "64") {
+            let iloc = result?;
+            assert_eq!(iloc.items.len(), 1);
+            assert_eq!(iloc.items[0].base_offset, base_offset);
+            assert_eq!(iloc.items[0].extents.len(), 1);
+            assert_eq!(iloc.items[0].extents[0].offset, extent_offset);
+            assert_eq!(iloc.items[0].extents[0].size as u64, extent_length);
+        } else {
+            // The extent cannot be read into memory.
+            assert!(matches!(result, Err(AvifError::BmffParseFailed(_))));
+        }
+        Ok(())
+    }
+
+    #[test_case(false, &[0x1234, 0xffff_ffff])]
+    #[test_case(true, &[0x1_0000_0000, u64::MAX])]
+    fn parse_chunk_offsets(large_offset: bool, offsets: &[u64]) -> AvifResult<()> {
+        let mut payload = vec![0, 0, 0, 0];
+        payload.extend_from_slice(&(offsets.len() as u32).to_be_bytes());
+        for offset in offsets {
+            if large_offset {
+                payload.extend_from_slice(&offset.to_be_bytes());
+            } else {
+                payload.extend_from_slice(&(*offset as u32).to_be_bytes());
+            }
+        }
+        let mut stream = IStream::create(&payload);
+        let mut sample_table = SampleTable::default();
+        mp4box::parse_stco(&mut stream, &mut sample_table, large_offset)?;
+        assert_eq!(sample_table.chunk_offsets, offsets);
+        Ok(())
+    }
 }
@@ -27,10 +27,10 @@ struct ObuHeader {
 #[derive(Debug, Default)]
 pub struct Av1SequenceHeader {
     reduced_still_picture_header: bool,
-    max_width: u32,
-    max_height: u32,
-    bit_depth: u8,
-    yuv_format: PixelFormat,
+    pub(crate) max_width: u32,
+    pub(crate) max_height: u32,
+    pub(crate) bit_depth: u8,
+    pub(crate) yuv_format: PixelFormat,
     #[allow(unused)]
     chroma_sample_position: ChromaSamplePosition,
     pub color_primaries: ColorPrimaries,
@@ -38,6 +38,7 @@ pub struct Av1SequenceHeader {
     pub matrix_coefficients: MatrixCoefficients,
     pub yuv_range: YuvRange,
     config: Av1CodecConfiguration,
+    operating_point_idc: Vec<u16>,
 }
 
 impl Av1SequenceHeader {
@@ -55,6 +56,7 @@ impl Av1SequenceHeader {
         }
         if self.reduced_still_picture_header {
             self.config.seq_level_idx0 = bits.read(5)? as u8;
+            self.operating_point_idc.push(0);
         } else {
             let mut buffer_delay_length = 0;
             let mut decoder_model_info_present_flag = false;
@@ -85,8 +87,8 @@ impl Av1SequenceHeader {
             let operating_points_cnt_minus_1 = bits.read(5)?;
             let operating_points_cnt = operating_points_cnt_minus_1 + 1;
             for i in 0..operating_points_cnt {
-                // operating_point_idc
-                bits.skip(12)?;
+                let operating_point_idc = bits.read(12)? as u16;
+                self.operating_point_idc.push(operating_point_idc);
                 let seq_level_idx = bits.read(5)?;
                 if i == 0 {
                     self.config.seq_level_idx0 = seq_level_idx as u8;
@@ -295,6 +297,27 @@ impl Av1SequenceHeader {
         Ok(ObuHeader { obu_type, size })
     }
 
+    pub(crate) fn config(&self) -> &Av1CodecConfiguration {
+        &self.config
+    }
+
+    // Returns true if the given spatial layer is part of the given operating point. Returns None
+    // if the operating point does not exist.
+    pub(crate) fn operating_point_has_spatial_layer(
+        &self,
+        operating_point: u8,
+        spatial_id: u16,
+    ) -> Option<bool> {
+        let operating_point_idc = *self.operating_point_idc.get(operating_point as usize)?;
+        // An operating_point_idc of 0 means that all layers are included. Otherwise, bits 8 to
+        // 11 indicate which spatial layers are included (Section 6.4.1 of the AV1 specification).
+        // There are at most 4 spatial layers.
+        Some(
+            operating_point_idc == 0
+                || (spatial_id < 4 && (operating_point_idc >> (8 + spatial_id)) & 1 == 1),
+        )
+    }
+
     pub(crate) fn parse_from_obus(data: &[u8]) -> AvifResult<Self> {
         let mut stream = IStream::create(data);
 
@@ -326,3 +349,51 @@ impl Av1SequenceHeader {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operating_point_has_spatial_layer() {
+        let sequence_header = Av1SequenceHeader {
+            // Operating point 0 contains spatial layers 0 and 1, operating point 1 contains only
+            // spatial layer 0 and operating point 2 contains all the layers.
+            operating_point_idc: vec![0x301, 0x101, 0],
+            ..Default::default()
+        };
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(0, 0),
+            Some(true)
+        );
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(0, 1),
+            Some(true)
+        );
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(0, 2),
+            Some(false)
+        );
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(1, 1),
+            Some(false)
+        );
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(2, 3),
+            Some(true)
+        );
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(3, 0),
+            None
+        );
+        // Spatial ids that cannot exist, whatever the operating point.
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(0, 4),
+            Some(false)
+        );
+        assert_eq!(
+            sequence_header.operating_point_has_spatial_layer(0, 0xFFFE),
+            Some(false)
+        );
+    }
+}
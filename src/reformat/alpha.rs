@@ -17,7 +17,6 @@ use super::libyuv;
 
 use super::rgb;
 
-use crate::decoder::Category;
 use crate::image::Plane;
 use crate::internal_utils::*;
 use crate::reformat::rgb::Format;
@@ -218,6 +217,66 @@ impl rgb::Image {
         Ok(())
     }
 
+    // Flattens the image onto an opaque background color using the source-over operator. The
+    // background is given as [r, g, b] at the depth of the image. Pixels are treated as
+    // premultiplied if premultiply_alpha is true and as straight alpha otherwise. The alpha
+    // channel is opaque after this call. Does nothing if the image has no alpha channel.
+    pub fn composite_onto(&mut self, background: [u16; 3]) -> AvifResult<()> {
+        if !self.has_alpha() {
+            return Ok(());
+        }
+        if self.pixels().is_null() || self.row_bytes == 0 {
+            return Err(AvifError::ReformatFailed);
+        }
+        if self.is_float || self.format == rgb::Format::Rgba1010102 {
+            return Err(AvifError::NotImplemented);
+        }
+        let max_channel = self.max_channel();
+        if background.iter().any(|x| *x > max_channel) {
+            return Err(AvifError::InvalidArgument);
+        }
+        let offsets = self.format.offsets();
+        let (rgb_offsets, alpha_offset) = ([offsets[0], offsets[1], offsets[2]], offsets[3]);
+        let max_channel_f = self.max_channel_f();
+        let premultiplied = self.premultiply_alpha;
+        let composite = |pixel: u16, alpha: u16, background: u16| -> u16 {
+            let alpha_f = alpha as f32 / max_channel_f;
+            let pixel_f = if premultiplied { pixel as f32 } else { pixel as f32 * alpha_f };
+            (pixel_f + background as f32 * (1.0 - alpha_f))
+                .round()
+                .min(max_channel_f) as u16
+        };
+        let width = usize_from_u32(self.width)?;
+        if self.depth > 8 {
+            for y in 0..self.height {
+                let row = self.row16_mut(y)?;
+                for x in 0..width {
+                    let offset = x * 4;
+                    let alpha = row[offset + alpha_offset];
+                    for (rgb_offset, background) in rgb_offsets.iter().zip(background) {
+                        row[offset + rgb_offset] =
+                            composite(row[offset + rgb_offset], alpha, background);
+                    }
+                    row[offset + alpha_offset] = max_channel;
+                }
+            }
+        } else {
+            for y in 0..self.height {
+                let row = self.row_mut(y)?;
+                for x in 0..width {
+                    let offset = x * 4;
+                    let alpha = row[offset + alpha_offset] as u16;
+                    for (rgb_offset, background) in rgb_offsets.iter().zip(background) {
+                        row[offset + rgb_offset] =
+                            composite(row[offset + rgb_offset] as u16, alpha, background) as u8;
+                    }
+                    row[offset + alpha_offset] = 255;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn rescale_alpha_value(value: u16, src_max_channel_f: f32, dst_max_channel: u16) -> u16 {
         let alpha_f = (value as f32) / src_max_channel_f;
         let dst_max_channel_f = dst_max_channel as f32;
@@ -300,69 +359,11 @@ impl rgb::Image {
     }
 }
 
-impl image::Image {
-    pub(crate) fn alpha_to_full_range(&mut self) -> AvifResult<()> {
-        if self.planes[3].is_none() {
-            return Ok(());
-        }
-        let width = self.width as usize;
-        let depth = self.depth;
-        if self.planes[3].unwrap_ref().is_pointer() {
-            let src = image::Image {
-                width: self.width,
-                height: self.height,
-                depth: self.depth,
-                yuv_format: self.yuv_format,
-                planes: [
-                    None,
-                    None,
-                    None,
-                    Some(self.planes[3].unwrap_ref().try_clone()?),
-                ],
-                row_bytes: [0, 0, 0, self.row_bytes[3]],
-                ..image::Image::default()
-            };
-            self.allocate_planes(Category::Alpha)?;
-            if depth > 8 {
-                for y in 0..self.height {
-                    let src_row = src.row16(Plane::A, y)?;
-                    let dst_row = self.row16_mut(Plane::A, y)?;
-                    for x in 0..width {
-                        dst_row[x] = limited_to_full_y(depth, src_row[x]);
-                    }
-                }
-            } else {
-                for y in 0..self.height {
-                    let src_row = src.row(Plane::A, y)?;
-                    let dst_row = self.row_mut(Plane::A, y)?;
-                    for x in 0..width {
-                        dst_row[x] = limited_to_full_y(8, src_row[x] as u16) as u8;
-                    }
-                }
-            }
-        } else if depth > 8 {
-            for y in 0..self.height {
-                let row = self.row16_mut(Plane::A, y)?;
-                for pixel in row.iter_mut().take(width) {
-                    *pixel = limited_to_full_y(depth, *pixel);
-                }
-            }
-        } else {
-            for y in 0..self.height {
-                let row = self.row_mut(Plane::A, y)?;
-                for pixel in row.iter_mut().take(width) {
-                    *pixel = limited_to_full_y(8, *pixel as u16) as u8;
-                }
-            }
-        }
-        Ok(())
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::decoder::Category;
     use crate::internal_utils::pixels::*;
 
     use rand::Rng;
@@ -451,6 +452,97 @@ mod tests {
         Ok(())
     }
 
+    #[test_matrix(0..4, [false, true], [8, 10], [false, true])]
+    fn composite_onto(
+        format_index: usize,
+        premultiplied: bool,
+        depth: u8,
+        white_background: bool,
+    ) -> AvifResult<()> {
+        let format = ALPHA_RGB_FORMATS[format_index];
+        let mut buffer: Vec<u8> = vec![];
+        let mut rgb = rgb_image(3, 1, depth, format, false, &mut buffer)?;
+        rgb.premultiply_alpha = premultiplied;
+        let max_channel = rgb.max_channel();
+        let offsets = format.offsets();
+        // Half of the maximum alpha value, rounded up.
+        let half = max_channel / 2 + 1;
+        // Mid-gray at 50% alpha. When premultiplied, the color is floor(gray * alpha / max).
+        let gray = if premultiplied {
+            (half as u32 * half as u32 / max_channel as u32) as u16
+        } else {
+            half
+        };
+        let pixels: [[u16; 4]; 3] = [
+            // Fully transparent.
+            [0, 0, 0, 0],
+            // Fully opaque.
+            [10, 20, 30, max_channel],
+            [gray, gray, gray, half],
+        ];
+        let mut row: Vec<u16> = vec![0; 12];
+        for (x, pixel) in pixels.iter().enumerate() {
+            for (channel, value) in pixel.iter().enumerate() {
+                row[x * 4 + offsets[channel]] = *value;
+            }
+        }
+        if depth == 8 {
+            for (dst, src) in rgb.row_mut(0)?.iter_mut().zip(&row) {
+                *dst = *src as u8;
+            }
+        } else {
+            rgb.row16_mut(0)?.copy_from_slice(&row);
+        }
+
+        let background = if white_background { [max_channel; 3] } else { [0; 3] };
+        rgb.composite_onto(background)?;
+
+        let row: Vec<u16> = if depth == 8 {
+            rgb.row(0)?.iter().map(|x| *x as u16).collect()
+        } else {
+            rgb.row16(0)?.to_vec()
+        };
+        // Hand-computed from gray * alpha / max + background * (1 - alpha / max).
+        let expected_gray = match (depth, white_background) {
+            (8, true) => 191,   // 64.25 + 127
+            (8, false) => 64,   // 64.25
+            (10, true) => 767,  // 256.25 + 511
+            (10, false) => 256, // 256.25
+            _ => unreachable!(),
+        };
+        let expected: [[u16; 4]; 3] = [
+            [background[0], background[1], background[2], max_channel],
+            [10, 20, 30, max_channel],
+            [expected_gray, expected_gray, expected_gray, max_channel],
+        ];
+        for (x, pixel) in expected.iter().enumerate() {
+            for (channel, value) in pixel.iter().enumerate() {
+                assert_eq!(
+                    row[x * 4 + offsets[channel]],
+                    *value,
+                    "pixel {x} channel {channel}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn composite_onto_without_alpha() -> AvifResult<()> {
+        let mut rgb = rgb::Image {
+            width: 1,
+            height: 1,
+            depth: 8,
+            format: rgb::Format::Rgb,
+            ..rgb::Image::default()
+        };
+        rgb.allocate()?;
+        rgb.row_mut(0)?.copy_from_slice(&[1, 2, 3]);
+        rgb.composite_onto([255, 255, 255])?;
+        assert_eq!(rgb.row(0)?, &[1, 2, 3]);
+        Ok(())
+    }
+
     #[test]
     fn rescale_alpha_value() {
         // 8bit to 10bit.
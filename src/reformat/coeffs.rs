@@ -19,27 +19,67 @@ fn expand_coeffs(y: f32, v: f32) -> [f32; 3] {
 }
 
 impl ColorPrimaries {
-    pub(crate) fn y_coeffs(&self) -> [f32; 3] {
-        // These values come from computations in Section 8 of
-        // https://www.itu.int/rec/T-REC-H.273-201612-S
-        match self {
+    // Returns the chromaticity coordinates of the red, green and blue primaries and of the white
+    // point, as [xr, yr, xg, yg, xb, yb, xw, yw]. These values come from Table 2 of
+    // https://www.itu.int/rec/T-REC-H.273-201612-S
+    pub(crate) fn values(&self) -> [f32; 8] {
+        const D65: [f32; 2] = [0.3127, 0.329];
+        const ILLUMINANT_C: [f32; 2] = [0.310, 0.316];
+        let (primaries, white) = match self {
             ColorPrimaries::Unknown | ColorPrimaries::Srgb | ColorPrimaries::Unspecified => {
-                expand_coeffs(0.2126, 0.0722)
+                ([0.64, 0.33, 0.30, 0.60, 0.15, 0.06], D65)
             }
-            ColorPrimaries::Bt470m => expand_coeffs(0.299, 0.1146),
-            ColorPrimaries::Bt470bg => expand_coeffs(0.222, 0.0713),
-            ColorPrimaries::Bt601 | ColorPrimaries::Smpte240 => expand_coeffs(0.212, 0.087),
-            ColorPrimaries::GenericFilm => expand_coeffs(0.2536, 0.06808),
-            ColorPrimaries::Bt2020 => expand_coeffs(0.2627, 0.0593),
-            ColorPrimaries::Xyz => expand_coeffs(0.0, 0.0),
-            ColorPrimaries::Smpte431 => expand_coeffs(0.2095, 0.0689),
-            ColorPrimaries::Smpte432 => expand_coeffs(0.229, 0.0793),
-            ColorPrimaries::Ebu3213 => expand_coeffs(0.2318, 0.096),
-        }
+            ColorPrimaries::Bt470m => ([0.67, 0.33, 0.21, 0.71, 0.14, 0.08], ILLUMINANT_C),
+            ColorPrimaries::Bt470bg => ([0.64, 0.33, 0.29, 0.60, 0.15, 0.06], D65),
+            ColorPrimaries::Bt601 | ColorPrimaries::Smpte240 => {
+                ([0.630, 0.340, 0.310, 0.595, 0.155, 0.070], D65)
+            }
+            ColorPrimaries::GenericFilm => {
+                ([0.681, 0.319, 0.243, 0.692, 0.145, 0.049], ILLUMINANT_C)
+            }
+            ColorPrimaries::Bt2020 => ([0.708, 0.292, 0.170, 0.797, 0.131, 0.046], D65),
+            ColorPrimaries::Xyz => ([1.0, 0.0, 0.0, 1.0, 0.0, 0.0], [1.0 / 3.0, 1.0 / 3.0]),
+            ColorPrimaries::Smpte431 => {
+                ([0.680, 0.320, 0.265, 0.690, 0.150, 0.060], [0.314, 0.351])
+            }
+            ColorPrimaries::Smpte432 => ([0.680, 0.320, 0.265, 0.690, 0.150, 0.060], D65),
+            ColorPrimaries::Ebu3213 => ([0.630, 0.340, 0.295, 0.605, 0.155, 0.077], D65),
+        };
+        [
+            primaries[0],
+            primaries[1],
+            primaries[2],
+            primaries[3],
+            primaries[4],
+            primaries[5],
+            white[0],
+            white[1],
+        ]
+    }
+
+    pub(crate) fn y_coeffs(&self) -> [f32; 3] {
+        // Equations 39 to 44 in Section 8 of https://www.itu.int/rec/T-REC-H.273-201612-S
+        let [xr, yr, xg, yg, xb, yb, xw, yw] = self.values();
+        let zr = 1.0 - (xr + yr);
+        let zg = 1.0 - (xg + yg);
+        let zb = 1.0 - (xb + yb);
+        let zw = 1.0 - (xw + yw);
+        let denominator =
+            yw * (xr * (yg * zb - yb * zg) + xg * (yb * zr - yr * zb) + xb * (yr * zg - yg * zr));
+        let kr = yr
+            * (xw * (yg * zb - yb * zg) + yw * (xb * zg - xg * zb) + zw * (xg * yb - xb * yg))
+            / denominator;
+        let kb = yb
+            * (xw * (yr * zg - yg * zr) + yw * (xg * zr - xr * zg) + zw * (xr * yg - xg * yr))
+            / denominator;
+        expand_coeffs(kr, kb)
     }
 }
 
-fn calculate_yuv_coefficients_from_cicp(
+// Returns the [Kr, Kg, Kb] coefficients that define the conversion between YUV and RGB for the
+// given CICP values. Returns None when the matrix coefficients are not defined by such
+// coefficients (Identity, YCgCo, the constant luminance matrices, ICtCp) or are unspecified.
+pub fn yuv_coefficients(
     color_primaries: ColorPrimaries,
     matrix_coefficients: MatrixCoefficients,
 ) -> Option<[f32; 3]> {
@@ -60,10 +100,9 @@ pub(crate) fn calculate_yuv_coefficients(
     color_primaries: ColorPrimaries,
     matrix_coefficients: MatrixCoefficients,
 ) -> [f32; 3] {
-    // Return known coefficients or fall back to BT.601.
-    calculate_yuv_coefficients_from_cicp(color_primaries, matrix_coefficients).unwrap_or(
-        calculate_yuv_coefficients_from_cicp(color_primaries, MatrixCoefficients::Bt601).unwrap(),
-    )
+    // Return known coefficients or fall back to BT.601. The RGB conversion functions reject the
+    // matrix coefficients that cannot be expressed this way before getting here.
+    yuv_coefficients(color_primaries, matrix_coefficients).unwrap_or(expand_coeffs(0.299, 0.114))
 }
 
 #[cfg(test)]
@@ -86,4 +125,60 @@ mod tests {
             &[0.212f32, 1f32 - 0.212 - 0.087, 0.087f32], // Kr,Kg,Kb as https://en.wikipedia.org/wiki/YCbCr#SMPTE_240M_conversion
         );
     }
+
+    fn assert_near(a: [f32; 3], b: [f32; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 0.0001, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn chroma_derived_coefficients() {
+        // The BT.2020 primaries give the BT.2020 non-constant luminance coefficients.
+        assert_near(
+            super::yuv_coefficients(ColorPrimaries::Bt2020, MatrixCoefficients::ChromaDerivedNcl)
+                .unwrap(),
+            super::yuv_coefficients(ColorPrimaries::Bt2020, MatrixCoefficients::Bt2020Ncl).unwrap(),
+        );
+        // The BT.709 primaries give the BT.709 coefficients.
+        assert_near(
+            super::yuv_coefficients(ColorPrimaries::Srgb, MatrixCoefficients::ChromaDerivedNcl)
+                .unwrap(),
+            super::yuv_coefficients(ColorPrimaries::Srgb, MatrixCoefficients::Bt709).unwrap(),
+        );
+        // Display P3 and DCI-P3, as published in SMPTE EG 432-1.
+        assert_near(
+            super::yuv_coefficients(
+                ColorPrimaries::Smpte432,
+                MatrixCoefficients::ChromaDerivedNcl,
+            )
+            .unwrap(),
+            [0.2290, 0.6917, 0.0793],
+        );
+        assert_near(
+            super::yuv_coefficients(
+                ColorPrimaries::Smpte431,
+                MatrixCoefficients::ChromaDerivedNcl,
+            )
+            .unwrap(),
+            [0.2095, 0.7216, 0.0689],
+        );
+    }
+
+    #[test]
+    fn no_yuv_coefficients() {
+        for matrix_coefficients in [
+            MatrixCoefficients::Identity,
+            MatrixCoefficients::Unspecified,
+            MatrixCoefficients::Bt2020Cl,
+            MatrixCoefficients::ChromaDerivedCl,
+            MatrixCoefficients::Ictcp,
+            MatrixCoefficients::Ycgco,
+        ] {
+            assert_eq!(
+                super::yuv_coefficients(ColorPrimaries::Bt2020, matrix_coefficients),
+                None
+            );
+        }
+    }
 }
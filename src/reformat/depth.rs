@@ -0,0 +1,279 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::decoder::Category;
+use crate::image::*;
+use crate::internal_utils::*;
+use crate::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Dither {
+    // Round each sample to the nearest 8-bit value.
+    None,
+    // 4x4 Bayer matrix.
+    #[default]
+    Ordered,
+    // Error diffusion with the Floyd-Steinberg kernel.
+    FloydSteinberg,
+}
+
+const BAYER_4X4: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl Image {
+    // Returns a copy of this image with all the planes reduced to a depth of 8 bits.
+    pub fn to_8bit_dithered(&self, dither: Dither) -> AvifResult<Image> {
+        if !self.depth_valid() {
+            return Err(AvifError::InvalidArgument);
+        }
+        if matches!(
+            self.yuv_format,
+            PixelFormat::AndroidP010 | PixelFormat::AndroidNv12 | PixelFormat::AndroidNv21
+        ) {
+            return Err(AvifError::NotImplemented);
+        }
+        let mut dst = Image {
+            width: self.width,
+            height: self.height,
+            depth: 8,
+            yuv_format: self.yuv_format,
+            yuv_range: self.yuv_range,
+            chroma_sample_position: self.chroma_sample_position,
+            alpha_present: self.alpha_present,
+            alpha_premultiplied: self.alpha_premultiplied,
+            color_primaries: self.color_primaries,
+            transfer_characteristics: self.transfer_characteristics,
+            matrix_coefficients: self.matrix_coefficients,
+            clli: self.clli,
+            pasp: self.pasp,
+            clap: self.clap,
+            irot_angle: self.irot_angle,
+            imir_axis: self.imir_axis,
+            exif: self.exif.clone(),
+            icc: self.icc.clone(),
+            xmp: self.xmp.clone(),
+            plane_allocator: self.plane_allocator.clone(),
+            ..Default::default()
+        };
+        if self.has_plane(Plane::Y) {
+            dst.allocate_planes(Category::Color)?;
+        }
+        if self.has_alpha() {
+            dst.allocate_planes(Category::Alpha)?;
+        }
+        let shift = self.depth - 8;
+        for plane in ALL_PLANES {
+            if !self.has_plane(plane) || !dst.has_plane(plane) {
+                continue;
+            }
+            let width = self.width(plane);
+            let height = u32_from_usize(self.height(plane))?;
+            match dither {
+                Dither::None => {
+                    for y in 0..height {
+                        let src_row = self.row_generic(plane, y)?;
+                        let dst_row = &mut dst.row_mut(plane, y)?[..width];
+                        for (x, pixel) in dst_row.iter_mut().enumerate() {
+                            *pixel = round_to_8bit(sample(src_row, x), shift);
+                        }
+                    }
+                }
+                Dither::Ordered => {
+                    for y in 0..height {
+                        let src_row = self.row_generic(plane, y)?;
+                        let dst_row = &mut dst.row_mut(plane, y)?[..width];
+                        let thresholds = &BAYER_4X4[y as usize % 4];
+                        for (x, pixel) in dst_row.iter_mut().enumerate() {
+                            // The threshold is (bayer + 0.5) / 16 in units of the 8-bit output.
+                            let value =
+                                (sample(src_row, x) << 5) + ((2 * thresholds[x % 4] + 1) << shift);
+                            *pixel = (value >> (shift + 5)).min(255) as u8;
+                        }
+                    }
+                }
+                Dither::FloydSteinberg => {
+                    let step = (1u32 << shift) as f32;
+                    // Errors carried over to the current and the next row. There is one extra
+                    // element on each side so that the kernel never goes out of bounds.
+                    let mut errors: Vec<f32> = create_vec_exact(width + 2)?;
+                    errors.resize(width + 2, 0.0);
+                    let mut next_errors: Vec<f32> = create_vec_exact(width + 2)?;
+                    next_errors.resize(width + 2, 0.0);
+                    for y in 0..height {
+                        let src_row = self.row_generic(plane, y)?;
+                        let dst_row = &mut dst.row_mut(plane, y)?[..width];
+                        for (x, pixel) in dst_row.iter_mut().enumerate() {
+                            let value = sample(src_row, x) as f32 + errors[x + 1];
+                            let quantized = (value / step).round().clamp(0.0, 255.0);
+                            *pixel = quantized as u8;
+                            let error = value - quantized * step;
+                            errors[x + 2] += error * 7.0 / 16.0;
+                            next_errors[x] += error * 3.0 / 16.0;
+                            next_errors[x + 1] += error * 5.0 / 16.0;
+                            next_errors[x + 2] += error / 16.0;
+                        }
+                        std::mem::swap(&mut errors, &mut next_errors);
+                        next_errors.fill(0.0);
+                    }
+                }
+            }
+        }
+        Ok(dst)
+    }
+
+    // Replaces the planes of this image by their 8-bit dithered version. Does nothing if the
+    // depth already is 8 or if there is no plane, such as when only the gain map was decoded.
+    pub(crate) fn reduce_to_8bit(&mut self, dither: Dither) -> AvifResult<()> {
+        if self.depth == 8 || ALL_PLANES.iter().all(|plane| !self.has_plane(*plane)) {
+            return Ok(());
+        }
+        let dst = self.to_8bit_dithered(dither)?;
+        self.depth = 8;
+        self.planes = dst.planes;
+        self.row_bytes = dst.row_bytes;
+        self.image_owns_planes = dst.image_owns_planes;
+        Ok(())
+    }
+}
+
+fn sample(row: PlaneRow, x: usize) -> u32 {
+    match row {
+        PlaneRow::Depth8(row) => row[x] as u32,
+        PlaneRow::Depth16(row) => row[x] as u32,
+    }
+}
+
+fn round_to_8bit(value: u32, shift: u8) -> u8 {
+    if shift == 0 {
+        return value as u8;
+    }
+    ((value + (1 << (shift - 1))) >> shift).min(255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal_utils::pixels::*;
+
+    use test_case::test_matrix;
+
+    fn gray_image(width: u32, height: u32, depth: u8, value: u16) -> Image {
+        let mut image = Image {
+            width,
+            height,
+            depth,
+            yuv_format: PixelFormat::Yuv400,
+            ..Default::default()
+        };
+        let size = (width * height) as usize;
+        image.planes[0] = Some(if depth == 8 {
+            Pixels::Buffer(vec![value as u8; size])
+        } else {
+            Pixels::Buffer16(vec![value; size])
+        });
+        image.row_bytes[0] = width * if depth == 8 { 1 } else { 2 };
+        image.image_owns_planes[0] = true;
+        image
+    }
+
+    fn histogram(image: &Image) -> [u32; 256] {
+        let mut histogram = [0; 256];
+        for y in 0..image.height {
+            for pixel in image.row(Plane::Y, y).unwrap() {
+                histogram[*pixel as usize] += 1;
+            }
+        }
+        histogram
+    }
+
+    #[test_matrix([10, 12, 16], [Dither::Ordered, Dither::FloydSteinberg])]
+    fn dithering_spreads_histogram(depth: u8, dither: Dither) {
+        // A flat area whose value sits a quarter of the way between two 8-bit levels.
+        let shift = depth - 8;
+        let value = (128u16 << shift) + (1 << (shift - 2));
+        let image = gray_image(64, 64, depth, value);
+        let rounded = image.to_8bit_dithered(Dither::None).unwrap();
+        let dithered = image.to_8bit_dithered(dither).unwrap();
+        assert_eq!(dithered.depth, 8);
+        let total = 64 * 64;
+        let rounded_histogram = histogram(&rounded);
+        let dithered_histogram = histogram(&dithered);
+        assert_eq!(*rounded_histogram.iter().max().unwrap(), total);
+        let dithered_peak = *dithered_histogram.iter().max().unwrap();
+        assert!(dithered_peak < total);
+        // Only the two neighboring levels are used and the average value is preserved.
+        assert_eq!(dithered_histogram[128] + dithered_histogram[129], total);
+        let mean = dithered_histogram[129] as f32 / total as f32;
+        assert!((mean - 0.25).abs() < 0.02, "mean: {mean}");
+    }
+
+    #[test_matrix([Dither::None, Dither::Ordered, Dither::FloydSteinberg])]
+    fn dithering_extremes(dither: Dither) {
+        for (value, expected) in [(0, 0), (1023, 255)] {
+            let dithered = gray_image(8, 8, 10, value)
+                .to_8bit_dithered(dither)
+                .unwrap();
+            assert_eq!(histogram(&dithered)[expected], 64);
+        }
+        // 8-bit images are copied as is.
+        let dithered = gray_image(8, 8, 8, 77).to_8bit_dithered(dither).unwrap();
+        assert_eq!(histogram(&dithered)[77], 64);
+    }
+
+    #[test]
+    fn dithering_all_planes() {
+        let mut image = Image {
+            width: 3,
+            height: 3,
+            depth: 10,
+            yuv_format: PixelFormat::Yuv420,
+            ..Default::default()
+        };
+        image.allocate_planes(Category::Color).unwrap();
+        image.allocate_planes(Category::Alpha).unwrap();
+        let dithered = image.to_8bit_dithered(Dither::Ordered).unwrap();
+        for plane in ALL_PLANES {
+            let plane_data = dithered.plane_data(plane).unwrap();
+            assert_eq!(plane_data.width, image.width(plane) as u32);
+            assert_eq!(plane_data.height, image.height(plane) as u32);
+            assert_eq!(plane_data.pixel_size, 1);
+        }
+        assert_eq!(dithered.row(Plane::A, 0).unwrap(), &[255, 255, 255]);
+    }
+
+    #[test]
+    fn reduce_to_8bit() {
+        let mut image = gray_image(8, 8, 12, 2049);
+        image.tai_timestamp = Some(Default::default());
+        let expected = image.to_8bit_dithered(Dither::FloydSteinberg).unwrap();
+        image.reduce_to_8bit(Dither::FloydSteinberg).unwrap();
+        assert_eq!(image.depth, 8);
+        assert_eq!(histogram(&image), histogram(&expected));
+        // The other properties are kept.
+        assert!(image.tai_timestamp.is_some());
+
+        let mut image = gray_image(8, 8, 8, 77);
+        image.reduce_to_8bit(Dither::Ordered).unwrap();
+        assert_eq!(histogram(&image)[77], 64);
+
+        // Only the metadata is kept when there is no plane.
+        let mut image = Image {
+            width: 8,
+            height: 8,
+            depth: 10,
+            ..Default::default()
+        };
+        image.reduce_to_8bit(Dither::Ordered).unwrap();
+        assert_eq!(image.depth, 10);
+    }
+}
@@ -371,6 +371,13 @@ pub(crate) fn yuv_to_rgb(image: &image::Image, rgb: &mut rgb::Image) -> AvifResu
         return Err(AvifError::NotImplemented);
     }
 
+    if rgb.chroma_upsampling.bilinear_or_better_filter_allowed()
+        && matches!(image.yuv_format, PixelFormat::Yuv420 | PixelFormat::Yuv422)
+        && image.chroma_sample_position != ChromaSamplePosition::CENTER
+    {
+        // The bilinear filters of libyuv assume that the chroma samples are centered.
+        return Err(AvifError::NotImplemented);
+    }
     let (matrix_yuv, matrix_yvu) = find_constants(image).ok_or(AvifError::NotImplemented)?;
     let alpha_preferred = rgb.has_alpha() && image.has_alpha();
     let conversion_function =
@@ -17,9 +17,16 @@ pub mod libyuv;
 #[cfg(feature = "libyuv")]
 pub mod scale;
 
+// The coefficients are needed by the decoder itself. The other modules convert decoded images
+// and can be left out by disabling the conversion feature.
+#[cfg(feature = "conversion")]
 pub mod alpha;
 pub mod coeffs;
+#[cfg(feature = "conversion")]
+pub mod depth;
+#[cfg(feature = "conversion")]
 pub mod rgb;
+#[cfg(feature = "conversion")]
 pub mod rgb_impl;
 
 // If libyuv is not present, add placeholder functions so that the library will build successfully
@@ -27,13 +34,16 @@ pub mod rgb_impl;
 #[cfg(not(feature = "libyuv"))]
 pub mod libyuv {
     use crate::decoder::Category;
+    #[cfg(feature = "conversion")]
     use crate::reformat::*;
     use crate::*;
 
+    #[cfg(feature = "conversion")]
     pub(crate) fn yuv_to_rgb(_image: &image::Image, _rgb: &mut rgb::Image) -> AvifResult<bool> {
         Err(AvifError::NotImplemented)
     }
 
+    #[cfg(feature = "conversion")]
     pub(crate) fn convert_to_half_float(_rgb: &mut rgb::Image, _scale: f32) -> AvifResult<()> {
         Err(AvifError::NotImplemented)
     }
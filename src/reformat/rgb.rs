@@ -162,6 +162,8 @@ impl Image {
             Pixels::Pointer16(ptr) => ptr.ptr_mut() as *mut u8,
             Pixels::Buffer(buffer) => buffer.as_mut_ptr(),
             Pixels::Buffer16(buffer) => buffer.as_mut_ptr() as *mut u8,
+            Pixels::Allocated(buffer) => buffer.ptr_mut(),
+            Pixels::Allocated16(buffer) => buffer.ptr_mut() as *mut u8,
         }
     }
 
@@ -612,6 +614,117 @@ mod tests {
         Ok(())
     }
 
+    // Upsampled chroma values along an edge between chroma samples 64 and 192.
+    const NEAREST: [u8; 8] = [64, 64, 64, 64, 192, 192, 192, 192];
+    const CENTERED: [u8; 8] = [64, 64, 64, 96, 160, 192, 192, 192];
+    const COSITED: [u8; 8] = [64, 64, 64, 128, 192, 192, 192, 192];
+
+    // Returns the blue channel of the pixels crossing a chroma edge (horizontally or
+    // vertically) after converting to RGB.
+    fn blue_across_chroma_edge(
+        yuv_format: PixelFormat,
+        chroma_upsampling: ChromaUpsampling,
+        chroma_sample_position: ChromaSamplePosition,
+        vertical: bool,
+        chroma: &[u8],
+    ) -> AvifResult<Vec<u8>> {
+        let (width, height) = if vertical { (2, 8) } else { (8, 2) };
+        let mut image = image::Image {
+            width,
+            height,
+            depth: 8,
+            yuv_format,
+            yuv_range: YuvRange::Full,
+            matrix_coefficients: MatrixCoefficients::Bt601,
+            chroma_sample_position,
+            ..image::Image::default()
+        };
+        image.allocate_planes(Category::Color)?;
+        for plane in [Plane::Y, Plane::V] {
+            for y in 0..image.height(plane) {
+                image.row_mut(plane, y as u32)?.fill(128);
+            }
+        }
+        for y in 0..image.height(Plane::U) {
+            let row = image.row_mut(Plane::U, y as u32)?;
+            if vertical {
+                row.fill(chroma[y]);
+            } else {
+                row.copy_from_slice(&chroma[..row.len()]);
+            }
+        }
+        let mut rgb = Image::create_from_yuv(&image);
+        rgb.depth = 8;
+        rgb.format = Format::Rgba;
+        rgb.chroma_upsampling = chroma_upsampling;
+        rgb.allocate()?;
+        rgb.convert_from_yuv(&image)?;
+        let mut blue = Vec::new();
+        for i in 0..8 {
+            let (x, y) = if vertical { (0, i) } else { (i, 0) };
+            blue.push(rgb.row(y)?[x as usize * 4 + 2]);
+        }
+        Ok(blue)
+    }
+
+    #[test_case(ChromaUpsampling::Nearest, ChromaSamplePosition::Unknown, false, &NEAREST)]
+    #[test_case(ChromaUpsampling::Nearest, ChromaSamplePosition::Colocated, true, &NEAREST)]
+    #[test_case(ChromaUpsampling::Bilinear, ChromaSamplePosition::Unknown, false, &CENTERED)]
+    #[test_case(ChromaUpsampling::Bilinear, ChromaSamplePosition::Unknown, true, &CENTERED)]
+    #[test_case(ChromaUpsampling::Bilinear, ChromaSamplePosition::Vertical, false, &COSITED)]
+    #[test_case(ChromaUpsampling::Bilinear, ChromaSamplePosition::Vertical, true, &CENTERED)]
+    #[test_case(ChromaUpsampling::Bilinear, ChromaSamplePosition::Colocated, false, &COSITED)]
+    #[test_case(ChromaUpsampling::Bilinear, ChromaSamplePosition::Colocated, true, &COSITED)]
+    fn chroma_upsampling(
+        chroma_upsampling: ChromaUpsampling,
+        chroma_sample_position: ChromaSamplePosition,
+        vertical: bool,
+        expected_chroma: &[u8; 8],
+    ) -> AvifResult<()> {
+        let blue = blue_across_chroma_edge(
+            PixelFormat::Yuv420,
+            chroma_upsampling,
+            chroma_sample_position,
+            vertical,
+            &[64, 64, 192, 192],
+        )?;
+        // The same image with the expected chroma values at full resolution.
+        let expected_blue = blue_across_chroma_edge(
+            PixelFormat::Yuv444,
+            chroma_upsampling,
+            chroma_sample_position,
+            vertical,
+            expected_chroma,
+        )?;
+        for (value, expected_value) in blue.iter().zip(expected_blue.iter()) {
+            assert!(
+                value.abs_diff(*expected_value) <= 1,
+                "{blue:?} vs {expected_blue:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn chroma_upsampling_422_ignores_vertical_position() -> AvifResult<()> {
+        let blue = blue_across_chroma_edge(
+            PixelFormat::Yuv422,
+            ChromaUpsampling::Bilinear,
+            ChromaSamplePosition::Colocated,
+            false,
+            &[64, 64, 192, 192],
+        )?;
+        let expected_blue = blue_across_chroma_edge(
+            PixelFormat::Yuv444,
+            ChromaUpsampling::Bilinear,
+            ChromaSamplePosition::Colocated,
+            false,
+            &COSITED,
+        )?;
+        assert_eq!(blue, expected_blue);
+        Ok(())
+    }
+
     #[test_case(Format::Rgba, &[0, 1, 2, 3])]
     #[test_case(Format::Abgr, &[3, 2, 1, 0])]
     #[test_case(Format::Rgb, &[0, 1, 2])]
@@ -152,6 +152,59 @@ fn yuv8_to_rgb8_color(
     Ok(())
 }
 
+// Specialization of yuv8_to_rgb8_color() for the most common case of 8-bit 4:2:0 limited range
+// BT.709 images converted to an 8-bit format with 4 channels. It uses 14-bit fixed point
+// arithmetic and converts the two pixels that share a chroma sample at once. The results are
+// within 1 of yuv8_to_rgb8_color().
+fn yuv8_420_limited_to_rgba8_bt709(
+    image: &image::Image,
+    rgb: &mut rgb::Image,
+    kr: f32,
+    kg: f32,
+    kb: f32,
+) -> AvifResult<()> {
+    const SHIFT: i32 = 14;
+    const ROUNDING: i32 = 1 << (SHIFT - 1);
+    let fixed = |value: f32| (value * (1 << SHIFT) as f32).round() as i32;
+    // Limited range: Y is in [16, 235] and U, V are in [16, 240].
+    let y_coeff = fixed(255.0 / 219.0);
+    let uv_scale = 255.0 / 224.0;
+    let r_cr = fixed(2.0 * (1.0 - kr) * uv_scale);
+    let b_cb = fixed(2.0 * (1.0 - kb) * uv_scale);
+    let g_cb = fixed(2.0 * kb * (1.0 - kb) / kg * uv_scale);
+    let g_cr = fixed(2.0 * kr * (1.0 - kr) / kg * uv_scale);
+    let r_offset = rgb.format.r_offset();
+    let g_offset = rgb.format.g_offset();
+    let b_offset = rgb.format.b_offset();
+    let width = image.width as usize;
+    let store = |dst: &mut [u8], index: usize, y: i32, r: i32, g: i32, b: i32| {
+        let y = (y - 16) * y_coeff + ROUNDING;
+        let pixel = &mut dst[index * 4..index * 4 + 4];
+        pixel[r_offset] = ((y + r) >> SHIFT).clamp(0, 255) as u8;
+        pixel[g_offset] = ((y + g) >> SHIFT).clamp(0, 255) as u8;
+        pixel[b_offset] = ((y + b) >> SHIFT).clamp(0, 255) as u8;
+    };
+    for j in 0..image.height {
+        let y_row = image.row(Plane::Y, j)?;
+        let u_row = image.row(Plane::U, j >> 1)?;
+        let v_row = image.row(Plane::V, j >> 1)?;
+        let dst = rgb.row_mut(j)?;
+        for uv_i in 0..width.div_ceil(2) {
+            let cb = u_row[uv_i] as i32 - 128;
+            let cr = v_row[uv_i] as i32 - 128;
+            let r = r_cr * cr;
+            let g = -g_cb * cb - g_cr * cr;
+            let b = b_cb * cb;
+            let i = uv_i * 2;
+            store(dst, i, y_row[i] as i32, r, g, b);
+            if i + 1 < width {
+                store(dst, i + 1, y_row[i + 1] as i32, r, g, b);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn yuv16_to_rgb16_color(
     image: &image::Image,
     rgb: &mut rgb::Image,
@@ -442,6 +495,15 @@ pub(crate) fn yuv_to_rgb_fast(image: &image::Image, rgb: &mut rgb::Image) -> Avi
         Mode::YuvCoefficients(kr, kg, kb) => {
             let has_color = image.yuv_format != PixelFormat::Yuv400;
             match (image.depth == 8, rgb.depth == 8, has_color) {
+                (true, true, true)
+                    if image.yuv_format == PixelFormat::Yuv420
+                        && image.yuv_range == YuvRange::Limited
+                        && image.matrix_coefficients == MatrixCoefficients::Bt709
+                        && rgb.format != rgb::Format::Rgb565
+                        && rgb.channel_count() == 4 =>
+                {
+                    yuv8_420_limited_to_rgba8_bt709(image, rgb, kr, kg, kb)
+                }
                 (true, true, true) => yuv8_to_rgb8_color(image, rgb, kr, kg, kb),
                 (false, false, true) => yuv16_to_rgb16_color(image, rgb, kr, kg, kb),
                 (false, true, true) => yuv16_to_rgb8_color(image, rgb, kr, kg, kb),
@@ -609,29 +671,51 @@ pub(crate) fn yuv_to_rgb_any(
                     cb = unorm_value(u_row, uv_i, yuv_max_channel, table_uv);
                     cr = unorm_value(v_row, uv_i, yuv_max_channel, table_uv);
                 } else {
-                    if image.chroma_sample_position != ChromaSamplePosition::CENTER {
-                        return Err(AvifError::NotImplemented);
-                    }
+                    // The chroma samples are either centered between two luma samples or cosited
+                    // with the even luma samples, independently along each axis.
+                    let (cosited_x, cosited_y) =
+                        if image.chroma_sample_position == ChromaSamplePosition::CENTER {
+                            (false, false)
+                        } else {
+                            match image.chroma_sample_position {
+                                ChromaSamplePosition::Vertical => (true, false),
+                                ChromaSamplePosition::Colocated => (true, true),
+                                _ => return Err(AvifError::NotImplemented),
+                            }
+                        };
 
                     // Bilinear filtering with weights. See
                     // https://github.com/AOMediaCodec/libavif/blob/0580334466d57fedb889d5ed7ae9574d6f66e00c/src/reformat.c#L657-L685.
+                    // weight_x and weight_y are the weights of the nearest chroma sample, the rest
+                    // goes to the adjacent one.
                     let image_width_minus_1 = (image.width - 1) as usize;
-                    let uv_adj_i = if i == 0 || (i == image_width_minus_1 && (i % 2) != 0) {
-                        uv_i
+                    let (uv_adj_i, weight_x) = if cosited_x {
+                        if (i % 2) == 0 || i == image_width_minus_1 {
+                            (uv_i, 1.0)
+                        } else {
+                            (uv_i + 1, 0.5)
+                        }
+                    } else if i == 0 || (i == image_width_minus_1 && (i % 2) != 0) {
+                        (uv_i, 0.75)
                     } else if (i % 2) != 0 {
-                        uv_i + 1
+                        (uv_i + 1, 0.75)
                     } else {
-                        uv_i - 1
+                        (uv_i - 1, 0.75)
                     };
-                    let uv_adj_j = if j == 0
-                        || (j == image.height - 1 && (j % 2) != 0)
-                        || image.yuv_format == PixelFormat::Yuv422
-                    {
-                        uv_j
+                    let (uv_adj_j, weight_y) = if image.yuv_format == PixelFormat::Yuv422 {
+                        (uv_j, 1.0)
+                    } else if cosited_y {
+                        if (j % 2) == 0 || j == image.height - 1 {
+                            (uv_j, 1.0)
+                        } else {
+                            (uv_j + 1, 0.5)
+                        }
+                    } else if j == 0 || (j == image.height - 1 && (j % 2) != 0) {
+                        (uv_j, 0.75)
                     } else if (j % 2) != 0 {
-                        uv_j + 1
+                        (uv_j + 1, 0.75)
                     } else {
-                        uv_j - 1
+                        (uv_j - 1, 0.75)
                     };
                     let u_adj_row = image.row_generic(Plane::U, uv_adj_j)?;
                     let v_adj_row = image.row_generic(Plane::V, uv_adj_j)?;
@@ -645,14 +729,21 @@ pub(crate) fn yuv_to_rgb_any(
                     unorm_v[0][1] = unorm_value(v_adj_row, uv_i, yuv_max_channel, table_uv);
                     unorm_u[1][1] = unorm_value(u_adj_row, uv_adj_i, yuv_max_channel, table_uv);
                     unorm_v[1][1] = unorm_value(v_adj_row, uv_adj_i, yuv_max_channel, table_uv);
-                    cb = (unorm_u[0][0] * (9.0 / 16.0))
-                        + (unorm_u[1][0] * (3.0 / 16.0))
-                        + (unorm_u[0][1] * (3.0 / 16.0))
-                        + (unorm_u[1][1] * (1.0 / 16.0));
-                    cr = (unorm_v[0][0] * (9.0 / 16.0))
-                        + (unorm_v[1][0] * (3.0 / 16.0))
-                        + (unorm_v[0][1] * (3.0 / 16.0))
-                        + (unorm_v[1][1] * (1.0 / 16.0));
+                    let weights = [
+                        [weight_x * weight_y, weight_x * (1.0 - weight_y)],
+                        [
+                            (1.0 - weight_x) * weight_y,
+                            (1.0 - weight_x) * (1.0 - weight_y),
+                        ],
+                    ];
+                    cb = (unorm_u[0][0] * weights[0][0])
+                        + (unorm_u[1][0] * weights[1][0])
+                        + (unorm_u[0][1] * weights[0][1])
+                        + (unorm_u[1][1] * weights[1][1]);
+                    cr = (unorm_v[0][0] * weights[0][0])
+                        + (unorm_v[1][0] * weights[1][0])
+                        + (unorm_v[0][1] * weights[0][1])
+                        + (unorm_v[1][1] * weights[1][1]);
                 }
             }
             let (mut rc, mut gc, mut bc) = compute_rgb(
@@ -924,4 +1015,51 @@ mod tests {
             ],
         );
     }
+
+    #[test_case::test_case(1, 1, rgb::Format::Rgba)]
+    #[test_case::test_case(5, 3, rgb::Format::Bgra)]
+    #[test_case::test_case(64, 17, rgb::Format::Argb)]
+    #[test_case::test_case(33, 32, rgb::Format::Abgr)]
+    fn yuv8_420_limited_bt709_fast_path(width: u32, height: u32, format: rgb::Format) {
+        let mut yuv = image::Image {
+            width,
+            height,
+            depth: 8,
+            yuv_format: PixelFormat::Yuv420,
+            yuv_range: YuvRange::Limited,
+            color_primaries: ColorPrimaries::Bt709,
+            matrix_coefficients: MatrixCoefficients::Bt709,
+            ..Default::default()
+        };
+        yuv.allocate_planes(decoder::Category::Color).unwrap();
+        // Pseudo-random samples covering the whole 8-bit range, including out of limited range
+        // values.
+        let mut seed: u32 = 1;
+        for plane in image::YUV_PLANES {
+            for y in 0..yuv.height(plane) {
+                for sample in yuv.row_mut(plane, y as u32).unwrap() {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    *sample = (seed >> 16) as u8;
+                }
+            }
+        }
+        let Mode::YuvCoefficients(kr, kg, kb) = Mode::from(&yuv) else {
+            panic!("expected yuv coefficients");
+        };
+        let mut rgbs = [(); 2].map(|_| {
+            let mut rgb = rgb::Image::create_from_yuv(&yuv);
+            rgb.format = format;
+            rgb.allocate().unwrap();
+            rgb
+        });
+        yuv_to_rgb_fast(&yuv, &mut rgbs[0]).unwrap();
+        yuv8_to_rgb8_color(&yuv, &mut rgbs[1], kr, kg, kb).unwrap();
+        for y in 0..height {
+            let fast_row = &rgbs[0].row(y).unwrap()[..width as usize * 4];
+            let generic_row = &rgbs[1].row(y).unwrap()[..width as usize * 4];
+            for (fast, generic) in fast_row.iter().zip(generic_row) {
+                assert!(fast.abs_diff(*generic) <= 1);
+            }
+        }
+    }
 }
@@ -0,0 +1,168 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::image::*;
+use crate::internal_utils::*;
+use crate::*;
+
+// 64-bit FNV-1a, see http://www.isthe.com/chongo/tech/comp/fnv/.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+// The hashes below only depend on the samples and properties of the image, not on the row
+// strides, the memory layout or the endianness of the platform. They are meant for golden tests
+// and cache keys: identical inputs give identical hashes across crate versions, but nothing can
+// be inferred from the hashes of different inputs.
+impl Image {
+    // Hashes the width(plane) x height(plane) samples of the given plane, in row order. Samples of
+    // images deeper than 8 bits are hashed as two little-endian bytes each.
+    pub fn plane_checksum(&self, plane: Plane) -> AvifResult<u64> {
+        if !self.has_plane(plane) {
+            return Err(AvifError::NoContent);
+        }
+        let mut hasher = Fnv1a::new();
+        let width = self.width(plane);
+        for y in 0..u32_from_usize(self.height(plane))? {
+            if self.depth == 8 {
+                hasher.write(&self.row(plane, y)?[..width]);
+            } else {
+                for sample in &self.row16(plane, y)?[..width] {
+                    hasher.write(&sample.to_le_bytes());
+                }
+            }
+        }
+        Ok(hasher.0)
+    }
+
+    // Combines the dimensions, depth, format and range of the image with the checksums of all the
+    // planes that are present.
+    pub fn content_hash(&self) -> AvifResult<u64> {
+        let mut hasher = Fnv1a::new();
+        hasher.write(&self.width.to_le_bytes());
+        hasher.write(&self.height.to_le_bytes());
+        hasher.write(&[self.depth, self.yuv_format as u8, self.yuv_range as u8]);
+        for plane in ALL_PLANES {
+            if self.has_plane(plane) {
+                hasher.write(&[1]);
+                hasher.write(&self.plane_checksum(plane)?.to_le_bytes());
+            } else {
+                hasher.write(&[0]);
+            }
+        }
+        Ok(hasher.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Category;
+    use crate::internal_utils::pixels::*;
+
+    fn image(depth: u8, row_bytes_padding: u32) -> Image {
+        let mut image = Image {
+            width: 3,
+            height: 3,
+            depth,
+            yuv_format: PixelFormat::Yuv420,
+            ..Default::default()
+        };
+        image.allocate_planes(Category::Color).unwrap();
+        if row_bytes_padding != 0 {
+            // Reallocate the luma plane with extra bytes at the end of each row, filled with
+            // garbage.
+            let pixel_size = if depth == 8 { 1 } else { 2 };
+            let row_bytes = 3 * pixel_size + row_bytes_padding;
+            let size = (row_bytes * 3 / pixel_size) as usize;
+            image.planes[0] = Some(if depth == 8 {
+                Pixels::Buffer(vec![0xAB; size])
+            } else {
+                Pixels::Buffer16(vec![0xABAB; size])
+            });
+            image.row_bytes[0] = row_bytes;
+        }
+        for y in 0..3u32 {
+            for x in 0..3usize {
+                let value = (y as usize * 3 + x) as u16;
+                if depth == 8 {
+                    image.row_mut(Plane::Y, y).unwrap()[x] = value as u8;
+                } else {
+                    image.row16_mut(Plane::Y, y).unwrap()[x] = value << 2;
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn checksum_ignores_padding() {
+        for depth in [8, 10] {
+            let image1 = image(depth, 0);
+            let image2 = image(depth, 6);
+            assert_eq!(
+                image1.plane_checksum(Plane::Y),
+                image2.plane_checksum(Plane::Y)
+            );
+            assert_eq!(image1.content_hash(), image2.content_hash());
+        }
+    }
+
+    #[test]
+    fn checksum_is_stable() {
+        // Golden values, which must not change across crate versions.
+        assert_eq!(image(8, 0).plane_checksum(Plane::Y), Ok(0xb11d013568a3b7cf));
+        assert_eq!(
+            image(10, 0).plane_checksum(Plane::Y),
+            Ok(0xdd2d0a788ce14a4d)
+        );
+        assert_eq!(Fnv1a::new().0, 0xcbf29ce484222325);
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.0, 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn hash_depends_on_content_and_properties() {
+        let image1 = image(8, 0);
+        let mut image2 = image(8, 0);
+        assert_eq!(image1.content_hash(), image2.content_hash());
+        image2.row_mut(Plane::V, 1).unwrap()[1] = 1;
+        assert_eq!(
+            image1.plane_checksum(Plane::U),
+            image2.plane_checksum(Plane::U)
+        );
+        assert_ne!(
+            image1.plane_checksum(Plane::V),
+            image2.plane_checksum(Plane::V)
+        );
+        assert_ne!(image1.content_hash(), image2.content_hash());
+
+        let mut image3 = image(8, 0);
+        image3.yuv_range = YuvRange::Limited;
+        assert_ne!(image1.content_hash(), image3.content_hash());
+        assert_eq!(image1.plane_checksum(Plane::A), Err(AvifError::NoContent));
+    }
+}
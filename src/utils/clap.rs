@@ -23,7 +23,7 @@ pub struct CleanAperture {
     pub vert_off: UFraction,
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct CropRect {
     pub x: u32,
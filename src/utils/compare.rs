@@ -0,0 +1,116 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::image::*;
+use crate::utils::metrics::*;
+use crate::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImageComparison {
+    // PSNR in dB of each plane, indexed by Plane, capped at MAX_PSNR. None if neither image has
+    // that plane and MAX_PSNR if the plane is identical in both images, as returned by psnr().
+    pub psnr: [Option<f64>; MAX_PLANE_COUNT],
+    // Largest absolute difference between two samples over all the planes.
+    pub max_abs_diff: u16,
+}
+
+impl ImageComparison {
+    pub fn is_identical(&self) -> bool {
+        self.max_abs_diff == 0
+    }
+
+    pub fn is_within(&self, max_abs_diff: u16) -> bool {
+        self.max_abs_diff <= max_abs_diff
+    }
+}
+
+impl Image {
+    // Compares the samples of two images which must have the same dimensions, depth, format and
+    // planes.
+    pub fn compare(&self, other: &Image) -> AvifResult<ImageComparison> {
+        check_comparable(self, other)?;
+        let mut comparison = ImageComparison::default();
+        for plane in ALL_PLANES {
+            if !self.has_plane(plane) {
+                continue;
+            }
+            let (squared_error_sum, max_abs_diff) = plane_error(self, other, plane)?;
+            let sample_count = self.width(plane) * self.height(plane);
+            comparison.psnr[plane.as_usize()] = Some(psnr_from_error(
+                squared_error_sum,
+                sample_count,
+                self.max_channel(),
+            ));
+            comparison.max_abs_diff = comparison.max_abs_diff.max(max_abs_diff);
+        }
+        Ok(comparison)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Category;
+
+    fn image_with_value(depth: u8, value: u16) -> Image {
+        let mut image = Image {
+            width: 4,
+            height: 4,
+            depth,
+            yuv_format: PixelFormat::Yuv420,
+            ..Default::default()
+        };
+        image
+            .allocate_planes_with_default_values(Category::Color, [value; 4])
+            .unwrap();
+        image
+    }
+
+    #[test_case::test_case(8)]
+    #[test_case::test_case(10)]
+    fn one_lsb_difference(depth: u8) {
+        let image = image_with_value(depth, 100);
+        let mut perturbed = image_with_value(depth, 100);
+        if depth == 8 {
+            perturbed.row_mut(Plane::Y, 1).unwrap()[2] = 101;
+        } else {
+            perturbed.row16_mut(Plane::Y, 1).unwrap()[2] = 101;
+        }
+
+        let comparison = image.compare(&image_with_value(depth, 100)).unwrap();
+        assert!(comparison.is_identical());
+        assert_eq!(comparison.psnr[0], Some(MAX_PSNR));
+
+        let comparison = image.compare(&perturbed).unwrap();
+        assert!(!comparison.is_identical());
+        assert!(comparison.is_within(1));
+        assert_eq!(comparison.max_abs_diff, 1);
+        // One sample out of 16 is off by one.
+        let max_channel = ((1u32 << depth) - 1) as f64;
+        let expected_psnr = 10.0 * (max_channel * max_channel * 16.0).log10();
+        assert!((comparison.psnr[0].unwrap() - expected_psnr).abs() < 1e-9);
+        assert_eq!(comparison.psnr[1], Some(MAX_PSNR));
+        assert_eq!(comparison.psnr[2], Some(MAX_PSNR));
+        assert_eq!(comparison.psnr[3], None);
+    }
+
+    #[test]
+    fn mismatched_images() {
+        let image = image_with_value(8, 100);
+        assert!(image.compare(&image_with_value(10, 100)).is_err());
+        let mut with_alpha = image_with_value(8, 100);
+        with_alpha.allocate_planes(Category::Alpha).unwrap();
+        assert!(image.compare(&with_alpha).is_err());
+    }
+}
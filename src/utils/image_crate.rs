@@ -0,0 +1,122 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::image;
+use crate::internal_utils::*;
+use crate::reformat::rgb;
+use crate::*;
+
+use ::image::DynamicImage;
+use ::image::RgbImage;
+use ::image::RgbaImage;
+
+impl TryFrom<&rgb::Image> for DynamicImage {
+    type Error = AvifError;
+
+    // Only 8-bit Rgb and Rgba images are supported.
+    fn try_from(rgb: &rgb::Image) -> AvifResult<Self> {
+        if rgb.depth != 8
+            || rgb.is_float
+            || !matches!(rgb.format, rgb::Format::Rgb | rgb::Format::Rgba)
+        {
+            return Err(AvifError::NotImplemented);
+        }
+        let row_size = usize_from_u32(checked_mul!(rgb.width, rgb.channel_count())?)?;
+        let mut pixels = create_vec_exact(checked_mul!(row_size, usize_from_u32(rgb.height)?)?)?;
+        for y in 0..rgb.height {
+            // row_bytes may be larger than row_size.
+            pixels.extend_from_slice(&rgb.row(y)?[..row_size]);
+        }
+        let image = if rgb.format == rgb::Format::Rgb {
+            RgbImage::from_raw(rgb.width, rgb.height, pixels).map(DynamicImage::ImageRgb8)
+        } else {
+            RgbaImage::from_raw(rgb.width, rgb.height, pixels).map(DynamicImage::ImageRgba8)
+        };
+        image.ok_or(AvifError::UnknownError(
+            "could not create the DynamicImage".into(),
+        ))
+    }
+}
+
+impl TryFrom<&image::Image> for DynamicImage {
+    type Error = AvifError;
+
+    // Converts to 8-bit Rgba if the image has alpha, and to 8-bit Rgb otherwise.
+    fn try_from(image: &image::Image) -> AvifResult<Self> {
+        let mut rgb = rgb::Image::create_from_yuv(image);
+        rgb.depth = 8;
+        rgb.format = if image.alpha_present { rgb::Format::Rgba } else { rgb::Format::Rgb };
+        rgb.allocate()?;
+        rgb.convert_from_yuv(image)?;
+        DynamicImage::try_from(&rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::decoder::Category;
+    use crate::image::Plane;
+    use crate::image::YuvRange;
+
+    use ::image::GenericImageView;
+
+    #[test]
+    fn from_yuv() -> AvifResult<()> {
+        let mut image = image::Image {
+            width: 3,
+            height: 2,
+            depth: 8,
+            yuv_format: PixelFormat::Yuv444,
+            yuv_range: YuvRange::Full,
+            matrix_coefficients: MatrixCoefficients::Identity,
+            ..image::Image::default()
+        };
+        image.allocate_planes(Category::Color)?;
+        // With the identity matrix, the Y, U and V planes are the G, B and R channels.
+        for (plane, value) in [(Plane::Y, 10), (Plane::U, 20), (Plane::V, 30)] {
+            for y in 0..image.height {
+                image.row_mut(plane, y)?.fill(value);
+            }
+        }
+        let dynamic_image = DynamicImage::try_from(&image)?;
+        assert_eq!(dynamic_image.dimensions(), (3, 2));
+        assert!(matches!(dynamic_image, DynamicImage::ImageRgb8(_)));
+        assert_eq!(dynamic_image.get_pixel(2, 1).0, [30, 10, 20, 255]);
+
+        image.allocate_planes(Category::Alpha)?;
+        for y in 0..image.height {
+            image.row_mut(Plane::A, y)?.fill(40);
+        }
+        image.alpha_present = true;
+        let dynamic_image = DynamicImage::try_from(&image)?;
+        assert!(matches!(dynamic_image, DynamicImage::ImageRgba8(_)));
+        assert_eq!(dynamic_image.get_pixel(0, 0).0, [30, 10, 20, 40]);
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_rgb() {
+        let rgb = rgb::Image {
+            depth: 10,
+            format: rgb::Format::Rgba,
+            ..Default::default()
+        };
+        assert!(matches!(
+            DynamicImage::try_from(&rgb),
+            Err(AvifError::NotImplemented)
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::parser::mp4box;
+
+const MARKER_SOI: u8 = 0xD8;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_EOI: u8 = 0xD9;
+const MARKER_APP2: u8 = 0xE2;
+const MPF_IDENTIFIER: &[u8] = b"MPF\0";
+const MP_ENTRY_TAG: u16 = 0xB002;
+const MP_ENTRY_SIZE: usize = 16;
+
+// Reads the integers of a TIFF structure, which can be in either byte order.
+struct TiffReader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl TiffReader<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self
+            .data
+            .get(offset..offset.checked_add(2)?)?
+            .try_into()
+            .ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self
+            .data
+            .get(offset..offset.checked_add(4)?)?
+            .try_into()
+            .ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+// Returns the offset of the MP Extensions header (the TIFF header following the "MPF\0"
+// identifier of the APP2 segment) in jpeg, if any. Only the segments before the first scan are
+// considered.
+fn find_mpf_header(jpeg: &[u8]) -> Option<usize> {
+    if jpeg.get(0..2)? != [0xFF, MARKER_SOI] {
+        return None;
+    }
+    let mut offset = 2;
+    loop {
+        if *jpeg.get(offset)? != 0xFF {
+            return None;
+        }
+        let marker = *jpeg.get(offset + 1)?;
+        if marker == 0xFF {
+            // Fill byte.
+            offset += 1;
+            continue;
+        }
+        if marker == MARKER_SOS || marker == MARKER_EOI {
+            return None;
+        }
+        let length = u16::from_be_bytes(jpeg.get(offset + 2..offset + 4)?.try_into().ok()?);
+        let payload = jpeg.get(offset + 4..offset + 2 + length as usize)?;
+        if marker == MARKER_APP2 && payload.starts_with(MPF_IDENTIFIER) {
+            return Some(offset + 4 + MPF_IDENTIFIER.len());
+        }
+        offset += 2 + length as usize;
+    }
+}
+
+// Returns the (offset, size) pairs of the images listed in the MP Index IFD, relative to the
+// start of the jpeg. The first entry describes the primary image.
+fn mp_entries(jpeg: &[u8], mpf_header: usize) -> Option<Vec<(usize, usize)>> {
+    let tiff = TiffReader {
+        data: jpeg.get(mpf_header..)?,
+        big_endian: match jpeg.get(mpf_header..mpf_header + 4)? {
+            [b'M', b'M', 0x00, 0x2A] => true,
+            [b'I', b'I', 0x2A, 0x00] => false,
+            _ => return None,
+        },
+    };
+    let ifd = tiff.u32(4)? as usize;
+    let field_count = tiff.u16(ifd)? as usize;
+    for field in 0..field_count {
+        let field_offset = ifd + 2 + field * 12;
+        if tiff.u16(field_offset)? != MP_ENTRY_TAG {
+            continue;
+        }
+        let entry_count = tiff.u32(field_offset + 4)? as usize / MP_ENTRY_SIZE;
+        let entries_offset = tiff.u32(field_offset + 8)? as usize;
+        let mut entries = Vec::new();
+        for entry in 0..entry_count {
+            let entry_offset = entries_offset + entry * MP_ENTRY_SIZE;
+            let size = tiff.u32(entry_offset + 4)? as usize;
+            let offset = tiff.u32(entry_offset + 8)? as usize;
+            // The offset of the primary image is 0. The other offsets are relative to the MP
+            // Extensions header.
+            let offset = if entry == 0 { 0 } else { mpf_header.checked_add(offset)? };
+            entries.push((offset, size));
+        }
+        return Some(entries);
+    }
+    None
+}
+
+// Returns the first AVIF file that is listed as an additional image in the Multi-Picture Format
+// (CIPA DC-007) index of the given JPEG, such as a gain map stored as AVIF next to the primary
+// JPEG image. Returns None if the JPEG has no MPF index or no AVIF additional image, or if the
+// index is malformed.
+pub fn extract_embedded_avif(jpeg: &[u8]) -> Option<Vec<u8>> {
+    let mpf_header = find_mpf_header(jpeg)?;
+    mp_entries(jpeg, mpf_header)?
+        .into_iter()
+        .skip(1)
+        .filter_map(|(offset, size)| jpeg.get(offset..offset.checked_add(size)?))
+        .find(|image| mp4box::peek_compatible_file_type(image).unwrap_or(false))
+        .map(|image| image.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns a JPEG-like file made of an APP2 MPF segment listing the primary image and the
+    // given additional images, followed by the additional images. The primary image has no scan.
+    fn jpeg_with_mpf(images: &[&[u8]], big_endian: bool) -> Vec<u8> {
+        let u16_bytes = |value: u16| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let u32_bytes = |value: u32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let entry_count = images.len() + 1;
+        let entries_offset = 8 + 2 + 12 + 4;
+        let tiff_size = entries_offset + entry_count * MP_ENTRY_SIZE;
+        let segment_size = 2 + MPF_IDENTIFIER.len() + tiff_size;
+        let primary_size = 2 + 2 + segment_size + 2;
+
+        let mut tiff = Vec::new();
+        tiff.extend_from_slice(if big_endian { b"MM\x00\x2A" } else { b"II\x2A\x00" });
+        tiff.extend_from_slice(&u32_bytes(8));
+        tiff.extend_from_slice(&u16_bytes(1));
+        tiff.extend_from_slice(&u16_bytes(MP_ENTRY_TAG));
+        tiff.extend_from_slice(&u16_bytes(7)); // UNDEFINED
+        tiff.extend_from_slice(&u32_bytes((entry_count * MP_ENTRY_SIZE) as u32));
+        tiff.extend_from_slice(&u32_bytes(entries_offset as u32));
+        tiff.extend_from_slice(&u32_bytes(0)); // Next IFD.
+        let mut entry = |size: usize, offset: usize| {
+            tiff.extend_from_slice(&u32_bytes(0));
+            tiff.extend_from_slice(&u32_bytes(size as u32));
+            tiff.extend_from_slice(&u32_bytes(offset as u32));
+            tiff.extend_from_slice(&[0; 4]);
+        };
+        entry(primary_size, 0);
+        let mpf_header = 2 + 4 + MPF_IDENTIFIER.len();
+        let mut offset = primary_size;
+        for image in images {
+            entry(image.len(), offset - mpf_header);
+            offset += image.len();
+        }
+
+        let mut jpeg = vec![0xFF, MARKER_SOI, 0xFF, MARKER_APP2];
+        jpeg.extend_from_slice(&(segment_size as u16).to_be_bytes());
+        jpeg.extend_from_slice(MPF_IDENTIFIER);
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, MARKER_EOI]);
+        assert_eq!(jpeg.len(), primary_size);
+        for image in images {
+            jpeg.extend_from_slice(image);
+        }
+        jpeg
+    }
+
+    const AVIF: &[u8] = &[
+        0x00, 0x00, 0x00, 0x14, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f', 0x00, 0x00, 0x00,
+        0x00, b'm', b'i', b'f', b'1', 0x00, 0x00, 0x00, 0x08, b'm', b'e', b't', b'a',
+    ];
+    const NOT_AVIF: &[u8] = &[0xFF, MARKER_SOI, 0xFF, MARKER_EOI];
+
+    #[test]
+    fn embedded_avif() {
+        for big_endian in [true, false] {
+            let jpeg = jpeg_with_mpf(&[NOT_AVIF, AVIF], big_endian);
+            assert_eq!(extract_embedded_avif(&jpeg).as_deref(), Some(AVIF));
+        }
+    }
+
+    #[test]
+    fn no_embedded_avif() {
+        assert_eq!(extract_embedded_avif(AVIF), None);
+        assert_eq!(extract_embedded_avif(NOT_AVIF), None);
+        let jpeg = jpeg_with_mpf(&[NOT_AVIF], false);
+        assert_eq!(extract_embedded_avif(&jpeg), None);
+        // Truncated additional image.
+        let jpeg = jpeg_with_mpf(&[AVIF], false);
+        assert_eq!(extract_embedded_avif(&jpeg[..jpeg.len() - 1]), None);
+    }
+}
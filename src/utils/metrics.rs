@@ -0,0 +1,244 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Quality metrics between two images, for example to pick the lowest encoding quality that stays
+// above a target. The images must have the same dimensions, depth, pixel format and planes,
+// otherwise AvifError::InvalidArgument is returned. Images of different depths are not rescaled.
+
+use crate::image::*;
+use crate::*;
+
+// PSNR returned for identical images, and the maximum PSNR returned by psnr() and
+// Image::compare().
+pub const MAX_PSNR: f64 = 99.0;
+
+const SSIM_WINDOW_SIZE: usize = 8;
+const SSIM_WINDOW_STEP: usize = 4;
+
+pub(crate) fn check_comparable(image1: &Image, image2: &Image) -> AvifResult<()> {
+    if !image1.has_same_properties(image2) || image1.yuv_format != image2.yuv_format {
+        return Err(AvifError::InvalidArgument);
+    }
+    for plane in ALL_PLANES {
+        if image1.has_plane(plane) != image2.has_plane(plane) {
+            return Err(AvifError::InvalidArgument);
+        }
+    }
+    Ok(())
+}
+
+// Returns the sum of the squared differences and the largest absolute difference between the
+// samples of plane in both images. The plane must be present in both images.
+pub(crate) fn plane_error(image1: &Image, image2: &Image, plane: Plane) -> AvifResult<(u64, u16)> {
+    let width = image1.width(plane);
+    let mut squared_error_sum = 0u64;
+    let mut max_abs_diff = 0u16;
+    for y in 0..image1.height(plane) as u32 {
+        let diffs: Vec<u16> = if image1.depth == 8 {
+            let row1 = &image1.row(plane, y)?[..width];
+            let row2 = &image2.row(plane, y)?[..width];
+            row1.iter()
+                .zip(row2)
+                .map(|(a, b)| a.abs_diff(*b) as u16)
+                .collect()
+        } else {
+            let row1 = &image1.row16(plane, y)?[..width];
+            let row2 = &image2.row16(plane, y)?[..width];
+            row1.iter().zip(row2).map(|(a, b)| a.abs_diff(*b)).collect()
+        };
+        for diff in diffs {
+            squared_error_sum += (diff as u64) * (diff as u64);
+            max_abs_diff = max_abs_diff.max(diff);
+        }
+    }
+    Ok((squared_error_sum, max_abs_diff))
+}
+
+// Converts the sum of the squared differences over sample_count samples to a PSNR in dB, capped at
+// MAX_PSNR. sample_count must not be 0.
+pub(crate) fn psnr_from_error(
+    squared_error_sum: u64,
+    sample_count: usize,
+    max_channel: u16,
+) -> f64 {
+    if squared_error_sum == 0 {
+        return MAX_PSNR;
+    }
+    let max_channel = max_channel as f64;
+    let mse = squared_error_sum as f64 / sample_count as f64;
+    (10.0 * (max_channel * max_channel / mse).log10()).min(MAX_PSNR)
+}
+
+// Returns the PSNR in dB over the samples of all the planes (subsampled chroma planes count for
+// fewer samples), or MAX_PSNR if the images are identical. Reads each sample once.
+pub fn psnr(image1: &Image, image2: &Image) -> AvifResult<f64> {
+    check_comparable(image1, image2)?;
+    let mut squared_error_sum = 0u64;
+    let mut sample_count = 0usize;
+    for plane in ALL_PLANES {
+        if !image1.has_plane(plane) {
+            continue;
+        }
+        squared_error_sum += plane_error(image1, image2, plane)?.0;
+        sample_count += image1.width(plane) * image1.height(plane);
+    }
+    if sample_count == 0 {
+        return Err(AvifError::InvalidArgument);
+    }
+    Ok(psnr_from_error(
+        squared_error_sum,
+        sample_count,
+        image1.max_channel(),
+    ))
+}
+
+fn plane_samples(image: &Image, plane: Plane) -> AvifResult<Vec<f64>> {
+    let width = image.width(plane);
+    let mut samples = Vec::with_capacity(width * image.height(plane));
+    for y in 0..image.height(plane) as u32 {
+        if image.depth == 8 {
+            samples.extend(image.row(plane, y)?[..width].iter().map(|v| *v as f64));
+        } else {
+            samples.extend(image.row16(plane, y)?[..width].iter().map(|v| *v as f64));
+        }
+    }
+    Ok(samples)
+}
+
+// Returns the mean SSIM of plane over 8x8 windows placed every 4 samples, with the constants of
+// Wang et al. (2004). The result is 1.0 for identical planes and decreases towards -1.0 as the
+// structure differs. Planes smaller than a window are treated as a single window. Each sample is
+// read by up to 4 windows, so this costs several times more than psnr() for the same plane, and
+// the plane of both images is copied to f64 buffers.
+pub fn ssim(image1: &Image, image2: &Image, plane: Plane) -> AvifResult<f64> {
+    check_comparable(image1, image2)?;
+    if !image1.has_plane(plane) {
+        return Err(AvifError::InvalidArgument);
+    }
+    let width = image1.width(plane);
+    let height = image1.height(plane);
+    let samples1 = plane_samples(image1, plane)?;
+    let samples2 = plane_samples(image2, plane)?;
+    let max_channel = image1.max_channel() as f64;
+    let c1 = (0.01 * max_channel) * (0.01 * max_channel);
+    let c2 = (0.03 * max_channel) * (0.03 * max_channel);
+    let window_width = width.min(SSIM_WINDOW_SIZE);
+    let window_height = height.min(SSIM_WINDOW_SIZE);
+    let window_size = (window_width * window_height) as f64;
+    let mut ssim_sum = 0.0;
+    let mut window_count = 0u32;
+    for window_y in (0..=height - window_height).step_by(SSIM_WINDOW_STEP) {
+        for window_x in (0..=width - window_width).step_by(SSIM_WINDOW_STEP) {
+            let (mut sum1, mut sum2, mut sum11, mut sum22, mut sum12) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in window_y..window_y + window_height {
+                let row = y * width;
+                for x in window_x..window_x + window_width {
+                    let a = samples1[row + x];
+                    let b = samples2[row + x];
+                    sum1 += a;
+                    sum2 += b;
+                    sum11 += a * a;
+                    sum22 += b * b;
+                    sum12 += a * b;
+                }
+            }
+            let mean1 = sum1 / window_size;
+            let mean2 = sum2 / window_size;
+            let variance1 = sum11 / window_size - mean1 * mean1;
+            let variance2 = sum22 / window_size - mean2 * mean2;
+            let covariance = sum12 / window_size - mean1 * mean2;
+            ssim_sum += ((2.0 * mean1 * mean2 + c1) * (2.0 * covariance + c2))
+                / ((mean1 * mean1 + mean2 * mean2 + c1) * (variance1 + variance2 + c2));
+            window_count += 1;
+        }
+    }
+    Ok(ssim_sum / window_count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal_utils::fill_color_planes;
+
+    // Returns an image with a diagonal gradient in every plane, inverted if requested.
+    fn gradient_image(depth: u8, yuv_format: PixelFormat, inverted: bool) -> Image {
+        let mut image = Image {
+            width: 33,
+            height: 17,
+            depth,
+            yuv_format,
+            ..Default::default()
+        };
+        let max_channel = image.max_channel() as usize;
+        fill_color_planes(&mut image, |_, x, y| {
+            let value = ((x + y) * 7).min(max_channel);
+            (if inverted { max_channel - value } else { value }) as u16
+        });
+        image
+    }
+
+    #[test_case::test_case(8, PixelFormat::Yuv420)]
+    #[test_case::test_case(10, PixelFormat::Yuv444)]
+    #[test_case::test_case(12, PixelFormat::Yuv400)]
+    fn identical_images(depth: u8, yuv_format: PixelFormat) {
+        let image = gradient_image(depth, yuv_format, false);
+        assert_eq!(psnr(&image, &image), Ok(MAX_PSNR));
+        for plane in YUV_PLANES {
+            if image.has_plane(plane) {
+                assert!((ssim(&image, &image, plane).unwrap() - 1.0).abs() < 1e-9);
+            } else {
+                assert!(ssim(&image, &image, plane).is_err());
+            }
+        }
+    }
+
+    #[test_case::test_case(8, PixelFormat::Yuv420)]
+    #[test_case::test_case(10, PixelFormat::Yuv422)]
+    fn inverted_images(depth: u8, yuv_format: PixelFormat) {
+        let image = gradient_image(depth, yuv_format, false);
+        let inverted = gradient_image(depth, yuv_format, true);
+        assert!(psnr(&image, &inverted).unwrap() < 10.0);
+        for plane in YUV_PLANES {
+            assert!(ssim(&image, &inverted, plane).unwrap() < 0.1);
+        }
+    }
+
+    #[test]
+    fn one_sample_difference() {
+        let image = gradient_image(8, PixelFormat::Yuv444, false);
+        let mut perturbed = gradient_image(8, PixelFormat::Yuv444, false);
+        perturbed.row_mut(Plane::Y, 3).unwrap()[5] += 1;
+        // One sample out of 3 * 33 * 17 is off by one.
+        let expected_psnr = 10.0 * (255.0f64 * 255.0 * (3.0 * 33.0 * 17.0)).log10();
+        assert!((psnr(&image, &perturbed).unwrap() - expected_psnr).abs() < 1e-9);
+        let ssim_y = ssim(&image, &perturbed, Plane::Y).unwrap();
+        assert!(ssim_y > 0.99 && ssim_y < 1.0);
+        assert_eq!(ssim(&image, &perturbed, Plane::U), Ok(1.0));
+    }
+
+    #[test]
+    fn mismatched_images() {
+        let image = gradient_image(8, PixelFormat::Yuv420, false);
+        for other in [
+            gradient_image(10, PixelFormat::Yuv420, false),
+            gradient_image(8, PixelFormat::Yuv444, false),
+        ] {
+            assert_eq!(psnr(&image, &other), Err(AvifError::InvalidArgument));
+            assert_eq!(
+                ssim(&image, &other, Plane::Y),
+                Err(AvifError::InvalidArgument)
+            );
+        }
+    }
+}
@@ -12,6 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod checksum;
 pub mod clap;
+#[cfg(feature = "compare")]
+pub mod compare;
+#[cfg(feature = "image-crate")]
+pub mod image_crate;
+pub mod jpeg;
+#[cfg(feature = "compare")]
+pub mod metrics;
+#[cfg(feature = "conversion")]
+pub mod ppm;
 pub mod raw;
 pub mod y4m;
+
+pub use jpeg::extract_embedded_avif;
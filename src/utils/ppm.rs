@@ -0,0 +1,128 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::image::Image;
+use crate::reformat::rgb;
+use crate::*;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+// Writes the image to path as a binary netpbm file, for quick visual checks: PGM (P5) for
+// monochrome images and PPM (P6) otherwise. The samples are converted to RGB with 8 bits for
+// 8-bit images and 16 bits (maxval 65535, big-endian) for higher depths. The alpha plane is
+// ignored.
+pub fn write(image: &Image, path: &str) -> AvifResult<()> {
+    let mut rgb = rgb::Image::create_from_yuv(image);
+    rgb.format = rgb::Format::Rgb;
+    rgb.depth = if image.depth == 8 { 8 } else { 16 };
+    rgb.allocate()?;
+    rgb.convert_from_yuv(image)?;
+
+    let monochrome = image.yuv_format == PixelFormat::Yuv400;
+    let magic = if monochrome { "P5" } else { "P6" };
+    let maxval = rgb.max_channel();
+    let file = File::create(path).or(Err(AvifError::IoError))?;
+    let mut writer = BufWriter::new(file);
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(format!("{magic}\n{} {}\n{maxval}\n", rgb.width, rgb.height).as_bytes());
+    // R, G and B are equal for monochrome images, so only R is written.
+    let step = if monochrome { 3 } else { 1 };
+    let width = rgb.width as usize * 3;
+    for y in 0..rgb.height {
+        if rgb.depth == 8 {
+            data.extend(rgb.row(y)?[..width].iter().step_by(step));
+        } else {
+            for sample in rgb.row16(y)?[..width].iter().step_by(step) {
+                data.extend_from_slice(&sample.to_be_bytes());
+            }
+        }
+        writer.write_all(&data).or(Err(AvifError::IoError))?;
+        data.clear();
+    }
+    writer.flush().or(Err(AvifError::IoError))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::*;
+    use crate::internal_utils::fill_color_planes;
+
+    // Returns a full range identity image so that the RGB samples are the YUV samples: G is Y, B is
+    // U and R is V.
+    fn gradient_image(depth: u8, yuv_format: PixelFormat) -> Image {
+        let mut image = Image {
+            width: 7,
+            height: 3,
+            depth,
+            yuv_format,
+            yuv_range: YuvRange::Full,
+            matrix_coefficients: MatrixCoefficients::Identity,
+            ..Default::default()
+        };
+        fill_color_planes(&mut image, |plane, x, y| {
+            (x * 30 + y * 10 + plane.as_usize()) as u16
+        });
+        image
+    }
+
+    fn write_and_read(image: &Image) -> Vec<u8> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        write(image, path).unwrap();
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn color_8bit() {
+        let data = write_and_read(&gradient_image(8, PixelFormat::Yuv444));
+        let header = b"P6\n7 3\n255\n";
+        assert_eq!(&data[..header.len()], header);
+        let pixels = &data[header.len()..];
+        assert_eq!(pixels.len(), 7 * 3 * 3);
+        // (x, y) = (0, 0), (1, 0) and (6, 2) in R, G, B order.
+        assert_eq!(pixels[0..3], [2, 0, 1]);
+        assert_eq!(pixels[3..6], [32, 30, 31]);
+        assert_eq!(pixels[pixels.len() - 3..], [202, 200, 201]);
+    }
+
+    #[test]
+    fn monochrome_8bit() {
+        let data = write_and_read(&gradient_image(8, PixelFormat::Yuv400));
+        let header = b"P5\n7 3\n255\n";
+        assert_eq!(&data[..header.len()], header);
+        let pixels = &data[header.len()..];
+        assert_eq!(pixels.len(), 7 * 3);
+        assert_eq!(pixels[0..3], [0, 30, 60]);
+        assert_eq!(pixels[7], 10);
+    }
+
+    #[test]
+    fn color_10bit() {
+        let data = write_and_read(&gradient_image(10, PixelFormat::Yuv444));
+        let header = b"P6\n7 3\n65535\n";
+        assert_eq!(&data[..header.len()], header);
+        let pixels = &data[header.len()..];
+        assert_eq!(pixels.len(), 7 * 3 * 3 * 2);
+        let sample = |index: usize| u16::from_be_bytes([pixels[index * 2], pixels[index * 2 + 1]]);
+        // 10-bit values are scaled to 16 bits.
+        let scale = |value: u32| ((value * 65535 + 511) / 1023) as u16;
+        assert_eq!(sample(0), scale(2));
+        assert_eq!(sample(1), 0);
+        assert_eq!(sample(5), scale(31));
+        assert_eq!(sample(7 * 3 * 3 - 2), scale(200));
+    }
+}
@@ -14,6 +14,7 @@
 
 use crate::image::Image;
 use crate::image::ALL_PLANES;
+#[cfg(feature = "conversion")]
 use crate::reformat::rgb;
 use crate::OptionExtension;
 
@@ -47,38 +48,49 @@ impl RawWriter {
         true
     }
 
+    #[cfg(feature = "conversion")]
+    fn write_rgb_frame(&mut self, image: &Image) -> bool {
+        let mut rgb = rgb::Image::create_from_yuv(image);
+        rgb.format = rgb::Format::Rgba;
+        rgb.depth = 16;
+        //rgb.depth = 8;
+        rgb.premultiply_alpha = true;
+        rgb.is_float = true;
+        if rgb.allocate().is_err() || rgb.convert_from_yuv(image).is_err() {
+            return false;
+        }
+        for y in 0..rgb.height {
+            if rgb.depth == 8 {
+                let row = rgb.row(y).unwrap();
+                if self.file.unwrap_ref().write_all(row).is_err() {
+                    return false;
+                }
+            } else {
+                let row = rgb.row16(y).unwrap();
+                let mut row16: Vec<u8> = Vec::new();
+                for &pixel in row {
+                    row16.extend_from_slice(&pixel.to_be_bytes());
+                }
+                if self.file.unwrap_ref().write_all(&row16[..]).is_err() {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    #[cfg(not(feature = "conversion"))]
+    fn write_rgb_frame(&mut self, _image: &Image) -> bool {
+        // RGB output requires the conversion feature.
+        false
+    }
+
     pub fn write_frame(&mut self, image: &Image) -> bool {
         if !self.write_header() {
             return false;
         }
         if self.rgb {
-            let mut rgb = rgb::Image::create_from_yuv(image);
-            rgb.format = rgb::Format::Rgba;
-            rgb.depth = 16;
-            //rgb.depth = 8;
-            rgb.premultiply_alpha = true;
-            rgb.is_float = true;
-            if rgb.allocate().is_err() || rgb.convert_from_yuv(image).is_err() {
-                return false;
-            }
-            for y in 0..rgb.height {
-                if rgb.depth == 8 {
-                    let row = rgb.row(y).unwrap();
-                    if self.file.unwrap_ref().write_all(row).is_err() {
-                        return false;
-                    }
-                } else {
-                    let row = rgb.row16(y).unwrap();
-                    let mut row16: Vec<u8> = Vec::new();
-                    for &pixel in row {
-                        row16.extend_from_slice(&pixel.to_be_bytes());
-                    }
-                    if self.file.unwrap_ref().write_all(&row16[..]).is_err() {
-                        return false;
-                    }
-                }
-            }
-            return true;
+            return self.write_rgb_frame(image);
         }
         for plane in ALL_PLANES {
             let plane_data = image.plane_data(plane);
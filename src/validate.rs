@@ -0,0 +1,248 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::decoder::item::*;
+use crate::decoder::GenericIO;
+use crate::internal_utils::io::*;
+use crate::parser::mp4box;
+use crate::parser::mp4box::*;
+use crate::parser::obu::Av1SequenceHeader;
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    // The file could not be parsed. This includes the rules that are always enforced by the
+    // parser, such as the 'pict' handler type or a single pitm box.
+    Parse,
+    // The primary item is missing or is not an image item.
+    PrimaryItem,
+    // MIAF 7.3.6.7: Every image item shall have an ispe property.
+    MissingIspe,
+    // AVIF 2.2.1: Every image item shall have a pixi property.
+    MissingPixi,
+    // The codec configuration property is missing or does not match the sequence header.
+    CodecConfig,
+    // MIAF 7.3.11.4.2: Grid tiles shall be at least 64x64 pixels, with even dimensions along the
+    // subsampled directions.
+    GridTileSize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub rule: Rule,
+    // The item the violation was found in. None for file level violations.
+    pub item_id: Option<u32>,
+    pub message: String,
+}
+
+impl Violation {
+    fn create(rule: Rule, item_id: Option<u32>, message: String) -> Self {
+        Self {
+            rule,
+            item_id,
+            message,
+        }
+    }
+}
+
+// Checks the given file against a set of MIAF/HEIF/AVIF rules and returns all the rules that it
+// violates. This does not decode any pixels. An empty list means that no violation was found.
+pub fn check(data: &[u8]) -> Vec<Violation> {
+    let mut io: GenericIO = Box::new(DecoderMemoryIO {
+        data: data.to_vec(),
+    });
+    let avif_boxes = match mp4box::parse(&mut io) {
+        Ok(avif_boxes) => avif_boxes,
+        Err(err) => return vec![Violation::create(Rule::Parse, None, format!("{err:?}"))],
+    };
+    let mut items = match construct_items(&avif_boxes.meta) {
+        Ok(items) => items,
+        Err(err) => return vec![Violation::create(Rule::Parse, None, format!("{err:?}"))],
+    };
+    let mut violations = Vec::new();
+    if !items.is_empty() {
+        let primary_item_id = avif_boxes.meta.primary_item_id;
+        match items.get(&primary_item_id) {
+            Some(item) if item.is_image_item() => {}
+            _ => violations.push(Violation::create(
+                Rule::PrimaryItem,
+                None,
+                format!("primary item id {primary_item_id} is not an image item"),
+            )),
+        }
+    }
+    let image_item_ids: Vec<u32> = items
+        .values()
+        .filter(|x| x.is_image_item() && !x.has_unsupported_essential_property)
+        .map(|x| x.id)
+        .collect();
+    for item_id in image_item_ids {
+        check_item(&mut items, item_id, &mut io, &mut violations);
+    }
+    violations
+}
+
+fn check_item(
+    items: &mut Items,
+    item_id: u32,
+    io: &mut GenericIO,
+    violations: &mut Vec<Violation>,
+) {
+    let item = items.get(&item_id).unwrap();
+    let properties = &item.properties;
+    if find_property!(properties, ImageSpatialExtents).is_none() {
+        violations.push(Violation::create(
+            Rule::MissingIspe,
+            Some(item_id),
+            "missing ispe property".into(),
+        ));
+    }
+    if item.pixi().is_none() {
+        violations.push(Violation::create(
+            Rule::MissingPixi,
+            Some(item_id),
+            "missing pixi property".into(),
+        ));
+    }
+    if item.item_type == "grid" {
+        check_grid_tiles(items, item_id, violations);
+        return;
+    }
+    if !item.is_image_codec_item() {
+        // Derived image items get their codec configuration from their input images.
+        return;
+    }
+    let codec_config = match item.codec_config() {
+        Some(codec_config) => codec_config.clone(),
+        None => {
+            violations.push(Violation::create(
+                Rule::CodecConfig,
+                Some(item_id),
+                "missing codec configuration property".into(),
+            ));
+            return;
+        }
+    };
+    if let CodecConfiguration::Av1(av1_config) = &codec_config {
+        let item = items.get_mut(&item_id).unwrap();
+        if let Some(message) = check_sequence_header(item, io, av1_config) {
+            violations.push(Violation::create(Rule::CodecConfig, Some(item_id), message));
+        }
+    }
+}
+
+fn check_grid_tiles(items: &Items, grid_item_id: u32, violations: &mut Vec<Violation>) {
+    for tile in items.values().filter(|x| x.dimg_for_id == grid_item_id) {
+        let ispe = match find_property!(tile.properties, ImageSpatialExtents) {
+            Some(ispe) => ispe,
+            // Reported as a missing ispe on the tile itself.
+            None => continue,
+        };
+        if ispe.width < 64 || ispe.height < 64 {
+            violations.push(Violation::create(
+                Rule::GridTileSize,
+                Some(tile.id),
+                format!(
+                    "grid tile is {}x{}, smaller than 64x64",
+                    ispe.width, ispe.height
+                ),
+            ));
+        }
+        let (subsampled_x, subsampled_y) = match tile.codec_config().map(|x| x.pixel_format()) {
+            Some(PixelFormat::Yuv420) => (true, true),
+            Some(PixelFormat::Yuv422) => (true, false),
+            _ => (false, false),
+        };
+        if (subsampled_x && ispe.width % 2 != 0) || (subsampled_y && ispe.height % 2 != 0) {
+            violations.push(Violation::create(
+                Rule::GridTileSize,
+                Some(tile.id),
+                format!(
+                    "grid tile is {}x{}, odd along a subsampled direction",
+                    ispe.width, ispe.height
+                ),
+            ));
+        }
+    }
+}
+
+fn check_sequence_header(
+    item: &mut Item,
+    io: &mut GenericIO,
+    av1_config: &Av1CodecConfiguration,
+) -> Option<String> {
+    let mut stream = match item.stream(io) {
+        Ok(stream) => stream,
+        Err(err) => return Some(format!("could not read the item payload: {err:?}")),
+    };
+    let data = match stream.bytes_left().and_then(|size| stream.get_slice(size)) {
+        Ok(data) => data,
+        Err(err) => return Some(format!("could not read the item payload: {err:?}")),
+    };
+    let sequence_header = match Av1SequenceHeader::parse_from_obus(data) {
+        Ok(sequence_header) => sequence_header,
+        Err(_) => return Some("no sequence header was found in the item payload".into()),
+    };
+    let header_config = sequence_header.config();
+    let fields = [
+        (
+            "seq_profile",
+            av1_config.seq_profile,
+            header_config.seq_profile,
+        ),
+        (
+            "seq_level_idx_0",
+            av1_config.seq_level_idx0,
+            header_config.seq_level_idx0,
+        ),
+        ("seq_tier_0", av1_config.seq_tier0, header_config.seq_tier0),
+        (
+            "high_bitdepth",
+            av1_config.high_bitdepth as u8,
+            header_config.high_bitdepth as u8,
+        ),
+        (
+            "twelve_bit",
+            av1_config.twelve_bit as u8,
+            header_config.twelve_bit as u8,
+        ),
+        (
+            "monochrome",
+            av1_config.monochrome as u8,
+            header_config.monochrome as u8,
+        ),
+        (
+            "chroma_subsampling_x",
+            av1_config.chroma_subsampling_x,
+            header_config.chroma_subsampling_x,
+        ),
+        (
+            "chroma_subsampling_y",
+            av1_config.chroma_subsampling_y,
+            header_config.chroma_subsampling_y,
+        ),
+    ];
+    let mismatches: Vec<String> = fields
+        .iter()
+        .filter(|(_, config_value, header_value)| config_value != header_value)
+        .map(|(name, config_value, header_value)| {
+            format!("{name} is {config_value} in av1C but {header_value} in the sequence header")
+        })
+        .collect();
+    if mismatches.is_empty() {
+        None
+    } else {
+        Some(mismatches.join(", "))
+    }
+}
@@ -57,6 +57,7 @@ fn main() {
         "AMediaCodec_delete",
         "AMediaCodec_dequeueInputBuffer",
         "AMediaCodec_dequeueOutputBuffer",
+        "AMediaCodec_flush",
         "AMediaCodec_getInputBuffer",
         "AMediaCodec_getOutputBuffer",
         "AMediaCodec_getOutputFormat",
@@ -96,6 +96,27 @@ fn verify_info(expected_info: &ExpectedImageInfo, image: &Image) {
     );
 }
 
+#[test]
+fn test_netflix_is_hdr() {
+    // Only uses the expected metadata, so this does not need the conformance files.
+    let mut count = 0;
+    for info in EXPECTED_INFOS
+        .iter()
+        .filter(|info| info.filename.starts_with("Netflix/avif/"))
+    {
+        let image = Image {
+            depth: info.depth,
+            color_primaries: info.color_primaries.into(),
+            transfer_characteristics: info.transfer_characteristics.into(),
+            ..Default::default()
+        };
+        let expected = info.filename.starts_with("Netflix/avif/hdr_");
+        assert_eq!(image.is_hdr(), expected, "{}", info.filename);
+        count += 1;
+    }
+    assert!(count > 0);
+}
+
 fn get_tempfile() -> String {
     let file = NamedTempFile::new().expect("unable to open tempfile");
     let path = file.into_temp_path();
@@ -181,6 +202,142 @@ fn test_conformance(index: usize) {
     let _ = remove_file(gold_y4m_file);
 }
 
+#[test_case::test_case(0)]
+#[test_case::test_case(1)]
+#[test_case::test_case(2)]
+fn test_preferred_layer(layer: u16) {
+    // The a1lx file has the same layered bitstream without lsel, so its layers can be decoded
+    // one by one progressively and used as references.
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = decoder::Strictness::None;
+    decoder.settings.allow_progressive = true;
+    let filename = get_test_file("Apple/multilayer_examples/animals_00_multilayer_a1lx.avif");
+    let _ = decoder.set_io_file(&filename).expect("Failed to set IO");
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.nth_image(layer as u32).is_ok());
+    let expected_hash = decoder.image().expect("image was none").content_hash();
+    assert!(expected_hash.is_ok());
+
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = decoder::Strictness::None;
+    decoder.settings.preferred_layer = Some(layer);
+    let filename = get_test_file("Apple/multilayer_examples/animals_00_multilayer_lsel.avif");
+    let _ = decoder.set_io_file(&filename).expect("Failed to set IO");
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.image_count(), 1);
+    assert!(decoder.next_image().is_ok());
+    let image = decoder.image().expect("image was none");
+    // All the layers have the dimensions of the item, and the decoded image is the selected one.
+    let expected_info = EXPECTED_INFOS
+        .iter()
+        .find(|info| info.filename.ends_with("animals_00_multilayer_lsel.avif"))
+        .unwrap();
+    verify_info(expected_info, &image);
+    assert_eq!(image.content_hash(), expected_hash);
+}
+
+#[test_case::test_case("Link-U/fox.profile0.8bpc.yuv420.avif")]
+#[test_case::test_case("Link-U/fox.profile0.10bpc.yuv420.avif")]
+fn test_force_full_range_output(filename: &str) {
+    let filename = get_test_file(filename);
+    let decode = |force_full_range_output| {
+        let mut decoder = decoder::Decoder::default();
+        decoder.settings.force_full_range_output = force_full_range_output;
+        let _ = decoder.set_io_file(&filename).expect("Failed to set IO");
+        assert!(decoder.parse().is_ok());
+        assert!(decoder.next_image().is_ok());
+        let image = decoder.image().expect("image was none");
+        let mut samples = Vec::new();
+        for plane in YUV_PLANES {
+            for y in 0..image.height(plane) as u32 {
+                if image.depth == 8 {
+                    samples.extend(image.row(plane, y).unwrap().iter().map(|v| *v as u16));
+                } else {
+                    samples.extend(image.row16(plane, y).unwrap().iter().copied());
+                }
+            }
+        }
+        let luma_count = image.width(Plane::Y) * image.height(Plane::Y);
+        (image.depth, image.yuv_range, luma_count, samples)
+    };
+    let (depth, limited_range, luma_count, limited) = decode(false);
+    assert_eq!(limited_range, YuvRange::Limited);
+    let (_, full_range, _, full) = decode(true);
+    assert_eq!(full_range, YuvRange::Full);
+    assert_eq!(limited.len(), full.len());
+
+    // ISO/IEC 23091-2: Y is in [16, 235] and U, V are in [16, 240], scaled to the depth.
+    let scale = (1u32 << (depth - 8)) as f32;
+    let max = ((1u32 << depth) - 1) as f32;
+    let bias = (1u32 << (depth - 1)) as f32;
+    for (i, (limited, full)) in limited.iter().zip(full.iter()).enumerate() {
+        let limited = *limited as f32;
+        let expected = if i < luma_count {
+            ((limited - 16.0 * scale) / (219.0 * scale) * max).round()
+        } else {
+            ((limited - bias) / (224.0 * scale) * max).round() + bias
+        };
+        assert_eq!(*full, expected.clamp(0.0, max) as u16);
+    }
+}
+
+#[test]
+fn test_preferred_layer_out_of_range() {
+    let filename = get_test_file("Apple/multilayer_examples/animals_00_multilayer_lsel.avif");
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = decoder::Strictness::None;
+    decoder.settings.preferred_layer = Some(4);
+    let _ = decoder.set_io_file(&filename).expect("Failed to set IO");
+    // Rejected by the operating point validation if av1C contains the sequence header, otherwise
+    // when looking the layer up in a1lx.
+    assert!(matches!(
+        decoder.parse(),
+        Err(AvifError::BmffParseFailed(_)) | Err(AvifError::InvalidImageGrid(_))
+    ));
+}
+
+#[test_case::test_case("animals_00_multilayer_a1op.avif")]
+#[test_case::test_case("animals_00_multilayer_lsel.avif")]
+#[test_case::test_case("animals_00_multilayer_a1op_lsel.avif")]
+fn test_operating_point_and_layer_selection(filename: &str) {
+    // These files have consistent a1op and lsel properties and must pass the validation.
+    let filename = get_test_file(&format!("Apple/multilayer_examples/{filename}"));
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = decoder::Strictness::None;
+    let _ = decoder.set_io_file(&filename).expect("Failed to set IO");
+    assert!(decoder.parse().is_ok());
+}
+
+#[test_case::test_matrix(
+    ["animals_00_multilayer_grid_a1lx.avif", "animals_00_multilayer_grid_lsel.avif"],
+    [false, true]
+)]
+fn test_layered_grid(filename: &str, allow_progressive: bool) {
+    let filename = get_test_file(&format!("Apple/multilayer_examples/{filename}"));
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = decoder::Strictness::None;
+    decoder.settings.allow_progressive = allow_progressive;
+    let _ = decoder.set_io_file(&filename).expect("Failed to set IO");
+    assert!(decoder.parse().is_ok());
+    let image = decoder.image().expect("image was none");
+    let (width, height) = (image.width, image.height);
+    let image_count = decoder.image_count();
+    if !allow_progressive {
+        assert_eq!(image_count, 1);
+    }
+    // All the cells are at the same layer in each frame, so the grid is always assembled from
+    // matching tiles.
+    for _ in 0..image_count {
+        assert!(decoder.next_image().is_ok());
+        let image = decoder.image().expect("image was none");
+        assert_eq!((image.width, image.height), (width, height));
+    }
+    assert!(matches!(
+        decoder.next_image(),
+        Err(AvifError::NoImagesRemaining)
+    ));
+}
+
 // If more files are added to this array, update the call to generate_tests macro below.
 const EXPECTED_INFOS: [ExpectedImageInfo; 172] = [
     // index: 0
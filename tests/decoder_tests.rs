@@ -16,6 +16,7 @@ use crabby_avif::decoder::track::RepetitionCount;
 use crabby_avif::decoder::CompressionFormat;
 use crabby_avif::decoder::ImageContentType;
 use crabby_avif::image::*;
+#[cfg(feature = "conversion")]
 use crabby_avif::reformat::rgb;
 use crabby_avif::*;
 
@@ -57,6 +58,37 @@ fn alpha_no_ispe() {
     assert!(alpha_plane.unwrap().row_bytes > 0);
 }
 
+// The overlay item of overlay_yellow_bg.avif has no pixi property.
+#[test_case::test_case(decoder::Strictness::All, false)]
+#[test_case::test_case(decoder::Strictness::None, true)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::PixiRequired]), false)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::ClapValid]), true)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::PixiRequired]), true)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::ClapValid]), false)]
+fn missing_pixi(strictness: decoder::Strictness, expect_ok: bool) {
+    let mut decoder = get_decoder("overlay_yellow_bg.avif");
+    decoder.settings.strictness = strictness;
+    let res = decoder.parse();
+    if expect_ok {
+        assert!(res.is_ok());
+    } else {
+        assert!(matches!(res, Err(AvifError::BmffParseFailed(_))));
+    }
+}
+
+// The alpha grid item that is made up from the alpha auxiliary images of the color tiles has no
+// pixi property, which must never be a reason to reject the file.
+#[test_case::test_case(decoder::Strictness::All)]
+#[test_case::test_case(decoder::Strictness::None)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::PixiRequired]))]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::PixiRequired]))]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::AlphaIspeRequired]))]
+fn made_up_alpha_item_without_pixi(strictness: decoder::Strictness) {
+    let mut decoder = get_decoder("color_grid_alpha_nogrid.avif");
+    decoder.settings.strictness = strictness;
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.image().expect("image was none").alpha_present);
+}
 // From avifanimationtest.cc
 #[test]
 fn animated_image() {
@@ -106,6 +138,227 @@ fn animated_image_with_source_set_to_primary_item() {
     assert!(decoder.next_image().is_err());
 }
 
+#[test_case::test_case("colors-animated-8bpc.avif", Some(1694021753))]
+#[test_case::test_case("colors-animated-12bpc-keyframes-0-2-3.avif", Some(1713365419))]
+#[test_case::test_case("white_1x1.avif", None)]
+fn media_times(filename: &str, expected_time: Option<i64>) {
+    let mut decoder = get_decoder(filename);
+    assert_eq!(decoder.media_times(), None);
+    assert!(decoder.parse().is_ok());
+    let media_times = decoder.media_times();
+    assert_eq!(media_times.map(|x| x.creation_time), expected_time);
+    assert_eq!(media_times.map(|x| x.modification_time), expected_time);
+    if let Some(media_times) = media_times {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        assert!(media_times.creation_time > 0 && media_times.creation_time < now);
+    }
+}
+
+#[test_case::test_case(4, None, None)]
+#[test_case::test_case(4, Some(1), Some(1))]
+#[test_case::test_case(1, Some(3), Some(4))]
+fn dav1d_thread_configuration(
+    max_threads: u32,
+    dav1d_frame_threads: Option<u32>,
+    dav1d_tile_threads: Option<u32>,
+) {
+    if !cfg!(feature = "dav1d") {
+        return;
+    }
+    let decode_all = |settings: decoder::Settings| {
+        let mut decoder = get_decoder("colors-animated-8bpc.avif");
+        decoder.settings = settings;
+        assert!(decoder.parse().is_ok());
+        let mut hashes = Vec::new();
+        for _ in 0..decoder.image_count() {
+            assert!(decoder.next_image().is_ok());
+            let image = decoder.image().expect("image was none");
+            hashes.push(image.content_hash().unwrap());
+        }
+        hashes
+    };
+    let settings = decoder::Settings {
+        codec_choice: decoder::CodecChoice::Dav1d,
+        max_threads,
+        dav1d_frame_threads,
+        dav1d_tile_threads,
+        ..Default::default()
+    };
+    let reference_settings = decoder::Settings {
+        codec_choice: decoder::CodecChoice::Dav1d,
+        ..Default::default()
+    };
+    assert_eq!(decode_all(settings), decode_all(reference_settings));
+}
+
+#[test_case::test_case(|s| s.source = decoder::Source::Tracks ; "source")]
+#[test_case::test_case(|s| s.ignore_exif = true ; "ignore_exif")]
+#[test_case::test_case(|s| s.ignore_xmp = true ; "ignore_xmp")]
+#[test_case::test_case(|s| s.strictness = decoder::Strictness::None ; "strictness")]
+#[test_case::test_case(|s| s.allow_progressive = true ; "allow_progressive")]
+#[test_case::test_case(|s| s.image_size_limit = 1 << 20 ; "image_size_limit")]
+#[test_case::test_case(|s| s.image_dimension_limit = 1000 ; "image_dimension_limit")]
+#[test_case::test_case(|s| s.image_count_limit = 100 ; "image_count_limit")]
+#[test_case::test_case(|s| s.preferred_layer = Some(0) ; "preferred_layer")]
+#[test_case::test_case(|s| s.assume_identity_when_444_lossless = true ; "assume_identity")]
+fn parse_setting_changed_after_parse(change: fn(&mut decoder::Settings)) {
+    let mut decoder = get_decoder("colors-animated-8bpc.avif");
+    assert!(decoder.parse().is_ok());
+    change(&mut decoder.settings);
+    assert_eq!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+    assert_eq!(decoder.nth_image(1), Err(AvifError::CannotChangeSetting));
+    // Parsing again takes the new value into account.
+    assert!(decoder.parse().is_ok());
+    assert_ne!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+}
+
+#[test_case::test_case(|s| s.image_content_to_decode = ImageContentType::AlphaOnly ; "image_content_to_decode")]
+#[test_case::test_case(|s| s.codec_choice = decoder::CodecChoice::Libgav1 ; "codec_choice")]
+#[test_case::test_case(|s| s.max_threads = 2 ; "max_threads")]
+#[test_case::test_case(|s| s.dav1d_frame_threads = Some(2) ; "dav1d_frame_threads")]
+#[test_case::test_case(|s| s.dav1d_tile_threads = Some(2) ; "dav1d_tile_threads")]
+#[test_case::test_case(
+    |s| s.android_mediacodec_output_color_format = AndroidMediaCodecOutputColorFormat::P010
+    ; "android_mediacodec_output_color_format"
+)]
+fn codec_setting_changed_after_first_decode(change: fn(&mut decoder::Settings)) {
+    // The codecs are not created yet, so the setting can be changed after parse().
+    let mut decoder = get_decoder("colors-animated-8bpc-alpha-exif-xmp.avif");
+    assert!(decoder.parse().is_ok());
+    change(&mut decoder.settings);
+    assert_ne!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+    if !HAS_DECODER {
+        return;
+    }
+
+    let mut decoder = get_decoder("colors-animated-8bpc-alpha-exif-xmp.avif");
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.next_image().is_ok());
+    change(&mut decoder.settings);
+    assert_eq!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+    assert_eq!(decoder.nth_image(0), Err(AvifError::CannotChangeSetting));
+    assert!(decoder.parse().is_ok());
+    assert_ne!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+}
+
+#[test]
+fn setting_changed_any_time() {
+    let mut decoder = get_decoder("colors-animated-8bpc.avif");
+    assert!(decoder.parse().is_ok());
+    for frame in 0..decoder.image_count() {
+        decoder.settings.allow_incremental = frame % 2 == 0;
+        decoder.settings.skip_corrupt_frames = frame % 2 == 1;
+        let res = decoder.next_image();
+        if !HAS_DECODER {
+            assert_eq!(res, Err(AvifError::NoCodecAvailable));
+            return;
+        }
+        assert!(res.is_ok());
+    }
+}
+
+#[test]
+fn gainmap_requested_after_parse() {
+    let mut decoder = get_decoder("seine_sdr_gainmap_srgb.avif");
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.gainmap_present());
+    // The gain map image item was not read by parse().
+    decoder.settings.image_content_to_decode = ImageContentType::All;
+    assert_eq!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+    decoder.settings.image_content_to_decode = ImageContentType::GainMap;
+    assert_eq!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+    assert!(decoder.parse().is_ok());
+    assert_ne!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+
+    // Not decoding the gain map that was read by parse() is allowed.
+    let mut decoder = get_decoder("seine_sdr_gainmap_srgb.avif");
+    decoder.settings.image_content_to_decode = ImageContentType::All;
+    assert!(decoder.parse().is_ok());
+    decoder.settings.image_content_to_decode = ImageContentType::ColorAndAlpha;
+    assert_ne!(decoder.next_image(), Err(AvifError::CannotChangeSetting));
+    if HAS_DECODER {
+        assert!(!decoder.gainmap().image.has_plane(Plane::Y));
+    }
+}
+
+#[test]
+fn animated_image_next_image_into() {
+    if !HAS_DECODER {
+        return;
+    }
+    let mut decoder = get_decoder("colors-animated-8bpc.avif");
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.image_count(), 5);
+    let mut dst = Image::default();
+    let mut plane_pointers = Vec::new();
+    for frame in 0..10 {
+        if frame == 5 {
+            // Start over from the first frame.
+            assert!(decoder.parse().is_ok());
+        }
+        assert!(decoder.next_image_into(&mut dst).is_ok());
+        let image = decoder.image().expect("image was none");
+        assert_eq!(dst.width, image.width);
+        assert_eq!(dst.height, image.height);
+        assert_eq!(dst.depth, image.depth);
+        assert_eq!(dst.yuv_format, image.yuv_format);
+        for plane in YUV_PLANES {
+            for y in 0..image.height(plane) as u32 {
+                let width = image.width(plane);
+                assert_eq!(
+                    dst.row(plane, y).unwrap()[..width],
+                    image.row(plane, y).unwrap()[..width]
+                );
+            }
+        }
+        let pointers: Vec<_> = YUV_PLANES
+            .iter()
+            .map(|plane| dst.planes[*plane as usize].as_ref().unwrap().ptr())
+            .collect();
+        if frame == 0 {
+            plane_pointers = pointers;
+        } else {
+            // No new planes are allocated after the first frame.
+            assert_eq!(pointers, plane_pointers);
+        }
+    }
+}
+
+#[test]
+fn animated_image_with_timed_metadata_track() {
+    let mut decoder = get_decoder("colors-animated-8bpc-timed-metadata.avif");
+    let res = decoder.parse();
+    assert!(res.is_ok());
+    assert_eq!(decoder.image_count(), 5);
+    let samples = decoder.metadata_track_samples();
+    assert_eq!(samples.len(), 3);
+    for (i, sample) in samples.iter().enumerate() {
+        assert_eq!(sample.track_id, 2);
+        assert_eq!(sample.timing.timescale, 30);
+        assert_eq!(sample.timing.pts_in_timescales, 30 * i as u64);
+        assert_eq!(sample.timing.duration_in_timescales, 30);
+        assert_eq!(sample.timing.pts, i as f64);
+        assert_eq!(sample.data, format!("{{\"frame\":{i}}}").as_bytes());
+    }
+    if !HAS_DECODER {
+        return;
+    }
+    for _ in 0..5 {
+        assert!(decoder.next_image().is_ok());
+    }
+}
+
+#[test]
+fn animated_image_without_timed_metadata_track() {
+    let mut decoder = get_decoder("colors-animated-8bpc.avif");
+    let res = decoder.parse();
+    assert!(res.is_ok());
+    assert!(decoder.metadata_track_samples().is_empty());
+}
+
 // From avifanimationtest.cc
 #[test]
 fn animated_image_with_alpha_and_metadata() {
@@ -128,6 +381,104 @@ fn animated_image_with_alpha_and_metadata() {
     }
 }
 
+#[test_case::test_case(u64::MAX, RepetitionCount::Infinite ; "indefinite")]
+#[test_case::test_case(75, RepetitionCount::Finite(2) ; "three times")]
+#[test_case::test_case(60, RepetitionCount::Finite(2) ; "partial repetition")]
+#[test_case::test_case(25, RepetitionCount::Finite(0) ; "once")]
+fn repetition_count_from_track_duration(track_duration: u64, expected: RepetitionCount) {
+    // The tracks of colors-animated-8bpc-alpha-exif-xmp.avif have a repeated edit list with a
+    // segment duration of 25 and an indefinite track duration in their version 1 tkhd box.
+    let mut data = std::fs::read(get_test_file("colors-animated-8bpc-alpha-exif-xmp.avif"))
+        .expect("could not read file");
+    let mut offset = 0;
+    while let Some(tkhd) = data[offset..].windows(4).position(|x| x == b"tkhd") {
+        let tkhd = offset + tkhd;
+        assert_eq!(data[tkhd + 4], 1);
+        assert_eq!(data[tkhd + 32..tkhd + 40], u64::MAX.to_be_bytes());
+        data[tkhd + 32..tkhd + 40].copy_from_slice(&track_duration.to_be_bytes());
+        offset = tkhd + 4;
+    }
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.repetition_count(), expected);
+}
+
+#[test_case::test_case(3, 1, Some("stts covers 3 samples but the track has 5 samples") ; "short stts")]
+#[test_case::test_case(7, 1, Some("stts covers 7 samples but the track has 5 samples") ; "long stts")]
+#[test_case::test_case(5, 0, Some("stts has samples with a sample_delta of 0") ; "zero delta")]
+#[test_case::test_case(5, 1, None ; "valid")]
+fn incomplete_stts(sample_count: u32, sample_delta: u32, problem: Option<&str>) {
+    // The color track of colors-animated-8bpc.avif has 5 samples and a single stts entry of 5
+    // samples with a delta of 1.
+    let mut data =
+        std::fs::read(get_test_file("colors-animated-8bpc.avif")).expect("could not read file");
+    let stts = data
+        .windows(4)
+        .position(|x| x == b"stts")
+        .expect("box not found");
+    assert_eq!(data[stts + 8..stts + 12], 1u32.to_be_bytes());
+    data[stts + 12..stts + 16].copy_from_slice(&sample_count.to_be_bytes());
+    data[stts + 16..stts + 20].copy_from_slice(&sample_delta.to_be_bytes());
+    for strictness in [decoder::Strictness::All, decoder::Strictness::None] {
+        let strict = matches!(strictness, decoder::Strictness::All);
+        let mut decoder = decoder::Decoder::default();
+        decoder.settings.strictness = strictness;
+        decoder.set_io_vec(data.clone());
+        assert!(decoder.parse().is_ok());
+        assert_eq!(decoder.image_count(), 5);
+        let expected_diagnostics: Vec<String> = match problem {
+            Some(problem) if strict => vec![format!("track id 1: {problem}")],
+            _ => vec![],
+        };
+        assert_eq!(decoder.diagnostics(), &expected_diagnostics[..]);
+        // The samples that are not covered by stts use the last sample_delta.
+        for n in 0..decoder.image_count() {
+            let timing = decoder.nth_image_timing(n).expect("failed to get timing");
+            assert_eq!(timing.pts_in_timescales, (n * sample_delta) as u64);
+            assert_eq!(timing.duration_in_timescales, sample_delta as u64);
+            assert!(timing.pts.is_finite() && timing.pts >= 0.0);
+            assert!(timing.duration.is_finite() && timing.duration >= 0.0);
+        }
+    }
+}
+
+#[test_case::test_case("alpha.avif")]
+#[test_case::test_case("color_grid_alpha_nogrid.avif")]
+#[test_case::test_case("colors-animated-8bpc-alpha-exif-xmp.avif")]
+fn alpha_is_consistent_across_codecs(filename: &str) {
+    // Returns the checksums of the alpha plane of every frame, or None if the codec is not
+    // available.
+    let decode_alpha = |codec_choice: decoder::CodecChoice| -> Option<Vec<u64>> {
+        let mut decoder = get_decoder(filename);
+        decoder.settings.codec_choice = codec_choice;
+        assert!(decoder.parse().is_ok());
+        let mut checksums = Vec::new();
+        for _ in 0..decoder.image_count() {
+            match decoder.next_image() {
+                Err(AvifError::NoCodecAvailable) => return None,
+                res => assert!(res.is_ok()),
+            }
+            let image = decoder.image().expect("image was none");
+            assert_eq!(image.yuv_range, YuvRange::Full);
+            checksums.push(image.plane_checksum(Plane::A).unwrap());
+        }
+        Some(checksums)
+    };
+    let checksums: Vec<Vec<u64>> = [
+        decoder::CodecChoice::Dav1d,
+        decoder::CodecChoice::Libgav1,
+        decoder::CodecChoice::MediaCodec,
+    ]
+    .into_iter()
+    .filter_map(decode_alpha)
+    .collect();
+    assert_eq!(!checksums.is_empty(), HAS_DECODER);
+    for pair in checksums.windows(2) {
+        assert_eq!(pair[0], pair[1]);
+    }
+}
+
 // From avifkeyframetest.cc
 #[test]
 fn keyframes() {
@@ -160,6 +511,276 @@ fn keyframes() {
     assert_eq!(decoder.nearest_keyframe(15), 3);
 }
 
+// Returns colors-animated-12bpc-keyframes-0-2-3.avif with its second color sample (an inter
+// frame) corrupted.
+fn corrupt_second_sample() -> Vec<u8> {
+    let mut data = std::fs::read(get_test_file("colors-animated-12bpc-keyframes-0-2-3.avif"))
+        .expect("could not read file");
+    // All the color samples are in a single chunk. Locate the second sample from the first 'stco'
+    // and 'stsz' boxes.
+    let read_u32 = |data: &[u8], offset: usize| {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+    };
+    let stco = data.windows(4).position(|x| x == b"stco").unwrap();
+    let stsz = data.windows(4).position(|x| x == b"stsz").unwrap();
+    let sample1 = read_u32(&data, stco + 12) + read_u32(&data, stsz + 16);
+    // The sample is a temporal delimiter OBU followed by a frame OBU. Make the size of the frame
+    // OBU exceed the sample.
+    assert_eq!(data[sample1..sample1 + 3], [0x12, 0x00, 0x32]);
+    data[sample1 + 3] = 0x7f;
+    data
+}
+
+#[test_case::test_case(false)]
+#[test_case::test_case(true)]
+fn skip_corrupt_frames(skip_corrupt_frames: bool) {
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.skip_corrupt_frames = skip_corrupt_frames;
+    decoder.set_io_vec(corrupt_second_sample());
+    assert!(decoder.parse().is_ok());
+    assert!(!decoder.is_keyframe(1));
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    let res = decoder.next_image();
+    if !skip_corrupt_frames {
+        assert!(res.is_err());
+        return;
+    }
+    assert!(res.is_ok());
+    assert_eq!(decoder.diagnostics().len(), 1);
+    let image = decoder.image().expect("image was none");
+    let gray = 1u16 << (image.depth - 1);
+    let y = image.row16(Plane::Y, 0).unwrap();
+    assert!(y.iter().all(|&x| x == gray));
+    // Frames 2 and 3 are keyframes and decode normally.
+    assert!(decoder.next_image().is_ok());
+    let image = decoder.image().expect("image was none");
+    let y = image.row16(Plane::Y, 0).unwrap();
+    assert!(y.iter().any(|&x| x != gray));
+    assert!(decoder.next_image().is_ok());
+    assert!(decoder.next_image().is_ok());
+}
+
+#[test]
+fn skip_current_image() {
+    assert!(!AvifError::BmffParseFailed("".into()).is_recoverable());
+    assert!(!AvifError::TruncatedData.is_recoverable());
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(corrupt_second_sample());
+    assert_eq!(decoder.skip_current_image(), Err(AvifError::NoContent));
+    assert!(decoder.parse().is_ok());
+    if !HAS_DECODER {
+        return;
+    }
+    let mut decoded_indices = Vec::new();
+    loop {
+        match decoder.next_image() {
+            Ok(_) => decoded_indices.push(decoder.image_index()),
+            Err(AvifError::NoImagesRemaining) => break,
+            Err(err) => {
+                assert_eq!(err, AvifError::DecodeColorFailed);
+                assert!(err.is_recoverable());
+                assert!(decoder.skip_current_image().is_ok());
+                assert_eq!(decoder.image_index(), 1);
+            }
+        }
+    }
+    // Frame 2 is a keyframe, so only frame 1 is missing.
+    assert_eq!(decoded_indices, [0, 2, 3, 4]);
+    assert_eq!(
+        decoder.skip_current_image(),
+        Err(AvifError::NoImagesRemaining)
+    );
+}
+
+#[test]
+fn grid_next_image_into() {
+    if !HAS_DECODER {
+        return;
+    }
+    let mut decoder = get_decoder("sofa_grid1x5_420.avif");
+    let mut dst = Image::default();
+    let mut plane_pointers = Vec::new();
+    for iteration in 0..2 {
+        assert!(decoder.parse().is_ok());
+        assert!(decoder.next_image_into(&mut dst).is_ok());
+        let pointers: Vec<_> = YUV_PLANES
+            .iter()
+            .map(|plane| dst.planes[*plane as usize].as_ref().unwrap().ptr())
+            .collect();
+        if iteration == 0 {
+            plane_pointers = pointers;
+        } else {
+            // The tiles were composited into the planes of dst.
+            assert_eq!(pointers, plane_pointers);
+        }
+    }
+    let mut reference = get_decoder("sofa_grid1x5_420.avif");
+    assert!(reference.parse().is_ok());
+    assert!(reference.next_image().is_ok());
+    let image = reference.image().expect("image was none");
+    for plane in YUV_PLANES {
+        let width = image.width(plane);
+        for y in 0..image.height(plane) as u32 {
+            assert_eq!(
+                dst.row(plane, y).unwrap()[..width],
+                image.row(plane, y).unwrap()[..width]
+            );
+        }
+    }
+}
+
+#[test_case::test_case("white_1x1.avif")]
+#[test_case::test_case("alpha.avif")]
+#[test_case::test_case("color_grid_alpha_nogrid.avif")]
+fn next_image_into(filename: &str) {
+    if !HAS_DECODER {
+        return;
+    }
+    let mut decoder = get_decoder(filename);
+    assert!(decoder.parse().is_ok());
+    let mut dst = Image::default();
+    assert!(decoder.next_image_into(&mut dst).is_ok());
+    // The planes of grid images are moved to dst, so compare with another decoder.
+    let mut reference = get_decoder(filename);
+    assert!(reference.parse().is_ok());
+    assert!(reference.next_image().is_ok());
+    let image = reference.image().expect("image was none");
+    let mut expected_rows = Vec::new();
+    for plane in ALL_PLANES {
+        assert_eq!(dst.has_plane(plane), image.has_plane(plane));
+        if !image.has_plane(plane) {
+            continue;
+        }
+        for y in 0..image.height(plane) as u32 {
+            let width = image.width(plane);
+            let row = image.row(plane, y).unwrap()[..width].to_vec();
+            assert_eq!(dst.row(plane, y).unwrap()[..width], row);
+            expected_rows.push((plane, y, row));
+        }
+    }
+    // dst owns its planes, so it outlives the decoder and its codec buffers.
+    drop(decoder);
+    drop(reference);
+    for (plane, y, row) in expected_rows {
+        assert_eq!(dst.row(plane, y).unwrap()[..row.len()], row);
+    }
+}
+
+#[test]
+fn effectively_monochrome() {
+    let mut decoder = get_decoder("paris_icc_exif_xmp.avif");
+    assert!(decoder.parse().is_ok());
+    assert_eq!(
+        decoder.is_effectively_monochrome(0),
+        Err(AvifError::NoContent)
+    );
+    if !HAS_DECODER {
+        return;
+    }
+    let mut image = Image::default();
+    assert!(decoder.next_image_into(&mut image).is_ok());
+    assert_eq!(decoder.is_effectively_monochrome(0), Ok(false));
+    // The cached value is returned for the same image and tolerance.
+    assert_eq!(decoder.is_effectively_monochrome(0), Ok(false));
+    assert_eq!(decoder.is_effectively_monochrome(u16::MAX), Ok(true));
+
+    // Flatten the chroma planes of the decoded copy.
+    assert_eq!(image.yuv_format, PixelFormat::Yuv420);
+    assert_eq!(image.depth, 8);
+    for plane in [Plane::U, Plane::V] {
+        let width = image.width(plane);
+        for y in 0..image.height(plane) as u32 {
+            image.row_mut(plane, y).unwrap()[..width].fill(128);
+        }
+    }
+    assert!(image.is_effectively_monochrome(0));
+}
+
+#[test]
+fn multiple_exif_items() {
+    // Turn the XMP item 3 of paris_icc_exif_xmp.avif into a second Exif item. Both items describe
+    // the primary item 1 with a 'cdsc' reference.
+    let mut data =
+        std::fs::read(get_test_file("paris_icc_exif_xmp.avif")).expect("could not read file");
+    let mime = data.windows(8).position(|x| x == b"mimeXMP\0").unwrap();
+    data[mime..mime + 4].copy_from_slice(b"Exif");
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert_eq!(decoder.extract_item_data(2), Err(AvifError::NoContent));
+    assert!(decoder.parse().is_ok());
+    let item_infos = decoder.item_infos();
+    assert_eq!(
+        item_infos
+            .iter()
+            .map(|x| (x.item_id, x.item_type.as_str(), x.describes_item_id))
+            .collect::<Vec<_>>(),
+        [(1, "av01", 0), (2, "Exif", 1), (3, "Exif", 1)]
+    );
+
+    // The Exif item with the lowest id is used.
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.exif.len(), 1126);
+    assert!(image.xmp.is_empty());
+    let exif = image.exif.clone();
+    let first = decoder.extract_item_data(2).unwrap();
+    assert_eq!(first.len(), item_infos[1].size);
+    // The payload starts with the offset to the TIFF header.
+    assert_eq!(first[..4], [0, 0, 0, 0]);
+    assert_eq!(first[4..], exif);
+    let second = decoder.extract_item_data(3).unwrap();
+    assert_eq!(second.len(), item_infos[2].size);
+    assert!(second.starts_with(b"<?xpacket"));
+    assert_eq!(
+        decoder.extract_item_data(4),
+        Err(AvifError::InvalidArgument)
+    );
+}
+
+#[test]
+fn exif_item_preference() {
+    let mut decoder = get_decoder("paris_icc_exif_xmp.avif");
+    assert!(decoder.parse().is_ok());
+    let exif = decoder.image().expect("image was none").exif.clone();
+    assert_eq!(exif.len(), 1126);
+
+    // In paris_icc_exif_xmp.avif, the Exif item 2 and the XMP item 3 both have a 'cdsc' reference
+    // to the primary item 1. Replace the reference of item 2 by an unknown reference type.
+    let mut data =
+        std::fs::read(get_test_file("paris_icc_exif_xmp.avif")).expect("could not read file");
+    let cdsc = data.windows(4).position(|x| x == b"cdsc").unwrap();
+    assert_eq!(data[cdsc + 4..cdsc + 6], [0, 2]);
+    data[cdsc..cdsc + 4].copy_from_slice(b"cdsx");
+
+    // An Exif item without any 'cdsc' reference is not used.
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data.clone());
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.item_infos()[1].describes_item_id, 0);
+    assert!(decoder.image().expect("image was none").exif.is_empty());
+
+    // Turn item 3 into an Exif item and swap the extents of items 2 and 3 in iloc, so that the
+    // Exif payload is the one of item 3. Item 2 is not valid Exif data anymore.
+    let mime = data.windows(8).position(|x| x == b"mimeXMP\0").unwrap();
+    data[mime..mime + 4].copy_from_slice(b"Exif");
+    let iloc = data.windows(4).position(|x| x == b"iloc").unwrap();
+    let item2 = iloc + 32;
+    let item3 = item2 + 14;
+    assert_eq!(data[item2 - 6..item2 - 4], [0, 2]);
+    assert_eq!(data[item3 - 6..item3 - 4], [0, 3]);
+    let extent2 = data[item2..item2 + 8].to_vec();
+    data.copy_within(item3..item3 + 8, item2);
+    data[item3..item3 + 8].copy_from_slice(&extent2);
+
+    // The item with a 'cdsc' reference to the primary item is preferred over the lower id.
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.image().expect("image was none").exif, exif);
+}
+
 // From avifdecodetest.cc
 #[test]
 fn color_grid_alpha_no_grid() {
@@ -228,6 +849,35 @@ fn progressive(filename: &str, layer_count: u32, width: u32, height: u32) {
     }
 }
 
+#[test]
+fn progressive_nth_image() {
+    if !HAS_DECODER {
+        return;
+    }
+    let filename = "progressive/tiger_3layer_1res.avif";
+    let mut decoder = get_decoder(filename);
+    decoder.settings.allow_progressive = true;
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.image_count(), 3);
+    let mut layers = Vec::new();
+    for _ in 0..3 {
+        let mut layer = Image::default();
+        assert!(decoder.next_image_into(&mut layer).is_ok());
+        layers.push(layer);
+    }
+
+    let mut decoder = get_decoder(filename);
+    decoder.settings.allow_progressive = true;
+    assert!(decoder.parse().is_ok());
+    // Seek forward past the first layers, then backwards, then forward again.
+    for index in [2, 0, 1] {
+        assert!(decoder.nth_image(index).is_ok());
+        let image = decoder.image().expect("image was none");
+        let expected = &layers[index as usize];
+        assert_eq!(image.content_hash(), expected.content_hash());
+    }
+}
+
 // From avifmetadatatest.cc
 #[test]
 fn decoder_parse_icc_exif_xmp() {
@@ -444,6 +1094,31 @@ fn decode_ignore_gain_map_but_read_metadata() {
     assert_eq!(decoder.gainmap().metadata.alternate_hdr_headroom.1, 10);
 }
 
+#[test_case::test_case(ImageContentType::ColorAndAlpha)]
+#[test_case::test_case(ImageContentType::None)]
+fn gainmap_metadata_without_decoding(image_content_to_decode: ImageContentType) {
+    let mut decoder = get_decoder("seine_sdr_gainmap_srgb.avif");
+    decoder.settings.image_content_to_decode = image_content_to_decode;
+    let res = decoder.parse();
+    assert!(res.is_ok());
+    let metadata = decoder
+        .gainmap_metadata()
+        .expect("gainmap metadata was none");
+    assert_eq!(metadata.alternate_hdr_headroom.0, 13);
+    assert_eq!(metadata.alternate_hdr_headroom.1, 10);
+    // The gain map image was not parsed or decoded.
+    assert_eq!(decoder.gainmap().image.width, 0);
+    assert_eq!(decoder.gainmap().image.row_bytes[0], 0);
+}
+
+#[test]
+fn gainmap_metadata_absent() {
+    let mut decoder = get_decoder("unsupported_gainmap_version.avif");
+    let res = decoder.parse();
+    assert!(res.is_ok());
+    assert!(decoder.gainmap_metadata().is_none());
+}
+
 // From avifgainmaptest.cc
 #[test]
 fn decode_ignore_color_and_alpha() {
@@ -474,30 +1149,209 @@ fn decode_ignore_color_and_alpha() {
     assert!(decoder.gainmap().image.row_bytes[0] > 0);
 }
 
-// From avifgainmaptest.cc
-#[test_case::test_case("paris_icc_exif_xmp.avif")]
-#[test_case::test_case("sofa_grid1x5_420.avif")]
-#[test_case::test_case("color_grid_alpha_nogrid.avif")]
 #[test_case::test_case("seine_sdr_gainmap_srgb.avif")]
-fn decode_ignore_all(filename: &str) {
+#[test_case::test_case("color_grid_gainmap_different_grid.avif")]
+#[test_case::test_case("color_grid_alpha_grid_gainmap_nogrid.avif")]
+#[test_case::test_case("color_nogrid_alpha_nogrid_gainmap_grid.avif")]
+fn decode_gainmap_only(filename: &str) {
     let mut decoder = get_decoder(filename);
-    // Ignore both the main image and the gain map.
-    decoder.settings.image_content_to_decode = ImageContentType::None;
-    // But do read the gain map metadata
-
-    let res = decoder.parse();
-    assert!(res.is_ok());
-    assert_eq!(decoder.compression_format(), CompressionFormat::Avif);
+    decoder.settings.image_content_to_decode = ImageContentType::GainMap;
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.gainmap_present());
     let image = decoder.image().expect("image was none");
-    // Main image metadata is available.
-    assert!(image.width > 0);
-    // But trying to access the next image should give an error because both
-    // ignoreColorAndAlpha and enableDecodingGainMap are set.
-    let res = decoder.next_image();
-    assert!(res.is_err());
-}
-
-// From avifcllitest.cc
+    let (width, height, depth) = (image.width, image.height, image.depth);
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    // The main image keeps its metadata but has no pixels.
+    let image = decoder.image().expect("image was none");
+    assert_eq!(
+        (image.width, image.height, image.depth),
+        (width, height, depth)
+    );
+    for plane in ALL_PLANES {
+        assert!(!image.has_plane(plane));
+    }
+    // The gain map is fully decoded.
+    let gainmap_image = &decoder.gainmap().image;
+    assert!(gainmap_image.has_plane(Plane::Y));
+    assert!(gainmap_image.plane_data(Plane::Y).is_some());
+    assert_eq!(decoder.decoded_row_count(), height);
+}
+
+#[test_case::test_case(ImageContentType::ColorAndAlpha)]
+#[test_case::test_case(ImageContentType::All)]
+fn tmap_primary_item(image_content_to_decode: ImageContentType) {
+    // Make the tmap item 2 of seine_sdr_gainmap_srgb.avif the primary item instead of its base
+    // image item 1.
+    let mut data =
+        std::fs::read(get_test_file("seine_sdr_gainmap_srgb.avif")).expect("could not read file");
+    let pitm = data.windows(4).position(|x| x == b"pitm").unwrap();
+    assert_eq!(data[pitm + 8..pitm + 10], [0, 1]);
+    data[pitm + 9] = 2;
+    let decode_gainmap = matches!(image_content_to_decode, ImageContentType::All);
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.image_content_to_decode = image_content_to_decode;
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.is_tone_mapped());
+    assert!(decoder.gainmap_present());
+
+    let mut reference = get_decoder("seine_sdr_gainmap_srgb.avif");
+    reference.settings.image_content_to_decode = ImageContentType::ColorAndAlpha;
+    assert!(reference.parse().is_ok());
+    assert!(!reference.is_tone_mapped());
+    let image = decoder.image().expect("image was none");
+    let reference_image = reference.image().expect("image was none");
+    assert_eq!(
+        (image.width, image.height, image.depth),
+        (
+            reference_image.width,
+            reference_image.height,
+            reference_image.depth
+        )
+    );
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    assert!(reference.next_image().is_ok());
+    let image = decoder.image().expect("image was none");
+    let reference_image = reference.image().expect("image was none");
+    assert_eq!(image.content_hash(), reference_image.content_hash());
+    assert_eq!(decoder.gainmap().image.has_plane(Plane::Y), decode_gainmap);
+}
+
+#[test]
+fn alternatives() {
+    // The 'altr' group of seine_sdr_gainmap_srgb.avif lists the tmap item 2 before its base image
+    // item 1, which is the primary item.
+    let mut decoder = get_decoder("seine_sdr_gainmap_srgb.avif");
+    assert_eq!(decoder.select_alternative(1), Err(AvifError::NoContent));
+    assert!(decoder.parse().is_ok());
+    let alternatives = decoder.alternatives();
+    assert_eq!(
+        alternatives
+            .iter()
+            .map(|x| (x.item_id, x.item_type.as_str(), x.supported))
+            .collect::<Vec<_>>(),
+        [(2, "tmap", true), (1, "av01", true)]
+    );
+    assert!(!decoder.is_tone_mapped());
+
+    assert!(decoder.select_alternative(2).is_ok());
+    assert!(decoder.is_tone_mapped());
+    assert_eq!(decoder.alternatives(), alternatives);
+    if HAS_DECODER {
+        assert!(decoder.next_image().is_ok());
+    }
+    // The selection is kept when parsing again.
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.is_tone_mapped());
+
+    assert!(decoder.select_alternative(1).is_ok());
+    assert!(!decoder.is_tone_mapped());
+    // The gain map item 3 is not part of the group.
+    assert_eq!(
+        decoder.select_alternative(3),
+        Err(AvifError::InvalidArgument)
+    );
+    assert_eq!(
+        decoder.select_alternative(100),
+        Err(AvifError::InvalidArgument)
+    );
+
+    // No altr group.
+    let mut decoder = get_decoder("white_1x1.avif");
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.alternatives().is_empty());
+    assert_eq!(
+        decoder.select_alternative(1),
+        Err(AvifError::InvalidArgument)
+    );
+}
+
+#[test]
+fn alternatives_with_different_cicp() {
+    // Replace the tmap item 2 in the 'altr' group of seine_sdr_gainmap_srgb.avif by the gain map
+    // item 3, whose nclx colr property differs from the one of the primary item 1.
+    let mut data =
+        std::fs::read(get_test_file("seine_sdr_gainmap_srgb.avif")).expect("could not read file");
+    let altr = data.windows(4).position(|x| x == b"altr").unwrap();
+    // Skip the version, flags, group_id and num_entities_in_group fields.
+    let entity_ids = altr + 16;
+    assert_eq!(data[entity_ids..entity_ids + 8], [0, 0, 0, 2, 0, 0, 0, 1]);
+    data[entity_ids..entity_ids + 4].copy_from_slice(&3u32.to_be_bytes());
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    assert_eq!(
+        decoder
+            .alternatives()
+            .iter()
+            .map(|x| (x.item_id, x.item_type.as_str(), x.supported))
+            .collect::<Vec<_>>(),
+        [(3, "av01", true), (1, "av01", true)]
+    );
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.color_primaries, ColorPrimaries::Bt709);
+    assert_eq!(
+        image.transfer_characteristics,
+        TransferCharacteristics::Srgb
+    );
+    let primary_hash = if HAS_DECODER {
+        assert!(decoder.next_image().is_ok());
+        Some(decoder.image().expect("image was none").content_hash())
+    } else {
+        None
+    };
+
+    assert!(decoder.select_alternative(3).is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.color_primaries, ColorPrimaries::Unspecified);
+    assert_eq!(
+        image.transfer_characteristics,
+        TransferCharacteristics::Unspecified
+    );
+    if HAS_DECODER {
+        assert!(decoder.next_image().is_ok());
+        let image = decoder.image().expect("image was none");
+        assert_ne!(Some(image.content_hash()), primary_hash);
+    }
+
+    assert!(decoder.select_alternative(1).is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(
+        image.transfer_characteristics,
+        TransferCharacteristics::Srgb
+    );
+}
+
+// From avifgainmaptest.cc
+#[test_case::test_case("paris_icc_exif_xmp.avif")]
+#[test_case::test_case("sofa_grid1x5_420.avif")]
+#[test_case::test_case("color_grid_alpha_nogrid.avif")]
+#[test_case::test_case("seine_sdr_gainmap_srgb.avif")]
+fn decode_ignore_all(filename: &str) {
+    let mut decoder = get_decoder(filename);
+    // Ignore both the main image and the gain map.
+    decoder.settings.image_content_to_decode = ImageContentType::None;
+    // But do read the gain map metadata
+
+    let res = decoder.parse();
+    assert!(res.is_ok());
+    assert_eq!(decoder.compression_format(), CompressionFormat::Avif);
+    let image = decoder.image().expect("image was none");
+    // Main image metadata is available.
+    assert!(image.width > 0);
+    // But trying to access the next image should give an error because both
+    // ignoreColorAndAlpha and enableDecodingGainMap are set.
+    let res = decoder.next_image();
+    assert!(res.is_err());
+}
+
+// From avifcllitest.cc
 #[test_case::test_case("clli_0_0.avif", 0, 0; "clli_0_0")]
 #[test_case::test_case("clli_0_1.avif", 0, 1; "clli_0_1")]
 #[test_case::test_case("clli_0_65535.avif", 0, 65535; "clli_0_65535")]
@@ -525,6 +1379,60 @@ fn clli(filename: &str, max_cll: u16, max_pall: u16) {
     }
 }
 
+// The clli property is associated twice with the primary item.
+#[test_case::test_case(decoder::Strictness::All, false)]
+#[test_case::test_case(decoder::Strictness::None, true)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::UniquePropertiesRequired]), false)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::PixiRequired]), true)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::UniquePropertiesRequired]), true)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![]), false)]
+fn clli_duplicate(strictness: decoder::Strictness, expect_ok: bool) {
+    let mut decoder = get_decoder("clli/clli_duplicate.avif");
+    decoder.settings.strictness = strictness;
+    let res = decoder.parse();
+    if !expect_ok {
+        assert!(matches!(res, Err(AvifError::BmffParseFailed(_))));
+        return;
+    }
+    assert!(res.is_ok());
+    let image = decoder.image().expect("image was none");
+    let clli = image.clli.as_ref().unwrap();
+    assert_eq!(clli.max_cll, 1);
+    assert_eq!(clli.max_pall, 1);
+}
+
+// The extent of the primary item of white_1x1_extent_offset_0.avif points at offset 0, i.e. at
+// the ftyp box.
+#[test_case::test_case(decoder::Strictness::All, false)]
+#[test_case::test_case(decoder::Strictness::None, true)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::ItemExtentsValid]), false)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::ItemExtentsValid]), true)]
+fn item_extent_outside_of_mdat(strictness: decoder::Strictness, expect_ok: bool) {
+    let mut decoder = get_decoder("white_1x1_extent_offset_0.avif");
+    decoder.settings.strictness = strictness.clone();
+    let res = decoder.parse();
+    if expect_ok {
+        assert!(res.is_ok());
+        assert_eq!(decoder.diagnostics().len(), 1);
+        assert!(decoder.diagnostics()[0].contains("item id 1"));
+        assert!(decoder.diagnostics()[0].contains("ftyp"));
+    } else {
+        match res {
+            Err(AvifError::BmffParseFailed(message)) => {
+                assert!(message.contains("item id 1"), "{message}");
+                assert!(message.contains("ftyp"), "{message}");
+            }
+            _ => panic!("unexpected result: {res:?}"),
+        }
+    }
+
+    let mut decoder = get_decoder("white_1x1.avif");
+    decoder.settings.strictness = strictness;
+    let res = decoder.parse();
+    assert!(res.is_ok());
+    assert!(decoder.diagnostics().is_empty());
+}
+
 #[test]
 fn raw_io() {
     let data =
@@ -766,9 +1674,51 @@ fn nth_image() {
     assert!(decoder.nth_image(50).is_err());
 }
 
-#[test]
-fn color_and_alpha_dimensions_do_not_match() {
+#[test_case::test_case("colors-animated-8bpc.avif")]
+#[test_case::test_case("colors-animated-12bpc-keyframes-0-2-3.avif")]
+#[test_case::test_case("colors-animated-8bpc-alpha-exif-xmp.avif")]
+fn next_image_at(filename: &str) {
+    let mut decoder = get_decoder(filename);
+    assert!(decoder.parse().is_ok());
+    let image_count = decoder.image_count();
+    let timings: Vec<_> = (0..image_count)
+        .map(|n| decoder.nth_image_timing(n).unwrap())
+        .collect();
+    if !HAS_DECODER {
+        return;
+    }
+    let mut reference = get_decoder(filename);
+    assert!(reference.parse().is_ok());
+    let last = image_count - 1;
+    // (pts, expected index), in an order that seeks both forwards and backwards.
+    let mut queries = vec![(-1.0, 0), (1000.0, last)];
+    for n in [3, 0, 4, 1, 2] {
+        let n = n.min(last);
+        let timing = timings[n as usize];
+        queries.push((timing.pts, n));
+        queries.push((timing.pts + timing.duration / 2.0, n));
+    }
+    for (pts, expected_index) in queries {
+        assert!(decoder.next_image_at(pts).is_ok());
+        assert_eq!(decoder.image_index(), expected_index as i32);
+        assert_eq!(
+            decoder.image_timing().pts,
+            timings[expected_index as usize].pts
+        );
+        assert!(reference.nth_image(expected_index).is_ok());
+        let image = decoder.image().expect("image was none");
+        let reference_image = reference.image().expect("image was none");
+        assert_eq!(image.content_hash(), reference_image.content_hash());
+    }
+}
+
+#[test_case::test_case(decoder::Strictness::All, true)]
+#[test_case::test_case(decoder::Strictness::None, false)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::AlphaDimensionsValid]), true)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::AlphaDimensionsValid]), false)]
+fn color_and_alpha_dimensions_do_not_match(strictness: decoder::Strictness, strict: bool) {
     let mut decoder = get_decoder("invalid_color10x10_alpha5x5.avif");
+    decoder.settings.strictness = strictness;
     // Parsing should succeed.
     let res = decoder.parse();
     assert!(res.is_ok());
@@ -779,11 +1729,23 @@ fn color_and_alpha_dimensions_do_not_match() {
     if !HAS_DECODER {
         return;
     }
-    // Decoding should fail.
     let res = decoder.next_image();
-    assert!(res.is_err());
+    if strict || !cfg!(feature = "libyuv") {
+        // Decoding should fail, either because of the mismatch or because scaling is not
+        // available.
+        assert!(res.is_err());
+        return;
+    }
+    // The alpha plane is upscaled to the color dimensions.
+    assert!(res.is_ok());
+    assert_eq!(decoder.diagnostics().len(), 1);
+    let image = decoder.image().expect("image was none");
+    assert!(image.has_alpha());
+    assert_eq!(image.width(Plane::A), 10);
+    assert_eq!(image.height(Plane::A), 10);
 }
 
+#[cfg(feature = "conversion")]
 #[test]
 fn rgb_conversion_alpha_premultiply() -> AvifResult<()> {
     let mut decoder = get_decoder("alpha.avif");
@@ -803,6 +1765,7 @@ fn rgb_conversion_alpha_premultiply() -> AvifResult<()> {
     Ok(())
 }
 
+#[cfg(feature = "conversion")]
 #[test]
 fn white_1x1() -> AvifResult<()> {
     let mut decoder = get_decoder("white_1x1.avif");
@@ -828,6 +1791,50 @@ fn white_1x1() -> AvifResult<()> {
     Ok(())
 }
 
+#[cfg(feature = "conversion")]
+#[test_case::test_case("paris_10bpc.avif", 10, reformat::depth::Dither::Ordered)]
+#[test_case::test_case(
+    "colors-animated-12bpc-keyframes-0-2-3.avif",
+    12,
+    reformat::depth::Dither::FloydSteinberg
+)]
+fn force_8bit_output(filename: &str, depth: u8, dither: reformat::depth::Dither) {
+    let mut decoder = get_decoder(filename);
+    decoder.settings.force_8bit_output = true;
+    decoder.settings.force_8bit_output_dither = dither;
+    assert!(decoder.parse().is_ok());
+    // The parsed depth is the one of the file.
+    assert_eq!(decoder.image().expect("image was none").depth, depth);
+    if !HAS_DECODER {
+        return;
+    }
+    let mut reference_decoder = get_decoder(filename);
+    assert!(reference_decoder.parse().is_ok());
+    for _ in 0..decoder.image_count() {
+        assert!(decoder.next_image().is_ok());
+        assert!(reference_decoder.next_image().is_ok());
+        let image = decoder.image().expect("image was none");
+        assert_eq!(image.depth, 8);
+        let reference_image = reference_decoder.image().expect("image was none");
+        assert_eq!(reference_image.depth, depth);
+        let expected_image = reference_image.to_8bit_dithered(dither).unwrap();
+        for plane in ALL_PLANES {
+            assert_eq!(image.has_plane(plane), expected_image.has_plane(plane));
+            if !image.has_plane(plane) {
+                continue;
+            }
+            assert!(image.row16(plane, 0).is_err());
+            let width = image.width(plane);
+            for y in 0..image.height(plane) as u32 {
+                assert_eq!(
+                    image.row(plane, y).unwrap()[..width],
+                    expected_image.row(plane, y).unwrap()[..width]
+                );
+            }
+        }
+    }
+}
+
 #[test]
 fn white_1x1_mdat_size0() -> AvifResult<()> {
     // Edit the file to simulate an 'mdat' box with size 0 (meaning it ends at EOF).
@@ -931,6 +1938,119 @@ fn heic_peek() {
     );
 }
 
+// Returns the top level boxes of the file as (box_type, box_data) pairs.
+fn top_level_boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let size = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        boxes.push((&data[offset + 4..offset + 8], &data[offset..offset + size]));
+        offset += size;
+    }
+    boxes
+}
+
+#[test_case::test_case("white_1x1.avif", true)]
+#[test_case::test_case("paris_10bpc.avif", true)]
+// The mdat box starts with the grid payload instead of a sequence header.
+#[test_case::test_case("sofa_grid1x5_420.avif", false)]
+fn peek_sequence_header_info(filename: &str, mdat_starts_with_sequence_header: bool) {
+    let data = std::fs::read(get_test_file(filename)).expect("could not read file");
+    let mut decoder = get_decoder(filename);
+    assert!(decoder.parse().is_ok());
+    let image = decoder.image().expect("image was none");
+    let expected_info = decoder::SequenceHeaderInfo {
+        source: decoder::SequenceHeaderInfoSource::PrimaryItem,
+        width: Some(image.width),
+        height: Some(image.height),
+        depth: Some(image.depth),
+        yuv_format: Some(image.yuv_format),
+    };
+    let boxes = top_level_boxes(&data);
+    let box_types: Vec<_> = boxes.iter().map(|(box_type, _)| *box_type).collect();
+    assert_eq!(box_types, [b"ftyp", b"meta", b"mdat"]);
+    let ftyp = boxes[0].1;
+    let meta = boxes[1].1;
+    let mdat = boxes[2].1;
+
+    // The meta box is fully available, even if the mdat box is not.
+    let meta_first = [ftyp, meta, &mdat[..8]].concat();
+    assert_eq!(
+        decoder::Decoder::peek_sequence_header_info(&meta_first),
+        Ok(expected_info)
+    );
+    // The meta box is truncated.
+    assert_eq!(
+        decoder::Decoder::peek_sequence_header_info(&meta_first[..ftyp.len() + meta.len() - 1]),
+        Ok(decoder::SequenceHeaderInfo::default())
+    );
+
+    // Only the ftyp box is available.
+    assert_eq!(
+        decoder::Decoder::peek_sequence_header_info(ftyp),
+        Ok(decoder::SequenceHeaderInfo::default())
+    );
+    assert_eq!(
+        decoder::Decoder::peek_sequence_header_info(&ftyp[..ftyp.len() - 4]),
+        Ok(decoder::SequenceHeaderInfo::default())
+    );
+    assert_eq!(
+        decoder::Decoder::peek_sequence_header_info(&meta),
+        Err(AvifError::InvalidFtyp)
+    );
+
+    // The mdat box comes first and is truncated.
+    let mdat_first = [ftyp, &mdat[..std::cmp::min(mdat.len(), 64)]].concat();
+    let expected_info = if mdat_starts_with_sequence_header {
+        decoder::SequenceHeaderInfo {
+            source: decoder::SequenceHeaderInfoSource::SequenceHeader,
+            ..expected_info
+        }
+    } else {
+        decoder::SequenceHeaderInfo::default()
+    };
+    assert_eq!(
+        decoder::Decoder::peek_sequence_header_info(&mdat_first),
+        Ok(expected_info)
+    );
+}
+
+#[test_case::test_case("white_1x1.avif", 1, 1)]
+#[test_case::test_case("paris_icc_exif_xmp.avif", 403, 302)]
+#[test_case::test_case("sofa_grid1x5_420.avif", 1024, 770)]
+#[test_case::test_case("seine_sdr_gainmap_srgb.avif", 400, 300)]
+#[test_case::test_case("colors-animated-8bpc.avif", 150, 150)]
+fn peek_dimensions(filename: &str, width: u32, height: u32) {
+    let data = std::fs::read(get_test_file(filename)).expect("could not read file");
+    let mut decoder = get_decoder(filename);
+    assert!(decoder.parse().is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!((image.width, image.height), (width, height));
+
+    assert_eq!(
+        decoder::Decoder::peek_dimensions(&data),
+        Some((width, height))
+    );
+    // The dimensions are known as soon as the meta box is complete.
+    let boxes = top_level_boxes(&data);
+    let meta_index = boxes
+        .iter()
+        .position(|(box_type, _)| *box_type == b"meta")
+        .unwrap();
+    let meta_end = boxes[..=meta_index]
+        .iter()
+        .map(|(_, box_data)| box_data.len())
+        .sum();
+    assert_eq!(
+        decoder::Decoder::peek_dimensions(&data[..meta_end]),
+        Some((width, height))
+    );
+    assert_eq!(
+        decoder::Decoder::peek_dimensions(&data[..meta_end - 1]),
+        None
+    );
+}
+
 #[test]
 fn heic_parsing() {
     let mut decoder = get_decoder("blue.heic");
@@ -940,6 +2060,9 @@ fn heic_parsing() {
         let image = decoder.image().expect("image was none");
         assert_eq!(image.width, 320);
         assert_eq!(image.height, 240);
+        // From the SPS of the HEVC bitstream, as reported by the hvcC property.
+        assert_eq!(image.depth, 8);
+        assert_eq!(image.yuv_format, PixelFormat::Yuv420);
         assert_eq!(decoder.compression_format(), CompressionFormat::Heic);
         if cfg!(feature = "android_mediacodec") {
             // Decoding is available only via android_mediacodec.
@@ -953,52 +2076,434 @@ fn heic_parsing() {
     }
 }
 
-#[test]
-fn clap_irot_imir_non_essential() {
-    let mut decoder = get_decoder("clap_irot_imir_non_essential.avif");
+#[test_case::test_case(decoder::Strictness::All, false)]
+#[test_case::test_case(decoder::Strictness::None, true)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::CodecConfigurationValid]), false)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::CodecConfigurationValid]), true)]
+fn av1c_depth_mismatch(strictness: decoder::Strictness, expect_ok: bool) {
+    // Make the av1C and pixi boxes of white_1x1.avif claim 10 bits while the bitstream is 8-bit.
+    let mut data = std::fs::read(get_test_file("white_1x1.avif")).expect("could not read file");
+    let find_box = |data: &[u8], box_type: &[u8]| {
+        data.windows(4)
+            .position(|x| x == box_type)
+            .expect("box not found")
+    };
+    let av1c = find_box(&data, b"av1C");
+    // high_bitdepth.
+    data[av1c + 6] |= 0x40;
+    let pixi = find_box(&data, b"pixi");
+    let num_channels = data[pixi + 8] as usize;
+    data[pixi + 9..pixi + 9 + num_channels].fill(10);
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = strictness;
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.depth, 10);
+    if !HAS_DECODER {
+        return;
+    }
+    let res = decoder.next_image();
+    if !expect_ok {
+        assert!(matches!(res, Err(AvifError::BmffParseFailed(_))));
+        return;
+    }
+    assert!(res.is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.depth, 8);
+    assert_eq!(decoder.diagnostics().len(), 1);
+}
+
+#[test_case::test_case(b"prof", b"RGB ", decoder::Strictness::All, true)]
+#[test_case::test_case(b"rICC", b"RGB ", decoder::Strictness::All, true)]
+#[test_case::test_case(b"prof", b"GRAY", decoder::Strictness::All, false)]
+#[test_case::test_case(b"rICC", b"GRAY", decoder::Strictness::None, true)]
+#[test_case::test_case(b"prof", b"GRAY", decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::IccColorSpaceValid]), false)]
+#[test_case::test_case(b"prof", b"GRAY", decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::IccColorSpaceValid]), true)]
+fn icc_color_space(
+    color_type: &[u8; 4],
+    color_space: &[u8; 4],
+    strictness: decoder::Strictness,
+    expect_ok: bool,
+) {
+    // paris_icc_exif_xmp.avif is a 4:4:4 image with a "prof" RGB ICC profile.
+    let mut data =
+        std::fs::read(get_test_file("paris_icc_exif_xmp.avif")).expect("could not read file");
+    let colr = data.windows(4).position(|x| x == b"colr").unwrap();
+    assert_eq!(&data[colr + 4..colr + 8], b"prof");
+    assert_eq!(&data[colr + 24..colr + 28], b"RGB ");
+    data[colr + 4..colr + 8].copy_from_slice(color_type);
+    data[colr + 24..colr + 28].copy_from_slice(color_space);
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = strictness;
+    decoder.set_io_vec(data);
     let res = decoder.parse();
-    assert!(res.is_err());
+    if !expect_ok {
+        assert!(matches!(res, Err(AvifError::BmffParseFailed(_))));
+        return;
+    }
+    assert!(res.is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(&image.icc[16..20], color_space);
+    // A mismatch that did not fail the parse is reported.
+    let mismatch = color_space == b"GRAY";
+    assert_eq!(decoder.diagnostics().len(), usize::from(mismatch));
 }
 
-#[derive(Clone)]
-struct ExpectedOverlayImageInfo<'a> {
-    filename: &'a str,
-    width: u32,
-    height: u32,
-    expected_pixels: &'a [(usize, u32, [u8; 4])], // (x, y, [rgba]).
+#[test_case::test_case(1, decoder::Strictness::All, true)]
+#[test_case::test_case(0, decoder::Strictness::None, true)]
+#[test_case::test_case(0, decoder::Strictness::All, true)]
+#[test_case::test_case(0, decoder::Strictness::SpecificExclude(vec![]), true)]
+#[test_case::test_case(0, decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::Av1LevelValid]), false)]
+#[test_case::test_case(1, decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::Av1LevelValid]), true)]
+#[test_case::test_case(31, decoder::Strictness::All, true)]
+fn av1c_level(seq_level_idx: u8, strictness: decoder::Strictness, expect_ok: bool) {
+    // The 1024x154 cells of the 1024x770 grid of sofa_grid1x5_420.avif share an av1C property with
+    // the level 2.1 (seq_level_idx 1). Level 2.0 is too low for 1024x154 cells and level 31 has no
+    // limits.
+    let mut data =
+        std::fs::read(get_test_file("sofa_grid1x5_420.avif")).expect("could not read file");
+    let av1c = data.windows(4).position(|x| x == b"av1C").unwrap();
+    // seq_profile and seq_level_idx_0.
+    assert_eq!(data[av1c + 5], 1);
+    data[av1c + 5] = seq_level_idx;
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = strictness;
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if !expect_ok {
+        assert!(matches!(res, Err(AvifError::BmffParseFailed(_))));
+        return;
+    }
+    assert!(res.is_ok());
+    // A level that is too low and did not fail the parse is reported.
+    assert_eq!(decoder.diagnostics().len(), usize::from(seq_level_idx == 0));
 }
 
-const RED: [u8; 4] = [255, 0, 0, 255];
-const GREEN: [u8; 4] = [0, 255, 0, 255];
-const BLUE: [u8; 4] = [0, 0, 255, 255];
-const BLACK: [u8; 4] = [0, 0, 0, 255];
-const YELLOW: [u8; 4] = [255, 255, 0, 255];
+#[test_case::test_case(0xfd, 8, PixelFormat::Yuv420 ; "420 8-bit")]
+#[test_case::test_case(0xfd, 10, PixelFormat::Yuv420 ; "420 10-bit")]
+#[test_case::test_case(0xfe, 10, PixelFormat::Yuv422 ; "422 10-bit")]
+#[test_case::test_case(0xff, 12, PixelFormat::Yuv444 ; "444 12-bit")]
+fn heic_depth_and_format_after_parse(
+    chroma_format_byte: u8,
+    depth: u8,
+    expected_yuv_format: PixelFormat,
+) {
+    // Rewrite chroma_format_idc, bit_depth_luma_minus8 and bit_depth_chroma_minus8 in the hvcC
+    // box of blue.heic, and the pixi box accordingly.
+    let mut data = std::fs::read(get_test_file("blue.heic")).expect("could not read file");
+    let hvcc = data.windows(4).position(|x| x == b"hvcC").unwrap();
+    assert_eq!(data[hvcc + 20..hvcc + 23], [0xfd, 0xf8, 0xf8]);
+    data[hvcc + 20] = chroma_format_byte;
+    data[hvcc + 21] = 0xf8 | (depth - 8);
+    data[hvcc + 22] = 0xf8 | (depth - 8);
+    if let Some(pixi) = data.windows(4).position(|x| x == b"pixi") {
+        let num_channels = data[pixi + 8] as usize;
+        data[pixi + 9..pixi + 9 + num_channels].fill(depth);
+    }
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if !cfg!(feature = "heic") {
+        assert!(res.is_err());
+        return;
+    }
+    assert!(res.is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.depth, depth);
+    assert_eq!(image.yuv_format, expected_yuv_format);
+}
 
-const EXPECTED_OVERLAY_IMAGE_INFOS: [ExpectedOverlayImageInfo; 4] = [
-    ExpectedOverlayImageInfo {
-        // Three 80x60 sub-images with the following offsets:
-        // horizontal_offsets: [0, 40, 80]
-        // vertical_offsets: [0, 40, 80]
-        filename: "overlay_exact_bounds.avif",
-        width: 160,
-        height: 140,
-        expected_pixels: &[
-            // Top left should be red.
-            (0, 0, RED),
-            (10, 10, RED),
-            (20, 20, RED),
-            // Green should be overlaid on top of the red block starting at (40, 40).
-            (40, 40, GREEN),
-            (50, 50, GREEN),
-            (60, 60, GREEN),
-            // Blue should be overlaid on top of the green block starting at (80, 80).
-            (80, 80, BLUE),
-            (90, 90, BLUE),
-            (100, 100, BLUE),
-            // Top right should be background color.
-            (159, 0, BLACK),
-            // Bottom left should be background color.
-            (0, 139, BLACK),
+#[test_case::test_case(b"mif1", b"miaf" ; "mif1")]
+#[test_case::test_case(b"mif2", b"mif2" ; "mif2")]
+fn miaf_brands(major_brand: &[u8; 4], compatible_brand: &[u8; 4]) {
+    // white_1x1.avif has the brands avif, avif, mif1, miaf, MA1A. Replace the avif brands so
+    // that only generic MIAF brands identify the file.
+    let mut data = std::fs::read(get_test_file("white_1x1.avif")).expect("could not read file");
+    assert_eq!(&data[8..12], b"avif");
+    assert_eq!(&data[16..20], b"avif");
+    data[8..12].copy_from_slice(major_brand);
+    data[16..20].copy_from_slice(compatible_brand);
+    assert!(decoder::Decoder::peek_compatible_file_type(&data));
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.compression_format(), CompressionFormat::Avif);
+    assert_eq!(decoder.image_count(), 1);
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.width, 1);
+    assert_eq!(image.height, 1);
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+}
+
+#[test]
+fn heic_identity_derived_item() {
+    // Same as blue.heic but the primary item is an iden item with an irot property whose
+    // dimg input is the hvc1 item.
+    let mut decoder = get_decoder("blue_iden_irot.heic");
+    let res = decoder.parse();
+    if cfg!(feature = "heic") {
+        assert!(res.is_ok());
+        let image = decoder.image().expect("image was none");
+        assert_eq!(image.width, 320);
+        assert_eq!(image.height, 240);
+        assert_eq!(image.depth, 8);
+        assert_eq!(image.irot_angle, Some(1));
+        assert_eq!(image.imir_axis, None);
+        assert_eq!(decoder.compression_format(), CompressionFormat::Heic);
+    } else {
+        assert!(res.is_err());
+    }
+}
+
+#[test]
+fn heic_identity_derived_item_icc() {
+    // Same as blue_iden_irot.heic but the iden item has an ICC colr property instead of an
+    // ispe property. The nclx colr property of the hvc1 item must be kept.
+    let mut data =
+        std::fs::read(get_test_file("blue_iden_irot.heic")).expect("could not read file");
+    let profile = [1u8; 128];
+    let mut colr = (12 + profile.len() as u32).to_be_bytes().to_vec();
+    colr.extend_from_slice(b"colrprof");
+    colr.extend_from_slice(&profile);
+    let find = |data: &[u8], box_type: &[u8]| data.windows(4).position(|x| x == box_type).unwrap();
+    // The ipco box is followed by ipma, so append the property and grow its parents.
+    let ipma = find(&data, b"ipma") - 4;
+    data.splice(ipma..ipma, colr.iter().copied());
+    for box_type in [b"meta", b"iprp", b"ipco"] {
+        let size = find(&data, box_type) - 4;
+        let grown =
+            u32::from_be_bytes(data[size..size + 4].try_into().unwrap()) + colr.len() as u32;
+        data[size..size + 4].copy_from_slice(&grown.to_be_bytes());
+    }
+    // The single iloc entry has a 4-byte base_offset into mdat, which moved.
+    let base_offset = find(&data, b"iloc") + 16;
+    assert_eq!(data[base_offset..base_offset + 4], 0x1b4u32.to_be_bytes());
+    data[base_offset..base_offset + 4].copy_from_slice(&(0x1b4 + colr.len() as u32).to_be_bytes());
+    // Associate the new property 6 with the iden item 2 instead of ispe.
+    let associations = find(&data, b"ipma") + 19;
+    assert_eq!(data[associations..associations + 5], [0, 2, 2, 0x02, 0x85]);
+    data[associations + 3] = 0x06;
+
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if cfg!(feature = "heic") {
+        assert!(res.is_ok());
+        let image = decoder.image().expect("image was none");
+        assert_eq!(image.icc, profile);
+        assert_eq!(image.color_primaries, ColorPrimaries::Bt709);
+        assert_eq!(
+            image.transfer_characteristics,
+            TransferCharacteristics::Srgb
+        );
+        assert_eq!(image.irot_angle, Some(1));
+    } else {
+        assert!(res.is_err());
+    }
+}
+
+#[test_case::test_case(1, true; "unused")]
+#[test_case::test_case(2, false; "primary")]
+fn heic_broken_identity_derived_item(primary_item_id: u8, expect_ok: bool) {
+    // Same as blue_iden_irot.heic but the dimg reference of the iden item 2 to the hvc1 item 1 has
+    // an unknown reference type, so that the iden item has no input.
+    let mut data =
+        std::fs::read(get_test_file("blue_iden_irot.heic")).expect("could not read file");
+    let dimg = data.windows(4).position(|x| x == b"dimg").unwrap();
+    assert_eq!(data[dimg + 4..dimg + 10], [0, 2, 0, 1, 0, 1]);
+    data[dimg..dimg + 4].copy_from_slice(b"dimx");
+    let pitm = data.windows(4).position(|x| x == b"pitm").unwrap();
+    assert_eq!(data[pitm + 8..pitm + 10], [0, 2]);
+    data[pitm + 9] = primary_item_id;
+
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if !cfg!(feature = "heic") {
+        assert!(res.is_err());
+        return;
+    }
+    // The broken iden item is skipped, which only matters if it is the primary item.
+    assert_eq!(res.is_ok(), expect_ok);
+    if expect_ok {
+        let image = decoder.image().expect("image was none");
+        assert_eq!(image.width, 320);
+        assert_eq!(image.height, 240);
+        assert_eq!(image.irot_angle, None);
+    }
+}
+
+#[test]
+fn heic_hvcc_out_of_order_arrays() {
+    // Rewrite the hvcC arrays of blue.heic (VPS, SPS, PPS) in the order PPS, SPS, VPS. The
+    // size of the box does not change.
+    let mut data = std::fs::read(get_test_file("blue.heic")).expect("could not read file");
+    let hvcc = data
+        .windows(4)
+        .position(|x| x == b"hvcC")
+        .expect("box not found");
+    // Skip the box type, configurationVersion and the next 21 bytes.
+    let num_of_arrays_offset = hvcc + 4 + 1 + 21;
+    assert_eq!(data[num_of_arrays_offset], 3);
+    let mut arrays: Vec<Vec<u8>> = Vec::new();
+    let mut offset = num_of_arrays_offset + 1;
+    for _ in 0..3 {
+        // NAL_unit_type, numNalus = 1, nalUnitLength and the NAL unit.
+        assert_eq!(&data[offset + 1..offset + 3], &[0, 1]);
+        let nal_unit_length = u16::from_be_bytes([data[offset + 3], data[offset + 4]]) as usize;
+        let array_end = offset + 5 + nal_unit_length;
+        arrays.push(data[offset..array_end].to_vec());
+        offset = array_end;
+    }
+    let reordered: Vec<u8> = arrays.into_iter().rev().flatten().collect();
+    data[num_of_arrays_offset + 1..offset].copy_from_slice(&reordered);
+
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if !cfg!(feature = "heic") {
+        assert!(res.is_err());
+        return;
+    }
+    assert!(res.is_ok());
+    assert!(decoder.diagnostics().is_empty());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.width, 320);
+    assert_eq!(image.height, 240);
+    if cfg!(feature = "android_mediacodec") {
+        // Decoding is available only via android_mediacodec.
+        assert!(decoder.next_image().is_ok());
+    }
+}
+
+#[test]
+fn clap_irot_imir_non_essential() {
+    let mut decoder = get_decoder("clap_irot_imir_non_essential.avif");
+    let res = decoder.parse();
+    assert!(res.is_err());
+}
+
+#[test]
+fn track_clap() {
+    // Append a 110x70 centered clap box to the av01 sample entry of the 150x150 sequence
+    // colors-animated-8bpc.avif. The mdat box follows the moov box so the iloc and stco offsets
+    // are shifted by the size of the clap box.
+    let mut data =
+        std::fs::read(get_test_file("colors-animated-8bpc.avif")).expect("could not read file");
+    let clap: Vec<u8> = [
+        40u32,
+        u32::from_be_bytes(*b"clap"),
+        110,
+        1,
+        70,
+        1,
+        0,
+        1,
+        0,
+        1,
+    ]
+    .iter()
+    .flat_map(|x| x.to_be_bytes())
+    .collect();
+    let read_u32 = |data: &[u8], offset: usize| {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    };
+    let add_u32 = |data: &mut [u8], offset: usize, value: u32| {
+        let sum = read_u32(data, offset) + value;
+        data[offset..offset + 4].copy_from_slice(&sum.to_be_bytes());
+    };
+    let find =
+        |data: &[u8], box_type: &[u8; 4]| data.windows(4).position(|x| x == box_type).unwrap();
+    let iloc = find(&data, b"iloc");
+    assert_eq!(data[iloc + 4..iloc + 8], [0, 0, 0, 0]); // Version 0.
+    assert_eq!(data[iloc + 8], 0x44); // offset_size and length_size are 4, base_offset_size is 0.
+    add_u32(&mut data, iloc + 18, clap.len() as u32);
+    let stco = find(&data, b"stco");
+    for i in 0..read_u32(&data, stco + 8) as usize {
+        add_u32(&mut data, stco + 12 + i * 4, clap.len() as u32);
+    }
+    let sample_entry = find(&data, b"stsd") + 12;
+    assert_eq!(data[sample_entry + 4..sample_entry + 8], *b"av01");
+    let sample_entry_end = sample_entry + read_u32(&data, sample_entry) as usize;
+    for box_type in [b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stsd"] {
+        let offset = find(&data, box_type) - 4;
+        add_u32(&mut data, offset, clap.len() as u32);
+    }
+    add_u32(&mut data, sample_entry, clap.len() as u32);
+    data.splice(sample_entry_end..sample_entry_end, clap);
+
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.source = decoder::Source::Tracks;
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.image_count(), 5);
+    let image = decoder.image().expect("image was none");
+    assert_eq!((image.width, image.height), (150, 150));
+    assert!(image.clap.is_some());
+    assert_eq!(
+        image.display_transform().crop_rect,
+        utils::clap::CropRect {
+            x: 20,
+            y: 40,
+            width: 110,
+            height: 70
+        }
+    );
+    if !HAS_DECODER {
+        return;
+    }
+    for _ in 0..decoder.image_count() {
+        assert!(decoder.next_image().is_ok());
+        let image = decoder.image().expect("image was none");
+        assert_eq!((image.width, image.height), (150, 150));
+        assert!(image.clap.is_some());
+    }
+}
+
+#[derive(Clone)]
+struct ExpectedOverlayImageInfo<'a> {
+    filename: &'a str,
+    width: u32,
+    height: u32,
+    #[cfg_attr(not(feature = "conversion"), allow(dead_code))]
+    expected_pixels: &'a [(usize, u32, [u8; 4])], // (x, y, [rgba]).
+}
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+const BLACK: [u8; 4] = [0, 0, 0, 255];
+const YELLOW: [u8; 4] = [255, 255, 0, 255];
+
+const EXPECTED_OVERLAY_IMAGE_INFOS: [ExpectedOverlayImageInfo; 4] = [
+    ExpectedOverlayImageInfo {
+        // Three 80x60 sub-images with the following offsets:
+        // horizontal_offsets: [0, 40, 80]
+        // vertical_offsets: [0, 40, 80]
+        filename: "overlay_exact_bounds.avif",
+        width: 160,
+        height: 140,
+        expected_pixels: &[
+            // Top left should be red.
+            (0, 0, RED),
+            (10, 10, RED),
+            (20, 20, RED),
+            // Green should be overlaid on top of the red block starting at (40, 40).
+            (40, 40, GREEN),
+            (50, 50, GREEN),
+            (60, 60, GREEN),
+            // Blue should be overlaid on top of the green block starting at (80, 80).
+            (80, 80, BLUE),
+            (90, 90, BLUE),
+            (100, 100, BLUE),
+            // Top right should be background color.
+            (159, 0, BLACK),
+            // Bottom left should be background color.
+            (0, 139, BLACK),
         ],
     },
     ExpectedOverlayImageInfo {
@@ -1086,6 +2591,7 @@ const EXPECTED_OVERLAY_IMAGE_INFOS: [ExpectedOverlayImageInfo; 4] = [
     },
 ];
 
+#[cfg(feature = "conversion")]
 macro_rules! pixel_eq {
     ($a:expr, $b:expr) => {
         assert!((i32::from($a) - i32::from($b)).abs() <= 3);
@@ -1111,21 +2617,653 @@ fn overlay(index: usize) {
     let image = decoder.image().expect("image was none");
     assert_eq!(image.width, info.width);
     assert_eq!(image.height, info.height);
-    let mut rgb = rgb::Image::create_from_yuv(image);
-    rgb.format = rgb::Format::Rgba;
-    assert!(rgb.allocate().is_ok());
-    assert!(rgb.convert_from_yuv(image).is_ok());
-    for expected_pixel in info.expected_pixels {
-        let column = expected_pixel.0;
-        let row = expected_pixel.1;
-        let pixels = rgb.row(row).expect("row was none");
-        let r = pixels[column * 4];
-        let g = pixels[(column * 4) + 1];
-        let b = pixels[(column * 4) + 2];
-        let a = pixels[(column * 4) + 3];
-        pixel_eq!(r, expected_pixel.2[0]);
-        pixel_eq!(g, expected_pixel.2[1]);
-        pixel_eq!(b, expected_pixel.2[2]);
-        pixel_eq!(a, expected_pixel.2[3]);
+    #[cfg(feature = "conversion")]
+    {
+        let mut rgb = rgb::Image::create_from_yuv(image);
+        rgb.format = rgb::Format::Rgba;
+        assert!(rgb.allocate().is_ok());
+        assert!(rgb.convert_from_yuv(image).is_ok());
+        for expected_pixel in info.expected_pixels {
+            let column = expected_pixel.0;
+            let row = expected_pixel.1;
+            let pixels = rgb.row(row).expect("row was none");
+            let r = pixels[column * 4];
+            let g = pixels[(column * 4) + 1];
+            let b = pixels[(column * 4) + 2];
+            let a = pixels[(column * 4) + 3];
+            pixel_eq!(r, expected_pixel.2[0]);
+            pixel_eq!(g, expected_pixel.2[1]);
+            pixel_eq!(b, expected_pixel.2[2]);
+            pixel_eq!(a, expected_pixel.2[3]);
+        }
+    }
+}
+
+#[test]
+fn incremental_overlay() {
+    // overlay_with_border.avif has three 80x60 cells with the vertical offsets [20, 60, 100].
+    // The rows above the topmost cell that is not decoded yet are final.
+    let data =
+        std::fs::read(get_test_file("overlay_with_border.avif")).expect("Unable to read file");
+    let len = data.len();
+    let available_size_rc = Rc::new(RefCell::new(0usize));
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.allow_incremental = true;
+    decoder.settings.strictness = decoder::Strictness::None;
+    decoder.set_io(Box::new(CustomIO {
+        available_size_rc: available_size_rc.clone(),
+        data,
+    }));
+    let step = std::cmp::max(1, len / 1000);
+
+    let mut parse_result = decoder.parse();
+    while matches!(parse_result, Err(AvifError::WaitingOnIo)) {
+        {
+            let mut available_size = available_size_rc.borrow_mut();
+            assert!(*available_size < len);
+            *available_size = std::cmp::min(*available_size + step, len);
+        }
+        parse_result = decoder.parse();
+    }
+    assert!(parse_result.is_ok());
+    if !HAS_DECODER {
+        return;
+    }
+
+    let mut previous_decoded_row_count = 0;
+    let mut decode_result = decoder.next_image();
+    while matches!(decode_result, Err(AvifError::WaitingOnIo)) {
+        {
+            let mut available_size = available_size_rc.borrow_mut();
+            assert!(*available_size < len);
+            let decoded_row_count = decoder.decoded_row_count();
+            assert!(decoded_row_count >= previous_decoded_row_count);
+            assert!([0, 60, 100].contains(&decoded_row_count));
+            previous_decoded_row_count = decoded_row_count;
+            *available_size = std::cmp::min(*available_size + step, len);
+        }
+        decode_result = decoder.next_image();
+    }
+    assert!(decode_result.is_ok());
+    assert_eq!(decoder.decoded_row_count(), 180);
+
+    // The incrementally decoded image must match the one decoded at once.
+    let mut reference = get_decoder("overlay_with_border.avif");
+    reference.settings.strictness = decoder::Strictness::None;
+    assert!(reference.parse().is_ok());
+    assert!(reference.next_image().is_ok());
+    let image = decoder.image().expect("image was none");
+    let reference_image = reference.image().expect("image was none");
+    assert_eq!(image.content_hash(), reference_image.content_hash());
+}
+
+#[test]
+fn next_image_incremental() {
+    let data = std::fs::read(get_test_file("sofa_grid1x5_420.avif")).expect("Unable to read file");
+    let len = data.len();
+    let available_size_rc = Rc::new(RefCell::new(0usize));
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.allow_incremental = true;
+    decoder.set_io(Box::new(CustomIO {
+        available_size_rc: available_size_rc.clone(),
+        data,
+    }));
+    let step = std::cmp::max(1, len / 100);
+    let grow = || {
+        let mut available_size = available_size_rc.borrow_mut();
+        assert!(*available_size < len);
+        *available_size = std::cmp::min(*available_size + step, len);
+    };
+
+    let mut parse_result = decoder.parse();
+    while matches!(parse_result, Err(AvifError::WaitingOnIo)) {
+        grow();
+        parse_result = decoder.parse();
+    }
+    assert!(parse_result.is_ok());
+    if !HAS_DECODER {
+        return;
+    }
+
+    let mut row_counts = Vec::new();
+    let mut decode_result = decoder.next_image_incremental(|rows| row_counts.push(rows));
+    while matches!(decode_result, Err(AvifError::WaitingOnIo)) {
+        grow();
+        decode_result = decoder.next_image_incremental(|rows| row_counts.push(rows));
+    }
+    assert!(decode_result.is_ok());
+    // The five cells of the grid become available one after the other.
+    assert!(row_counts.len() > 1);
+    assert!(row_counts.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(row_counts.last(), Some(&decoder.image().unwrap().height));
+}
+
+#[test]
+fn unknown_properties() {
+    let mut decoder = get_decoder("white_1x1.avif");
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.unknown_properties().is_empty());
+
+    // Rename the non-essential colr property of white_1x1.avif to an unknown box type.
+    let mut data = std::fs::read(get_test_file("white_1x1.avif")).expect("could not read file");
+    let colr = data
+        .windows(4)
+        .position(|x| x == b"colr")
+        .expect("box not found");
+    data[colr..colr + 4].copy_from_slice(b"abcd");
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    assert_eq!(decoder.unknown_properties(), vec![(1, "abcd".to_string())]);
+}
+
+// Replaces the given ipma associations of the file.
+fn patch_ipma(filename: &str, associations: &[u8], patched_associations: &[u8]) -> Vec<u8> {
+    let mut data = std::fs::read(get_test_file(filename)).expect("could not read file");
+    let ipma = data
+        .windows(4)
+        .position(|x| x == b"ipma")
+        .expect("box not found");
+    let offset = ipma
+        + data[ipma..]
+            .windows(associations.len())
+            .position(|x| x == associations)
+            .expect("associations not found");
+    data[offset..offset + associations.len()].copy_from_slice(patched_associations);
+    data
+}
+
+#[test_case::test_case(decoder::Strictness::All, false)]
+#[test_case::test_case(decoder::Strictness::None, true)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::PropertyIndicesValid]), false)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::PropertyIndicesValid]), true)]
+fn out_of_range_non_essential_property_index(strictness: decoder::Strictness, expect_ok: bool) {
+    // The associations of the primary item of white_1x1.avif are ispe, pixi, av1C (essential)
+    // and colr. There are only 4 properties, so point colr to the non-existing property 5.
+    let data = patch_ipma(
+        "white_1x1.avif",
+        &[0x01, 0x02, 0x83, 0x04],
+        &[0x01, 0x02, 0x83, 0x05],
+    );
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = strictness;
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if !expect_ok {
+        assert!(matches!(res, Err(AvifError::BmffParseFailed(_))));
+        return;
+    }
+    assert!(res.is_ok());
+    assert_eq!(
+        decoder.diagnostics(),
+        ["item id 1: property_index 5 in ipma exceeds the property count, ignored the property"]
+    );
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+}
+
+#[test_case::test_case(decoder::Strictness::All, false)]
+#[test_case::test_case(decoder::Strictness::None, true)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::PropertyIndicesValid]), false)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::PropertyIndicesValid]), true)]
+fn out_of_range_essential_property_index(strictness: decoder::Strictness, expect_ok: bool) {
+    // The associations of the alpha item 2 of alpha.avif are ispe, pixi, av1C (essential) and
+    // auxC. There are only 7 properties, so point av1C to the non-existing property 8.
+    let data = patch_ipma(
+        "alpha.avif",
+        &[0x00, 0x02, 0x04, 0x01, 0x05, 0x86, 0x07],
+        &[0x00, 0x02, 0x04, 0x01, 0x05, 0x88, 0x07],
+    );
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = strictness;
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if !expect_ok {
+        assert!(matches!(res, Err(AvifError::BmffParseFailed(_))));
+        return;
+    }
+    // The alpha item is ignored.
+    assert!(res.is_ok());
+    assert!(!decoder.image().expect("image was none").alpha_present);
+    assert!(decoder.diagnostics().contains(
+        &"item id 2: property_index 8 in ipma exceeds the property count, ignored the item"
+            .to_string()
+    ));
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    assert!(!decoder.image().expect("image was none").has_alpha());
+}
+
+// Items are linked by their references only, so the order of the items in iinf and their ids do
+// not matter.
+#[test_case::test_case(false ; "alpha infe first")]
+#[test_case::test_case(true ; "alpha infe first with lower item id")]
+fn alpha_item_before_color_item(swap_item_ids: bool) {
+    let mut data = std::fs::read(get_test_file("alpha.avif")).expect("could not read file");
+    let find = |data: &[u8], box_type: &[u8; 4]| {
+        data.windows(4)
+            .position(|x| x == box_type)
+            .expect("box not found")
+    };
+    // iinf contains the 26 byte infe boxes of the color item 1 then of the alpha item 2.
+    let infe = find(&data, b"infe") - 4;
+    let (color_infe, alpha_infe) = data[infe..infe + 52].split_at_mut(26);
+    color_infe.swap_with_slice(alpha_infe);
+    if swap_item_ids {
+        // The item ids are 16-bit in all the boxes of alpha.avif.
+        let mut item_id_offsets = vec![
+            find(&data, b"pitm") + 8,
+            infe + 12,
+            infe + 26 + 12,
+            // The auxl reference from item 2 to item 1.
+            find(&data, b"auxl") + 4,
+            find(&data, b"auxl") + 8,
+        ];
+        // The two iloc entries are 14 bytes long.
+        let iloc = find(&data, b"iloc");
+        item_id_offsets.extend([iloc + 12, iloc + 26]);
+        // The ipma entry of item 1 is 7 bytes long.
+        let ipma = find(&data, b"ipma");
+        item_id_offsets.extend([ipma + 12, ipma + 19]);
+        for offset in item_id_offsets {
+            let item_id = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            assert!(item_id == 1 || item_id == 2);
+            data[offset..offset + 2].copy_from_slice(&(3 - item_id).to_be_bytes());
+        }
+        // ipma entries must be ordered by increasing item id.
+        let (entry1, entry2) = data[ipma + 12..ipma + 26].split_at_mut(7);
+        entry1.swap_with_slice(entry2);
+    }
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    let image = decoder.image().expect("image was none");
+    assert!(image.alpha_present);
+    assert_eq!(image.width, 80);
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    let mut reference = get_decoder("alpha.avif");
+    assert!(reference.parse().is_ok());
+    assert!(reference.next_image().is_ok());
+    let image = decoder.image().expect("image was none");
+    let reference = reference.image().expect("image was none");
+    for plane in ALL_PLANES {
+        for y in 0..image.height(plane) as u32 {
+            assert_eq!(
+                image.row(plane, y).unwrap(),
+                reference.row(plane, y).unwrap()
+            );
+        }
+    }
+}
+
+#[test_case::test_case("alpha.avif")]
+#[test_case::test_case("colors-animated-8bpc.avif")]
+fn total_bytes_read(filename: &str) {
+    // The mdat box is the last box of these files.
+    let data = std::fs::read(get_test_file(filename)).expect("could not read file");
+    let mdat_payload = data
+        .windows(4)
+        .position(|x| x == b"mdat")
+        .expect("box not found")
+        + 4;
+    let mut decoder = get_decoder(filename);
+    assert_eq!(decoder.total_bytes_read(), 0);
+    assert!(decoder.parse().is_ok());
+    // All the boxes preceding the payload of mdat were read.
+    assert!(decoder.total_bytes_read() >= mdat_payload as u64);
+    if !HAS_DECODER {
+        return;
+    }
+    for _ in 0..decoder.image_count() {
+        assert!(decoder.next_image().is_ok());
+    }
+    let io_stats = decoder.io_stats();
+    assert!(
+        decoder.total_bytes_read()
+            >= (mdat_payload + io_stats.color_obu_size + io_stats.alpha_obu_size) as u64
+    );
+}
+
+#[test_case::test_case("sofa_grid1x5_420.avif", PixelFormat::Yuv420)]
+#[test_case::test_case("colors-animated-8bpc.avif", PixelFormat::Yuv420)]
+#[test_case::test_case("gainmap_oriented.avif", PixelFormat::Yuv420)]
+#[test_case::test_case("colors-animated-12bpc-keyframes-0-2-3.avif", PixelFormat::Yuv422)]
+fn chroma_is_not_upsampled(filename: &str, yuv_format: PixelFormat) {
+    let mut decoder = get_decoder(filename);
+    assert!(decoder.parse().is_ok());
+    assert_eq!(
+        decoder.image().expect("image was none").yuv_format,
+        yuv_format
+    );
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.yuv_format, yuv_format);
+    let chroma_width = image.width.div_ceil(2) as usize;
+    let chroma_height = if yuv_format == PixelFormat::Yuv420 {
+        image.height.div_ceil(2)
+    } else {
+        image.height
+    } as usize;
+    for plane in [Plane::U, Plane::V] {
+        assert_eq!(image.width(plane), chroma_width);
+        assert_eq!(image.height(plane), chroma_height);
+        // The planes hold exactly that many rows.
+        let has_row = |y: usize| {
+            if image.depth == 8 {
+                image.row(plane, y as u32).is_ok()
+            } else {
+                image.row16(plane, y as u32).is_ok()
+            }
+        };
+        assert!(has_row(chroma_height - 1));
+        assert!(!has_row(chroma_height));
+    }
+}
+
+#[test]
+fn item_property_associations() {
+    let mut decoder = get_decoder("white_1x1.avif");
+    assert_eq!(decoder.item_property_associations(1), None);
+    assert!(decoder.parse().is_ok());
+    // The ipco box of white_1x1.avif contains ispe, pixi, av1C and colr, in that order. They are
+    // all associated with the primary item 1, and only av1C is marked as essential.
+    assert_eq!(
+        decoder.item_property_associations(1),
+        Some(&[(1, false), (2, false), (3, true), (4, false)][..])
+    );
+    assert_eq!(decoder.item_property_associations(2), None);
+}
+
+#[test_case::test_case("white_1x1.avif", 1 ; "item")]
+#[test_case::test_case("colors-animated-8bpc.avif", 5 ; "track")]
+fn payload_integrity_intact(filename: &str, payload_count: usize) {
+    let mut decoder = get_decoder(filename);
+    assert!(decoder.parse().is_ok());
+    let report = decoder
+        .verify_payload_integrity()
+        .expect("verification failed");
+    assert_eq!(report.payloads.len(), payload_count);
+    assert!(report.is_intact());
+    for payload in &report.payloads {
+        assert!(payload.declared_size > 0);
+        assert_eq!(payload.read_size, payload.declared_size);
+    }
+}
+
+#[test]
+fn payload_integrity_truncated() {
+    // The mdat box is at the end of white_1x1.avif and the payload is 23 bytes long.
+    let mut data = std::fs::read(get_test_file("white_1x1.avif")).expect("could not read file");
+    data.truncate(data.len() - 10);
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = decoder::Strictness::None;
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    let report = decoder
+        .verify_payload_integrity()
+        .expect("verification failed");
+    assert!(!report.is_intact());
+    assert_eq!(
+        report.payloads,
+        vec![decoder::PayloadIntegrity {
+            category: decoder::Category::Color,
+            item_id: 1,
+            sample_index: 0,
+            declared_size: 23,
+            extents_size: 23,
+            read_size: 13,
+            short_read: true,
+            outside_mdat: false,
+        }]
+    );
+}
+
+#[test]
+fn payload_integrity_extent_larger_than_mdat() {
+    // Make the only extent of white_1x1.avif 32 bytes long while its mdat box only holds 23.
+    let mut data = std::fs::read(get_test_file("white_1x1.avif")).expect("could not read file");
+    let iloc = data
+        .windows(4)
+        .position(|x| x == b"iloc")
+        .expect("box not found");
+    // version 0, 4-byte offsets and lengths, no base offset, a single item with a single extent.
+    let extent_length = iloc + 22..iloc + 26;
+    assert_eq!(data[extent_length.clone()], [0, 0, 0, 23]);
+    data[extent_length].copy_from_slice(&[0, 0, 0, 32]);
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = decoder::Strictness::None;
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    let report = decoder
+        .verify_payload_integrity()
+        .expect("verification failed");
+    assert!(!report.is_intact());
+    assert_eq!(report.payloads.len(), 1);
+    assert_eq!(report.payloads[0].declared_size, 32);
+    assert_eq!(report.payloads[0].read_size, 23);
+    assert!(report.payloads[0].short_read);
+}
+
+#[test]
+fn payload_integrity_sample_larger_than_mdat() {
+    // The mdat box is at the end of colors-animated-8bpc.avif. Append a free box and make the last
+    // sample run 16 bytes into it, so that the sample can be read but is not within the mdat box.
+    let mut data =
+        std::fs::read(get_test_file("colors-animated-8bpc.avif")).expect("could not read file");
+    let stsz = data
+        .windows(4)
+        .position(|x| x == b"stsz")
+        .expect("box not found");
+    // version 0, sample_size 0, sample_count 5, then one 4-byte entry per sample.
+    let last_sample_size = stsz + 32..stsz + 36;
+    assert_eq!(data[last_sample_size.clone()], [0, 0, 0, 25]);
+    data[last_sample_size].copy_from_slice(&[0, 0, 0, 41]);
+    data.extend_from_slice(&[0, 0, 0, 32]);
+    data.extend_from_slice(b"free");
+    data.resize(data.len() + 24, 0);
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    let report = decoder
+        .verify_payload_integrity()
+        .expect("verification failed");
+    assert!(!report.is_intact());
+    assert_eq!(report.payloads.len(), 5);
+    for payload in &report.payloads[..4] {
+        assert!(payload.is_intact());
+    }
+    let last_payload = &report.payloads[4];
+    assert_eq!(last_payload.declared_size, 41);
+    assert_eq!(last_payload.read_size, 41);
+    assert!(!last_payload.short_read);
+    assert!(last_payload.outside_mdat);
+}
+
+#[test_case::test_case(decoder::Strictness::All, false)]
+#[test_case::test_case(decoder::Strictness::None, true)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::EssentialPropertiesKnown]), false)]
+#[test_case::test_case(decoder::Strictness::SpecificInclude(vec![decoder::StrictnessFlag::PixiRequired]), true)]
+#[test_case::test_case(decoder::Strictness::SpecificExclude(vec![decoder::StrictnessFlag::EssentialPropertiesKnown]), true)]
+fn unknown_essential_property(strictness: decoder::Strictness, expect_ok: bool) {
+    // Rename the colr property of white_1x1.avif to an unknown box type and mark its association
+    // as essential.
+    let mut data = std::fs::read(get_test_file("white_1x1.avif")).expect("could not read file");
+    let find_box = |data: &[u8], box_type: &[u8]| {
+        data.windows(4)
+            .position(|x| x == box_type)
+            .expect("box not found")
+    };
+    let colr = find_box(&data, b"colr");
+    data[colr..colr + 4].copy_from_slice(b"abcd");
+    // Single entry for item 1 with the 1-byte property indices 1, 2, 0x80|3 and 4 (colr).
+    let ipma = find_box(&data, b"ipma");
+    assert_eq!(data[ipma + 15..ipma + 19], [0x01, 0x02, 0x83, 0x04]);
+    data[ipma + 18] |= 0x80;
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.strictness = strictness;
+    decoder.set_io_vec(data);
+    let res = decoder.parse();
+    if !expect_ok {
+        assert!(matches!(res, Err(AvifError::NotImplemented)));
+        return;
+    }
+    assert!(res.is_ok());
+    assert_eq!(decoder.diagnostics().len(), 1);
+    assert!(decoder.diagnostics()[0].contains("item id 1"));
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.width, 1);
+    assert_eq!(image.height, 1);
+}
+
+#[cfg(feature = "image-crate")]
+#[test]
+fn image_crate_conversion() {
+    use ::image::GenericImageView;
+
+    let mut decoder = get_decoder("white_1x1.avif");
+    assert!(decoder.parse().is_ok());
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    let image = decoder.image().expect("image was none");
+    let dynamic_image = ::image::DynamicImage::try_from(image).expect("conversion failed");
+    assert_eq!(dynamic_image.dimensions(), (1, 1));
+    assert_eq!(dynamic_image.get_pixel(0, 0).0, [255, 255, 255, 255]);
+}
+
+#[test_case::test_case("alpha.avif" ; "item")]
+#[test_case::test_case("colors-animated-8bpc-alpha-exif-xmp.avif" ; "track")]
+fn alpha_only(filename: &str) {
+    let mut decoder = get_decoder(filename);
+    decoder.settings.image_content_to_decode = ImageContentType::AlphaOnly;
+    assert!(decoder.parse().is_ok());
+    assert!(decoder.image().expect("image was none").alpha_present);
+    if !HAS_DECODER {
+        return;
+    }
+    let mut reference = get_decoder(filename);
+    assert!(reference.parse().is_ok());
+    for _ in 0..decoder.image_count() {
+        assert!(decoder.next_image().is_ok());
+        assert!(reference.next_image().is_ok());
+        let image = decoder.image().expect("image was none");
+        let reference_image = reference.image().expect("image was none");
+        assert!(!image.has_plane(Plane::Y));
+        assert!(image.has_plane(Plane::A));
+        assert_eq!(decoder.decoded_row_count(), image.height);
+        for y in 0..image.height {
+            if image.depth == 8 {
+                assert_eq!(
+                    image.row(Plane::A, y).unwrap(),
+                    reference_image.row(Plane::A, y).unwrap()
+                );
+            } else {
+                assert_eq!(
+                    image.row16(Plane::A, y).unwrap(),
+                    reference_image.row16(Plane::A, y).unwrap()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn alpha_only_without_alpha() {
+    let mut decoder = get_decoder("white_1x1.avif");
+    decoder.settings.image_content_to_decode = ImageContentType::AlphaOnly;
+    assert!(decoder.parse().is_ok());
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(matches!(decoder.next_image(), Err(AvifError::NoContent)));
+}
+
+#[derive(Debug, Default)]
+struct CountingAllocator {
+    allocations: std::sync::atomic::AtomicUsize,
+    deallocations: std::sync::atomic::AtomicUsize,
+}
+
+// SAFETY: The buffers come from the global allocator.
+unsafe impl PlaneAllocator for CountingAllocator {
+    fn allocate(&self, layout: std::alloc::Layout) -> *mut u8 {
+        self.allocations
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        unsafe { std::alloc::alloc(layout) }
+    }
+
+    unsafe fn deallocate(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        self.deallocations
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        unsafe { std::alloc::dealloc(ptr, layout) };
+    }
+}
+
+#[test]
+fn plane_allocator() {
+    let allocator = std::sync::Arc::new(CountingAllocator::default());
+    // The color planes of this file are a grid, so they are composited into planes allocated by
+    // the decoder.
+    let mut decoder = get_decoder("color_grid_alpha_nogrid.avif");
+    decoder.settings.plane_allocator = Some(allocator.clone());
+    assert!(decoder.parse().is_ok());
+    if !HAS_DECODER {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    let allocations = allocator
+        .allocations
+        .load(std::sync::atomic::Ordering::SeqCst);
+    assert!(allocations > 0);
+    let image = decoder.image().expect("image was none");
+    assert!(image.has_plane(Plane::Y));
+    drop(decoder);
+    assert_eq!(
+        allocator
+            .deallocations
+            .load(std::sync::atomic::Ordering::SeqCst),
+        allocations
+    );
+}
+
+#[test_case::test_case("white_1x1.avif", false, &[(1, 1)]; "single image")]
+#[test_case::test_case("seine_sdr_gainmap_srgb.avif", false, &[(400, 300)]; "gainmap")]
+#[test_case::test_case("sofa_grid1x5_420.avif", false, &[(1024, 770)]; "grid")]
+#[test_case::test_case("sofa_grid1x5_420.avif", true, &[(1024, 154); 5]; "collection")]
+fn image_collection(filename: &str, ungrid: bool, expected_dimensions: &[(u32, u32)]) {
+    let mut data = std::fs::read(get_test_file(filename)).expect("could not read file");
+    if ungrid {
+        // Turn the grid into a collection of independent images by renaming the grid item type
+        // and its 'dimg' references.
+        for box_type in [b"grid", b"dimg"] {
+            let offset = data.windows(4).position(|x| x == box_type).unwrap();
+            data[offset..offset + 4].copy_from_slice(b"abcd");
+        }
+    }
+    let mut decoder = decoder::Decoder::default();
+    decoder.set_io_vec(data);
+    if ungrid {
+        // The primary item is not an image anymore.
+        assert!(decoder.parse().is_err());
+    }
+    let res = decoder.image_collection();
+    if !HAS_DECODER {
+        assert!(matches!(res, Err(AvifError::NoCodecAvailable)));
+        return;
     }
+    let images = res.expect("image_collection failed");
+    let dimensions: Vec<_> = images.iter().map(|x| (x.width, x.height)).collect();
+    assert_eq!(dimensions, expected_dimensions);
+    assert!(decoder.image().is_none());
 }
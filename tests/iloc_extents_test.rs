@@ -15,10 +15,13 @@
 #[path = "./mod.rs"]
 mod tests;
 
+#[cfg(feature = "conversion")]
 use crabby_avif::reformat::rgb::*;
+#[cfg(feature = "conversion")]
 use image::ImageReader;
 use tests::*;
 
+#[cfg(feature = "conversion")]
 #[test]
 fn iloc_extents() {
     let mut decoder = get_decoder("sacre_coeur_2extents.avif");
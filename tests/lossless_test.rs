@@ -12,10 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#![cfg(feature = "conversion")]
+
 #[path = "./mod.rs"]
 mod tests;
 
+use crabby_avif::decoder;
 use crabby_avif::reformat::rgb::*;
+use crabby_avif::MatrixCoefficients;
+use crabby_avif::PixelFormat;
 use image::ImageReader;
 use tests::*;
 
@@ -47,3 +52,56 @@ fn lossless(avif_file: &str, png_file: &str) {
             .unwrap()
     );
 }
+
+#[test_case::test_case(false; "default")]
+#[test_case::test_case(true; "assume_identity")]
+fn lossless_without_cicp(assume_identity: bool) {
+    // sacre_coeur_2extents.avif is a lossless 4:4:4 image with MatrixCoefficients::Identity.
+    // Remove the nclx property and set matrix_coefficients to Unspecified in the sequence header
+    // so that the file carries no information about the matrix coefficients.
+    let mut data =
+        std::fs::read(get_test_file("sacre_coeur_2extents.avif")).expect("could not read file");
+    assert_eq!(&data[244..248], b"colr");
+    data[247] = b'X';
+    // The sequence header OBU payload starts at offset 294. matrix_coefficients spans the last
+    // two bits of byte 300 and the first six bits of byte 301.
+    assert_eq!(&data[292..294], &[0x0a, 0x09]);
+    assert_eq!(data[300] & 0x03, 0);
+    assert_eq!(data[301] & 0xfc, 0);
+    data[301] |= 0x08;
+    let mut decoder = decoder::Decoder::default();
+    decoder.settings.assume_identity_when_444_lossless = assume_identity;
+    decoder.set_io_vec(data);
+    assert!(decoder.parse().is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.yuv_format, PixelFormat::Yuv444);
+    assert_eq!(
+        image.matrix_coefficients,
+        if assume_identity {
+            MatrixCoefficients::Identity
+        } else {
+            MatrixCoefficients::Unspecified
+        }
+    );
+    if !HAS_DECODER || !assume_identity {
+        return;
+    }
+    assert!(decoder.next_image().is_ok());
+    let decoded = decoder.image().expect("image was none");
+    let mut rgb = Image::create_from_yuv(decoded);
+    rgb.format = Format::Rgb;
+    assert!(rgb.allocate().is_ok());
+    assert!(rgb.convert_from_yuv(decoded).is_ok());
+
+    let source = ImageReader::open(get_test_file("sacre_coeur.png"));
+    let source = source.unwrap().decode().unwrap();
+
+    assert_eq!(
+        source.as_bytes(),
+        rgb.pixels
+            .as_ref()
+            .unwrap()
+            .slice(0, source.as_bytes().len() as u32)
+            .unwrap()
+    );
+}
@@ -28,6 +28,7 @@ pub fn get_test_file(filename: &str) -> String {
 }
 
 #[cfg(test)]
+#[allow(dead_code)]
 pub fn get_decoder(filename: &str) -> decoder::Decoder {
     let abs_filename = get_test_file(filename);
     let mut decoder = decoder::Decoder::default();
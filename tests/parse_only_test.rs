@@ -0,0 +1,126 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Parsing and the metadata getters must work without any codec or conversion feature, for
+// example when built with:
+//   cargo test --no-default-features --test parse_only_test
+
+#[path = "./mod.rs"]
+mod tests;
+
+use crabby_avif::decoder::*;
+use crabby_avif::*;
+use tests::*;
+
+struct ExpectedMetadata {
+    filename: &'static str,
+    width: u32,
+    height: u32,
+    yuv_format: PixelFormat,
+    alpha_present: bool,
+    image_count: u32,
+    timescale: u64,
+    gainmap_present: bool,
+    first_extent: (u64, usize),
+}
+
+const EXPECTED_METADATA: [ExpectedMetadata; 4] = [
+    ExpectedMetadata {
+        filename: "white_1x1.avif",
+        width: 1,
+        height: 1,
+        yuv_format: PixelFormat::Yuv444,
+        alpha_present: false,
+        image_count: 1,
+        timescale: 1,
+        gainmap_present: false,
+        first_extent: (282, 23),
+    },
+    ExpectedMetadata {
+        filename: "alpha.avif",
+        width: 80,
+        height: 80,
+        yuv_format: PixelFormat::Yuv444,
+        alpha_present: true,
+        image_count: 1,
+        timescale: 1,
+        gainmap_present: false,
+        first_extent: (437, 1542),
+    },
+    ExpectedMetadata {
+        filename: "colors-animated-8bpc.avif",
+        width: 150,
+        height: 150,
+        yuv_format: PixelFormat::Yuv420,
+        alpha_present: false,
+        image_count: 5,
+        timescale: 30,
+        gainmap_present: false,
+        first_extent: (1023, 39),
+    },
+    ExpectedMetadata {
+        filename: "seine_sdr_gainmap_srgb.avif",
+        width: 400,
+        height: 300,
+        yuv_format: PixelFormat::Yuv444,
+        alpha_present: false,
+        image_count: 1,
+        timescale: 1,
+        gainmap_present: true,
+        first_extent: (89668, 39963),
+    },
+];
+
+#[test_case::test_case(0)]
+#[test_case::test_case(1)]
+#[test_case::test_case(2)]
+#[test_case::test_case(3)]
+fn parse_only(index: usize) {
+    let expected = &EXPECTED_METADATA[index];
+    let mut decoder = get_decoder(expected.filename);
+    assert!(decoder.parse().is_ok());
+    let image = decoder.image().expect("image was none");
+    assert_eq!(image.width, expected.width);
+    assert_eq!(image.height, expected.height);
+    assert_eq!(image.depth, 8);
+    assert_eq!(image.yuv_format, expected.yuv_format);
+    assert_eq!(image.alpha_present, expected.alpha_present);
+    assert_eq!(image.image_sequence_track_present, expected.image_count > 1);
+    assert_eq!(decoder.image_count(), expected.image_count);
+    assert_eq!(decoder.timescale(), expected.timescale);
+    assert_eq!(decoder.gainmap_present(), expected.gainmap_present);
+    assert_eq!(
+        decoder.gainmap_metadata().is_some(),
+        expected.gainmap_present
+    );
+    assert_eq!(decoder.compression_format(), CompressionFormat::Avif);
+    for index in 0..expected.image_count {
+        let timing = decoder.nth_image_timing(index).expect("no timing");
+        assert_eq!(timing.timescale, expected.timescale);
+        assert_eq!(timing.pts_in_timescales, index as u64);
+    }
+    let extent = decoder.nth_image_max_extent(0).expect("no extent");
+    assert_eq!((extent.offset, extent.size), expected.first_extent);
+    assert!(decoder
+        .verify_payload_integrity()
+        .expect("verification failed")
+        .is_intact());
+
+    let res = decoder.next_image();
+    if HAS_DECODER {
+        assert!(res.is_ok());
+    } else {
+        assert!(matches!(res, Err(AvifError::NoCodecAvailable)));
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2024 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[path = "./mod.rs"]
+mod tests;
+
+use crabby_avif::validate::*;
+use tests::*;
+
+use test_case::test_case;
+
+fn read_test_file(filename: &str) -> Vec<u8> {
+    std::fs::read(get_test_file(filename)).expect("could not read file")
+}
+
+// Returns the offset of the payload of the first box of the given type.
+fn find_box_payload(data: &[u8], box_type: &[u8; 4]) -> usize {
+    data.windows(4)
+        .position(|x| x == box_type)
+        .expect("box not found")
+        + 4
+}
+
+#[test_case("white_1x1.avif")]
+#[test_case("paris_10bpc.avif")]
+#[test_case("colors-animated-8bpc.avif")]
+#[test_case("sofa_grid1x5_420.avif")]
+#[test_case("seine_sdr_gainmap_srgb.avif")]
+fn valid_files(filename: &str) {
+    assert_eq!(check(&read_test_file(filename)), vec![]);
+}
+
+#[test]
+fn unparsable_file() {
+    let violations = check(&read_test_file("clap_irot_imir_non_essential.avif"));
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].rule, Rule::Parse);
+    assert_eq!(violations[0].item_id, None);
+}
+
+#[test]
+fn missing_ispe() {
+    let violations = check(&read_test_file("alpha_noispe.avif"));
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].rule, Rule::MissingIspe);
+    assert_eq!(violations[0].item_id, Some(2));
+}
+
+#[test]
+fn missing_pixi() {
+    let violations = check(&read_test_file("overlay_yellow_bg.avif"));
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].rule, Rule::MissingPixi);
+    assert_eq!(violations[0].item_id, Some(4));
+}
+
+#[test]
+fn codec_config_mismatch() {
+    let mut data = read_test_file("white_1x1.avif");
+    // Change seq_level_idx_0 in the av1C box only.
+    let av1c = find_box_payload(&data, b"av1C");
+    data[av1c + 1] += 1;
+    let violations = check(&data);
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].rule, Rule::CodecConfig);
+    assert_eq!(violations[0].item_id, Some(1));
+    assert!(violations[0].message.contains("seq_level_idx_0"));
+}
+
+#[test]
+fn grid_tile_too_small() {
+    let mut data = read_test_file("sofa_grid1x5_420.avif");
+    // The first ispe box belongs to the grid item and the second one is shared by the 1024x154
+    // tiles. Make the tiles 1024x63.
+    let grid_ispe = find_box_payload(&data, b"ispe");
+    let tile_ispe = grid_ispe + find_box_payload(&data[grid_ispe..], b"ispe");
+    data[tile_ispe + 8..tile_ispe + 12].copy_from_slice(&63u32.to_be_bytes());
+    let violations = check(&data);
+    // Each of the 5 tiles is too small and has an odd height in 4:2:0.
+    assert_eq!(violations.len(), 10);
+    for violation in &violations {
+        assert_eq!(violation.rule, Rule::GridTileSize);
+        assert_ne!(violation.item_id, Some(1));
+    }
+}